@@ -0,0 +1,362 @@
+// Runs the editor's pipeline over many files from the command line. Pixels go through the CPU
+// renderer in state/reference.rs, which mirrors shader.wgsl, so results match the editor's exports.
+use image::GenericImageView;
+use image::imageops::FilterType;
+use parallel_image_editor_in_rust::state::adjustments::{AdjustmentKind, AdjustmentStack};
+use parallel_image_editor_in_rust::state::curves::CurveSet;
+use parallel_image_editor_in_rust::state::customlib::{CropRect, RenderSettings};
+use parallel_image_editor_in_rust::state::export::{
+    ChromaSubsampling, DEFAULT_NAME_TEMPLATE, ExportFormat, file_stem, fill_template,
+};
+use parallel_image_editor_in_rust::state::metadata::{ImageMetadata, decode_with_metadata};
+use parallel_image_editor_in_rust::state::reference::render_image;
+use parallel_image_editor_in_rust::state::transform::MAX_STRAIGHTEN;
+use parallel_image_editor_in_rust::utils::utils::clamp_crop;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const USAGE: &str = "usage: batch [options] --out <dir> <input>...
+
+Inputs are files or patterns with * and ? in the file name, like photos/*.jpg.
+
+Output:
+  -o, --out <dir>          directory to write to, created if missing
+  -f, --format <name>      png, jpeg, webp, tiff or bmp (default png)
+      --quality <1-100>    JPEG quality (default 90)
+      --subsampling <s>    JPEG chroma subsampling, 444, 422 or 420 (default 420)
+      --name <template>    file name template with {name}, {ext} and {index} (default {name}_edited.{ext})
+      --strip-metadata     leave EXIF, ICC and XMP out of the results
+  -j, --jobs <n>           files processed at once (default: one per core)
+
+Edits, applied in the editor's order:
+      --resize <w>x<h>     resize the source first
+      --resize-filter <f>  nearest, triangle, catmullrom, gaussian or lanczos3 (default lanczos3)
+      --rotate <degrees>   90, 180 or 270, clockwise
+      --flip-h, --flip-v   mirror left to right, top to bottom
+      --straighten <deg>   free rotation, -45 to 45
+      --auto-crop          zoom in so straightening leaves no transparent corners
+      --crop <x>,<y>,<w>,<h>  in pixels of the rotated image
+      --hue <degrees>      hue rotation
+      --saturation <f>     -1 removes all color, 0 keeps it
+      --value <f>          -1 is black, 0 keeps the brightness
+      --layers <file>      adjustment layers, as copied from the Layers panel
+      --curves <file>      curves, as copied from the Curves panel";
+
+struct Options {
+    inputs: Vec<PathBuf>,
+    out_dir: PathBuf,
+    format: ExportFormat,
+    name_template: String,
+    keep_metadata: bool,
+    jobs: usize,
+    resize: Option<((u32, u32), FilterType)>,
+    settings: RenderSettings,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(err) = std::fs::create_dir_all(&options.out_dir) {
+        eprintln!(
+            "error: could not create {}: {}",
+            options.out_dir.display(),
+            err
+        );
+        return ExitCode::FAILURE;
+    }
+
+    // workers take the next file until none are left
+    let next = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    let total = options.inputs.len();
+    std::thread::scope(|scope| {
+        for _ in 0..options.jobs.min(total) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(input) = options.inputs.get(index) else {
+                        break;
+                    };
+                    match process(&options, index, input) {
+                        Ok(output) => println!(
+                            "[{}/{}] {} -> {}",
+                            index + 1,
+                            total,
+                            input.display(),
+                            output.display()
+                        ),
+                        Err(err) => {
+                            failed.fetch_add(1, Ordering::Relaxed);
+                            eprintln!("[{}/{}] {}: {}", index + 1, total, input.display(), err);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let failed = failed.into_inner();
+    if failed > 0 {
+        eprintln!("{} of {} files failed", failed, total);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn process(options: &Options, index: usize, input: &Path) -> Result<PathBuf, String> {
+    let bytes = std::fs::read(input).map_err(|err| err.to_string())?;
+    let (mut image, metadata) = decode_with_metadata(&bytes)?;
+    if let Some(((width, height), filter)) = options.resize {
+        image = image.resize_exact(width, height, filter);
+    }
+
+    let mut settings = options.settings.clone();
+    // a crop reaching past this image is cut down to it, like the crop tool does
+    let frame = settings.transform.oriented_dimensions(image.dimensions());
+    settings.crop = settings.crop.map(|rect| clamp_crop(rect, frame));
+    if settings
+        .crop
+        .is_some_and(|rect| rect.width == 0 || rect.height == 0)
+    {
+        return Err(String::from("the crop lies outside the image"));
+    }
+
+    let rendered = render_image(&image, None, &settings);
+    let metadata = if options.keep_metadata {
+        metadata
+    } else {
+        ImageMetadata::default()
+    };
+    let encoded = options.format.encode(&rendered, &metadata)?;
+
+    let name = file_stem(&input.to_string_lossy());
+    let file_name = options.format.with_extension(&fill_template(
+        &options.name_template,
+        &name,
+        &options.format,
+        index,
+    ));
+    let output = options.out_dir.join(file_name);
+    std::fs::write(&output, encoded).map_err(|err| err.to_string())?;
+    Ok(output)
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut inputs = Vec::new();
+    let mut out_dir = None;
+    let mut format_name = String::from("png");
+    let mut quality = None;
+    let mut subsampling = None;
+    let mut name_template = String::from(DEFAULT_NAME_TEMPLATE);
+    let mut keep_metadata = true;
+    let mut jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut resize = None;
+    let mut resize_filter = FilterType::Lanczos3;
+    let mut hsv = None;
+    let mut layers = AdjustmentStack::default();
+    let mut settings = RenderSettings::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", flag))
+        };
+        match arg.as_str() {
+            "-o" | "--out" => out_dir = Some(PathBuf::from(value(arg)?)),
+            "-f" | "--format" => format_name = value(arg)?,
+            "--quality" => quality = Some(parse_number::<u8>(arg, &value(arg)?)?),
+            "--subsampling" => {
+                let text = value(arg)?;
+                subsampling = Some(ChromaSubsampling::parse(&text).ok_or_else(|| {
+                    format!("--subsampling takes 444, 422 or 420, got '{}'", text)
+                })?);
+            }
+            "--name" => name_template = value(arg)?,
+            "--strip-metadata" => keep_metadata = false,
+            "-j" | "--jobs" => jobs = parse_number::<usize>(arg, &value(arg)?)?.max(1),
+            "--resize" => {
+                let text = value(arg)?;
+                let (width, height) = text
+                    .split_once('x')
+                    .ok_or_else(|| format!("--resize expects <w>x<h>, got '{}'", text))?;
+                resize = Some((
+                    parse_number::<u32>(arg, width)?,
+                    parse_number::<u32>(arg, height)?,
+                ));
+            }
+            "--resize-filter" => {
+                resize_filter = match value(arg)?.as_str() {
+                    "nearest" => FilterType::Nearest,
+                    "triangle" => FilterType::Triangle,
+                    "catmullrom" => FilterType::CatmullRom,
+                    "gaussian" => FilterType::Gaussian,
+                    "lanczos3" => FilterType::Lanczos3,
+                    other => return Err(format!("unknown resize filter '{}'", other)),
+                }
+            }
+            "--rotate" => match parse_number::<u32>(arg, &value(arg)?)? {
+                degrees @ (0 | 90 | 180 | 270) => {
+                    for _ in 0..degrees / 90 {
+                        settings.transform.rotate_clockwise();
+                    }
+                }
+                degrees => return Err(format!("--rotate takes 90, 180 or 270, got {}", degrees)),
+            },
+            "--flip-h" => settings.transform.flip_horizontal(),
+            "--flip-v" => settings.transform.flip_vertical(),
+            "--straighten" => {
+                let degrees = parse_number::<f32>(arg, &value(arg)?)?;
+                settings.transform.straighten = degrees.clamp(-MAX_STRAIGHTEN, MAX_STRAIGHTEN);
+            }
+            "--auto-crop" => settings.transform.auto_crop = true,
+            "--crop" => {
+                let text = value(arg)?;
+                let parts = text
+                    .split(',')
+                    .map(|part| parse_number::<u32>(arg, part.trim()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let [x, y, width, height] = parts[..] else {
+                    return Err(format!("--crop expects <x>,<y>,<w>,<h>, got '{}'", text));
+                };
+                settings.crop = Some(CropRect {
+                    x,
+                    y,
+                    width,
+                    height,
+                });
+            }
+            "--hue" | "--saturation" | "--value" => {
+                let number = parse_number::<f32>(arg, &value(arg)?)?;
+                let [hue, saturation, brightness] = hsv.get_or_insert([0.0; 3]);
+                match arg.as_str() {
+                    "--hue" => *hue = number.to_radians(),
+                    "--saturation" => *saturation = number,
+                    _ => *brightness = number,
+                }
+            }
+            "--layers" => layers = AdjustmentStack::deserialize(&read_preset(&value(arg)?)?)?,
+            "--curves" => settings.curves = CurveSet::deserialize(&read_preset(&value(arg)?)?)?,
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("unknown option '{}'", flag));
+            }
+            pattern => inputs.extend(expand_input(pattern)?),
+        }
+    }
+
+    // the HSV options stand in for the HSV panel, which edits the topmost HSV layer
+    if let Some([hue, saturation, value]) = hsv {
+        let layer = layers
+            .find_or_push("hsv")
+            .ok_or_else(|| String::from("no room for an HSV layer"))?;
+        layer.kind = AdjustmentKind::Hsv {
+            hue,
+            saturation,
+            value,
+        };
+    }
+    settings.adjustments = layers;
+
+    let mut format = ExportFormat::default_for(&format_name)
+        .ok_or_else(|| format!("unknown format '{}'", format_name))?;
+    if let ExportFormat::Jpeg {
+        quality: q,
+        subsampling: s,
+    } = &mut format
+    {
+        if let Some(quality) = quality {
+            *q = quality.clamp(1, 100);
+        }
+        if let Some(subsampling) = subsampling {
+            *s = subsampling;
+        }
+    }
+    if inputs.is_empty() {
+        return Err(String::from("no input files"));
+    }
+    Ok(Options {
+        inputs,
+        out_dir: out_dir.ok_or_else(|| String::from("--out is required"))?,
+        format,
+        name_template,
+        keep_metadata,
+        jobs,
+        resize: resize.map(|size| (size, resize_filter)),
+        settings,
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, text: &str) -> Result<T, String> {
+    text.parse::<T>()
+        .map_err(|_| format!("{}: invalid number '{}'", flag, text))
+}
+
+fn read_preset(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path, err))
+}
+
+// a path, or a pattern with * and ? in its file name, expanded to the matching files in name order
+fn expand_input(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let path = Path::new(pattern);
+    let file_pattern = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if !file_pattern.contains(['*', '?']) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let entries = std::fs::read_dir(dir)
+        .map_err(|err| format!("could not list {}: {}", dir.display(), err))?;
+    let mut matches: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+        .filter(|entry| wildcard_match(&file_pattern, &entry.file_name().to_string_lossy()))
+        .map(|entry| dir.join(entry.file_name()))
+        .collect();
+    if matches.is_empty() {
+        return Err(format!("no files match '{}'", pattern));
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+// `*` matches any run of characters, `?` any one character
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // where the last star was, and how much of the name it has taken so far
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,93 @@
+use crate::components::draggable_panel::DraggablePanel;
+use crate::state::app_state::{BatchItem, BatchState, BatchStatus, GalleryState, ImageVec};
+use dioxus::prelude::*;
+
+fn status_text(status: &BatchStatus) -> String {
+    match status {
+        BatchStatus::Queued => String::from("Queued"),
+        BatchStatus::Rendering => String::from("Rendering..."),
+        BatchStatus::Saved(name) => format!("Saved {}", name),
+        BatchStatus::Failed(reason) => format!("Failed: {}", reason),
+    }
+}
+
+#[component]
+pub fn BatchPanel() -> Element {
+    let batch = use_context::<BatchState>();
+    let mut items = batch.items;
+    let mut run_signal = batch.run_signal;
+    let selection = use_context::<GalleryState>().selection;
+    let image_count = use_context::<ImageVec>().vector.read().len();
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+    let names = use_context::<ImageVec>().names;
+    let image_name = move |index: usize| {
+        names.read().get(index).cloned().unwrap_or_else(|| format!("Image {}", index + 1))
+    };
+
+    // the gallery selection if there is one, every loaded image otherwise
+    let targets = move || -> Vec<usize> {
+        let mut indices: Vec<usize> = selection
+            .read()
+            .iter()
+            .copied()
+            .filter(|&i| i < image_count)
+            .collect();
+        if indices.is_empty() {
+            indices = (0..image_count).collect();
+        }
+        indices.sort();
+        indices
+    };
+
+    let running = batch.running();
+    let saved = items.read().iter().filter(|item| matches!(item.status, BatchStatus::Saved(_))).count();
+    let failures: Vec<BatchItem> = items
+        .read()
+        .iter()
+        .filter(|item| matches!(item.status, BatchStatus::Failed(_)))
+        .cloned()
+        .collect();
+    let finished = saved + failures.len();
+
+    rsx! {
+        DraggablePanel {
+            title: String::from("Batch apply"),
+            PanelContent:
+                rsx! {
+                    p { class: "panel-hint",
+                        "Applies the filters, adjustment layers, levels and curves of {image_name(curr_index())} to {targets().len()} image(s). Pick images in the gallery to limit the batch. Files are named with the Save as template and format."
+                    }
+                    div { class: "panel-button-row",
+                        button { class: "btn",
+                            disabled: running || image_count == 0,
+                            onclick: move |_| {
+                                items.set(
+                                    targets()
+                                        .into_iter()
+                                        .map(|index| BatchItem { index, status: BatchStatus::Queued })
+                                        .collect(),
+                                );
+                                run_signal.set(true);
+                            },
+                            "Run batch"
+                        }
+                    }
+                    if !items.read().is_empty() {
+                        p { class: "slider-progress", "{finished} / {items.read().len()} done, {saved} saved, {failures.len()} failed" }
+                        for item in items.read().iter().cloned() {
+                            div { class: "layer-row", key: "{item.index}",
+                                p { class: "layer-name", "{image_name(item.index)}" }
+                                p { class: "slider-progress", "{status_text(&item.status)}" }
+                            }
+                        }
+                    }
+                    if !running && !failures.is_empty() {
+                        p { "Failures" }
+                        for item in failures {
+                            p { class: "panel-hint", "{image_name(item.index)}: {status_text(&item.status)}" }
+                        }
+                    }
+                }
+        }
+    }
+}
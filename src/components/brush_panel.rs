@@ -0,0 +1,137 @@
+use crate::components::draggable_panel::DraggablePanel;
+use crate::state::app_state::BrushState;
+use dioxus::prelude::*;
+
+fn color_to_hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+fn hex_to_color(hex: &str) -> Option<[u8; 3]> {
+    let hex = hex.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[component]
+pub fn BrushPanel() -> Element {
+    let mut settings = use_context::<BrushState>().settings;
+    let brush = settings();
+    let color_hex = color_to_hex(brush.color);
+
+    rsx! {
+        DraggablePanel {
+            title: String::from("Brush"),
+            PanelContent:
+                rsx! {
+                    div { class: "panel-button-row",
+                        button { class: if !brush.eraser { "btn on" } else { "btn" },
+                            onclick: move |_| settings.write().eraser = false,
+                            "Paint"
+                        }
+                        button { class: if brush.eraser { "btn on" } else { "btn" },
+                            onclick: move |_| settings.write().eraser = true,
+                            "Eraser"
+                        }
+                        input {
+                            class: "panel-color-input",
+                            type: "color",
+                            value: "{color_hex}",
+                            oninput: move |e| {
+                                if let Some(parsed) = hex_to_color(&e.value()) {
+                                    settings.write().color = parsed;
+                                }
+                            },
+                        }
+                    }
+                    div { class: "panel-slider-container",
+                        p { "SIZE" },
+                        input {
+                            class: "panel-slider",
+                            type: "range",
+                            min: 1.0,
+                            value: "{brush.size}",
+                            max: 500.0,
+                            step: 1.0,
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<f32>() {
+                                    settings.write().size = parsed;
+                                }
+                            },
+                        }
+                        p { class: "slider-progress", "{brush.size:.0}px" }
+                    },
+                    div { class: "panel-slider-container",
+                        p { "HARDNESS" },
+                        input {
+                            class: "panel-slider",
+                            type: "range",
+                            min: 0.0,
+                            value: "{brush.hardness}",
+                            max: 1.0,
+                            step: 0.01,
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<f32>() {
+                                    settings.write().hardness = parsed;
+                                }
+                            },
+                        }
+                        p { class: "slider-progress", "{brush.hardness * 100.0:.0}%" }
+                    },
+                    div { class: "panel-slider-container",
+                        p { "OPACITY" },
+                        input {
+                            class: "panel-slider",
+                            type: "range",
+                            min: 0.0,
+                            value: "{brush.opacity}",
+                            max: 1.0,
+                            step: 0.01,
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<f32>() {
+                                    settings.write().opacity = parsed;
+                                }
+                            },
+                        }
+                        p { class: "slider-progress", "{brush.opacity * 100.0:.0}%" }
+                    },
+                    div { class: "panel-slider-container",
+                        p { "FLOW" },
+                        input {
+                            class: "panel-slider",
+                            type: "range",
+                            min: 0.01,
+                            value: "{brush.flow}",
+                            max: 1.0,
+                            step: 0.01,
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<f32>() {
+                                    settings.write().flow = parsed;
+                                }
+                            },
+                        }
+                        p { class: "slider-progress", "{brush.flow * 100.0:.0}%" }
+                    },
+                    div { class: "panel-slider-container",
+                        p { "SPACING" },
+                        input {
+                            class: "panel-slider",
+                            type: "range",
+                            min: 0.01,
+                            value: "{brush.spacing}",
+                            max: 2.0,
+                            step: 0.01,
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<f32>() {
+                                    settings.write().spacing = parsed;
+                                }
+                            },
+                        }
+                        p { class: "slider-progress", "{brush.spacing * 100.0:.0}%" }
+                    }
+                }
+        }
+    }
+}
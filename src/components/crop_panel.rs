@@ -0,0 +1,248 @@
+use crate::components::draggable_panel::DraggablePanel;
+use crate::state::app_state::{AspectRatio, BoardState, CropState, HistoryState, ImageVec, TransformState};
+use crate::state::customlib::CropRect;
+use crate::state::history::EditCommand;
+use crate::utils::utils::{clamp_crop, crop_from_corners, fit_crop_to_aspect};
+use dioxus::prelude::*;
+use image::GenericImageView;
+use crate::utils::platform::use_window_pointer;
+use dioxus::html::geometry::PixelsRect;
+
+#[derive(Clone, Copy, PartialEq)]
+enum CropDrag {
+    Move,
+    TopLeft,
+    TopRight,
+    BottomRight,
+    BottomLeft,
+}
+
+// converts a client position to pixels of the rotated frame using where the board is shown
+fn client_to_image(client: (f64, f64), bounds: (u32, u32), board: Option<PixelsRect>) -> (f64, f64) {
+    let Some(rect) = board else {
+        return client;
+    };
+    (
+        (client.0 - rect.min_x()) / rect.width() * bounds.0 as f64,
+        (client.1 - rect.min_y()) / rect.height() * bounds.1 as f64,
+    )
+}
+
+// size of the frame crops are taken in, the source turned by its quarter turns
+fn use_image_bounds() -> impl Fn() -> (u32, u32) + Copy {
+    let image_vec = use_context::<ImageVec>().vector;
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+    let transforms = use_context::<TransformState>();
+    move || {
+        let source = image_vec
+            .read()
+            .get(curr_index())
+            .map(|img| img.dimensions())
+            .unwrap_or((1, 1));
+        transforms.transform(curr_index()).oriented_dimensions(source)
+    }
+}
+
+#[component]
+pub fn CropOverlay() -> Element {
+    let mut rect = use_context::<CropState>().rect;
+    let aspect = use_context::<CropState>().aspect;
+    let bounds = use_image_bounds();
+    let mut drag_mode: Signal<Option<CropDrag>> = use_signal(|| None);
+    let mut drag_start = use_signal(|| (0.0, 0.0));
+    let mut rect_start = use_signal(|| CropRect::full((0, 0)));
+
+    let board = use_context::<BoardState>().rect;
+
+    let crop_handle = move |client_x: f64, client_y: f64| {
+        if let Some(mode) = *drag_mode.read() {
+            let dims = bounds();
+            let (px, py) = client_to_image((client_x, client_y), dims, board());
+            let start = rect_start();
+            let (left, top) = (start.x as f64, start.y as f64);
+            let (right, bottom) = (left + start.width as f64, top + start.height as f64);
+
+            let new_rect = match mode {
+                CropDrag::Move => {
+                    let (sx, sy) = drag_start();
+                    let x = (left + px - sx).max(0.0).round() as u32;
+                    let y = (top + py - sy).max(0.0).round() as u32;
+                    clamp_crop(CropRect { x, y, ..start }, dims)
+                }
+                CropDrag::TopLeft => crop_from_corners((right, bottom), (px, py), aspect().ratio(), dims),
+                CropDrag::TopRight => crop_from_corners((left, bottom), (px, py), aspect().ratio(), dims),
+                CropDrag::BottomRight => crop_from_corners((left, top), (px, py), aspect().ratio(), dims),
+                CropDrag::BottomLeft => crop_from_corners((right, top), (px, py), aspect().ratio(), dims),
+            };
+            rect.set(new_rect);
+        }
+    };
+
+    use_window_pointer(crop_handle, move || drag_mode.set(None));
+
+    let mut start_drag = move |mode: CropDrag, evt: Event<MouseData>| {
+        evt.stop_propagation();
+        let client = evt.client_coordinates();
+        drag_mode.set(Some(mode));
+        drag_start.set(client_to_image((client.x, client.y), bounds(), board()));
+        rect_start.set(rect());
+    };
+
+    let (width, height) = bounds();
+    let current = rect();
+    let rect_style = format!(
+        "left: {}%; top: {}%; width: {}%; height: {}%;",
+        current.x as f64 / width as f64 * 100.0,
+        current.y as f64 / height as f64 * 100.0,
+        current.width as f64 / width as f64 * 100.0,
+        current.height as f64 / height as f64 * 100.0,
+    );
+
+    rsx! {
+        div { class: "crop-overlay",
+            div { class: "crop-rect",
+                style: rect_style,
+                onmousedown: move |evt| start_drag(CropDrag::Move, evt),
+                div { class: "crop-handle top-left", onmousedown: move |evt| start_drag(CropDrag::TopLeft, evt) }
+                div { class: "crop-handle top-right", onmousedown: move |evt| start_drag(CropDrag::TopRight, evt) }
+                div { class: "crop-handle bottom-right", onmousedown: move |evt| start_drag(CropDrag::BottomRight, evt) }
+                div { class: "crop-handle bottom-left", onmousedown: move |evt| start_drag(CropDrag::BottomLeft, evt) }
+            }
+        }
+    }
+}
+
+#[component]
+pub fn CropPanel() -> Element {
+    let mut panel_visible = use_context::<CropState>().panel_visible;
+    let mut rect = use_context::<CropState>().rect;
+    let mut aspect = use_context::<CropState>().aspect;
+    let mut crops = use_context::<CropState>().crops;
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+    let bounds = use_image_bounds();
+    let history = use_context::<HistoryState>();
+    let mut custom_w = use_signal(|| 3u32);
+    let mut custom_h = use_signal(|| 2u32);
+
+    // re-opening the tool starts from the previously applied crop
+    use_hook(move || {
+        let previous = crops.read().get(&curr_index()).copied();
+        rect.set(previous.unwrap_or(CropRect::full(bounds())));
+    });
+
+    let mut set_aspect = move |new_aspect: AspectRatio| {
+        aspect.set(new_aspect);
+        rect.set(fit_crop_to_aspect(rect(), new_aspect.ratio(), bounds()));
+    };
+
+    let aspect_class = move |a: AspectRatio| if aspect() == a { "btn on" } else { "btn" };
+    let is_custom = matches!(aspect(), AspectRatio::Custom(_, _));
+    let current = rect();
+
+    rsx! {
+        DraggablePanel {
+            title: String::from("Crop"),
+            PanelContent:
+                rsx! {
+                    div { class: "panel-button-row",
+                        button { class: aspect_class(AspectRatio::Free), onclick: move |_| set_aspect(AspectRatio::Free), "Free" }
+                        button { class: aspect_class(AspectRatio::Square), onclick: move |_| set_aspect(AspectRatio::Square), "1:1" }
+                        button { class: aspect_class(AspectRatio::FourThree), onclick: move |_| set_aspect(AspectRatio::FourThree), "4:3" }
+                        button { class: aspect_class(AspectRatio::SixteenNine), onclick: move |_| set_aspect(AspectRatio::SixteenNine), "16:9" }
+                        button { class: if is_custom { "btn on" } else { "btn" },
+                            onclick: move |_| set_aspect(AspectRatio::Custom(custom_w(), custom_h())),
+                            "Custom"
+                        }
+                    }
+                    if is_custom {
+                        div { class: "panel-slider-container",
+                            p { "RATIO" },
+                            input { class: "panel-number-input", type: "number", min: 1, value: "{custom_w}",
+                                oninput: move |e| {
+                                    if let Ok(parsed) = e.value().parse::<u32>() {
+                                        custom_w.set(parsed);
+                                        set_aspect(AspectRatio::Custom(parsed, custom_h()));
+                                    }
+                                }
+                            }
+                            p { ":" }
+                            input { class: "panel-number-input", type: "number", min: 1, value: "{custom_h}",
+                                oninput: move |e| {
+                                    if let Ok(parsed) = e.value().parse::<u32>() {
+                                        custom_h.set(parsed);
+                                        set_aspect(AspectRatio::Custom(custom_w(), parsed));
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    div { class: "panel-slider-container",
+                        p { "X" },
+                        input { class: "panel-number-input", type: "number", min: 0, value: "{current.x}",
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<u32>() {
+                                    rect.set(clamp_crop(CropRect { x: parsed, ..rect() }, bounds()));
+                                }
+                            }
+                        }
+                        p { "Y" },
+                        input { class: "panel-number-input", type: "number", min: 0, value: "{current.y}",
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<u32>() {
+                                    rect.set(clamp_crop(CropRect { y: parsed, ..rect() }, bounds()));
+                                }
+                            }
+                        }
+                    }
+                    div { class: "panel-slider-container",
+                        p { "W" },
+                        input { class: "panel-number-input", type: "number", min: 1, value: "{current.width}",
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<u32>() {
+                                    let height = match aspect().ratio() {
+                                        Some(r) => (parsed as f64 / r).round() as u32,
+                                        None => rect().height,
+                                    };
+                                    // clamping the sides one by one breaks a locked ratio, fit it again
+                                    rect.set(fit_crop_to_aspect(clamp_crop(CropRect { width: parsed, height, ..rect() }, bounds()), aspect().ratio(), bounds()));
+                                }
+                            }
+                        }
+                        p { "H" },
+                        input { class: "panel-number-input", type: "number", min: 1, value: "{current.height}",
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<u32>() {
+                                    let width = match aspect().ratio() {
+                                        Some(r) => (parsed as f64 * r).round() as u32,
+                                        None => rect().width,
+                                    };
+                                    rect.set(fit_crop_to_aspect(clamp_crop(CropRect { width, height: parsed, ..rect() }, bounds()), aspect().ratio(), bounds()));
+                                }
+                            }
+                        }
+                    }
+                    div { class: "panel-button-row",
+                        button { class: "btn",
+                            onclick: move |_| {
+                                aspect.set(AspectRatio::Free);
+                                rect.set(CropRect::full(bounds()));
+                                let before = crops.write().remove(&curr_index());
+                                if before.is_some() {
+                                    history.record(curr_index(), EditCommand::Crop { before, after: None }, None);
+                                }
+                            },
+                            "Reset"
+                        }
+                        button { class: "btn",
+                            onclick: move |_| {
+                                let before = crops.write().insert(curr_index(), rect());
+                                history.record(curr_index(), EditCommand::Crop { before, after: Some(rect()) }, None);
+                                panel_visible.set(false);
+                            },
+                            "Apply"
+                        }
+                    }
+                }
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use crate::components::draggable_panel::DraggablePanel;
+use crate::state::app_state::{CurvesState, HistoryState, ImageVec};
+use crate::state::curves::{Curve, CurveChannel, CurveSet, PRESETS_SETTING, serialize_presets};
+use crate::utils::platform::{log, store_setting};
+use dioxus::prelude::*;
+use dioxus::html::geometry::PixelsRect;
+use std::rc::Rc;
+
+// side of the square editor in svg units
+const EDITOR_SIZE: f32 = 256.0;
+// how close (in curve units) a click has to be to grab an existing point
+const HIT_RADIUS: f32 = 0.04;
+const PATH_SAMPLES: usize = 64;
+
+// converts a client position to curve coordinates, with y pointing up
+fn client_to_curve(client: (f64, f64), editor: Option<PixelsRect>) -> Option<(f32, f32)> {
+    let rect = editor?;
+    let x = (client.0 - rect.min_x()) / rect.width();
+    let y = 1.0 - (client.1 - rect.min_y()) / rect.height();
+    Some((x.clamp(0.0, 1.0) as f32, y.clamp(0.0, 1.0) as f32))
+}
+
+fn curve_path(curve: &Curve) -> String {
+    (0..=PATH_SAMPLES)
+        .map(|i| {
+            let x = i as f32 / PATH_SAMPLES as f32;
+            let y = curve.evaluate(x);
+            let command = if i == 0 { "M" } else { "L" };
+            format!("{} {:.1} {:.1}", command, x * EDITOR_SIZE, (1.0 - y) * EDITOR_SIZE)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[component]
+pub fn CurvesPanel() -> Element {
+    let curves_state = use_context::<CurvesState>();
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+    let history = use_context::<HistoryState>();
+    let mut presets = curves_state.presets;
+    let mut channel = use_signal(|| CurveChannel::Master);
+    let mut dragging = use_signal(|| None::<usize>);
+    let mut preset_name = use_signal(|| String::new());
+    let mut preset_text = use_signal(|| String::new());
+    let mut preset_error = use_signal(|| None::<String>);
+    let mut editor = use_signal(|| None::<Rc<MountedData>>);
+    // where the editor was on screen when the last drag started, the panel can move in between
+    let mut editor_rect = use_signal(|| None::<PixelsRect>);
+
+    let set = curves_state.curves(curr_index());
+    let active = set.curve(channel()).clone();
+
+    let edit = move |coalesce: Option<&'static str>, f: Box<dyn FnOnce(&mut CurveSet)>| {
+        curves_state.edit(curr_index(), history, coalesce, f);
+    };
+
+    // grabs the point under the cursor, or adds a new one there
+    let start_drag = move |evt: Event<MouseData>| {
+        let client = evt.client_coordinates();
+        spawn(async move {
+            if let Some(mounted) = editor() {
+                editor_rect.set(mounted.get_client_rect().await.ok());
+            }
+            let Some((x, y)) = client_to_curve((client.x, client.y), editor_rect()) else {
+                return;
+            };
+            let hit = curves_state
+                .curves(curr_index())
+                .curve(channel())
+                .hit_test(x, y, HIT_RADIUS);
+            let grabbed = match hit {
+                Some(index) => index,
+                None => {
+                    let mut inserted = 0;
+                    curves_state.edit(curr_index(), history, Some("curve-point"), |set| {
+                        inserted = set.curve_mut(channel()).insert(x, y);
+                    });
+                    inserted
+                }
+            };
+            dragging.set(Some(grabbed));
+        });
+    };
+
+    let mut end_drag = move || {
+        if dragging().is_some() {
+            dragging.set(None);
+            history.seal(curr_index());
+        }
+    };
+
+    let channel_class = move |c: CurveChannel| if channel() == c { "btn on" } else { "btn" };
+
+    rsx! {
+        DraggablePanel {
+            title: String::from("Curves"),
+            PanelContent:
+                rsx! {
+                    div { class: "panel-button-row",
+                        for c in CurveChannel::ALL {
+                            button { class: channel_class(c), onclick: move |_| channel.set(c), "{c.label()}" }
+                        }
+                    }
+                    svg {
+                        id: "curves-editor",
+                        class: "curves-editor",
+                        view_box: "0 0 {EDITOR_SIZE} {EDITOR_SIZE}",
+                        onmounted: move |evt| editor.set(Some(evt.data())),
+                        onmousedown: start_drag,
+                        onmousemove: move |evt| {
+                            if let Some(index) = dragging() {
+                                let client = evt.client_coordinates();
+                                if let Some((x, y)) = client_to_curve((client.x, client.y), editor_rect()) {
+                                    edit(Some("curve-point"), Box::new(move |set| {
+                                        set.curve_mut(channel()).move_point(index, x, y);
+                                    }));
+                                }
+                            }
+                        },
+                        onmouseup: move |_| end_drag(),
+                        onmouseleave: move |_| end_drag(),
+                        // double click removes a point, the end points stay
+                        ondoubleclick: move |evt| {
+                            let client = evt.client_coordinates();
+                            if let Some((x, y)) = client_to_curve((client.x, client.y), editor_rect())
+                                && let Some(index) = curves_state.curves(curr_index()).curve(channel()).hit_test(x, y, HIT_RADIUS)
+                            {
+                                edit(None, Box::new(move |set| set.curve_mut(channel()).remove(index)));
+                            }
+                        },
+                        for i in 1..4 {
+                            line { class: "curves-grid",
+                                x1: "{EDITOR_SIZE * i as f32 / 4.0}", y1: "0",
+                                x2: "{EDITOR_SIZE * i as f32 / 4.0}", y2: "{EDITOR_SIZE}",
+                            }
+                            line { class: "curves-grid",
+                                x1: "0", y1: "{EDITOR_SIZE * i as f32 / 4.0}",
+                                x2: "{EDITOR_SIZE}", y2: "{EDITOR_SIZE * i as f32 / 4.0}",
+                            }
+                        }
+                        line { class: "curves-grid", x1: "0", y1: "{EDITOR_SIZE}", x2: "{EDITOR_SIZE}", y2: "0" }
+                        for c in CurveChannel::ALL {
+                            if c != channel() && !set.curve(c).is_identity() {
+                                path { class: "curves-line faint {c.name()}", d: curve_path(set.curve(c)) }
+                            }
+                        }
+                        path { class: "curves-line {channel().name()}", d: curve_path(&active) }
+                        for (x, y) in active.points.iter().copied() {
+                            circle { class: "curves-point",
+                                cx: "{x * EDITOR_SIZE}",
+                                cy: "{(1.0 - y) * EDITOR_SIZE}",
+                                r: "5",
+                            }
+                        }
+                    }
+                    p { class: "panel-hint", "Click to add a point, drag to move it, double click to remove it" }
+                    div { class: "panel-button-row",
+                        button { class: "btn",
+                            onclick: move |_| edit(None, Box::new(move |set| *set.curve_mut(channel()) = Curve::default())),
+                            "Reset {channel().label()}"
+                        }
+                        button { class: "btn",
+                            onclick: move |_| edit(None, Box::new(|set| *set = CurveSet::default())),
+                            "Reset all"
+                        }
+                    }
+                    div { class: "panel-slider-container",
+                        p { "PRESET" },
+                        select { class: "panel-select",
+                            onchange: move |e| {
+                                let preset = presets
+                                    .read()
+                                    .iter()
+                                    .find(|(name, _)| *name == e.value())
+                                    .map(|(_, curves)| curves.clone());
+                                if let Some(preset) = preset {
+                                    edit(None, Box::new(move |set| *set = preset));
+                                }
+                            },
+                            option { value: "", selected: true, disabled: true, "Choose..." }
+                            for (name, _) in presets.read().iter() {
+                                option { value: "{name}", "{name}" }
+                            }
+                        }
+                    }
+                    div { class: "panel-slider-container",
+                        input { class: "panel-text-input", type: "text", placeholder: "Preset name",
+                            value: "{preset_name}",
+                            oninput: move |e| preset_name.set(e.value()),
+                        }
+                        button { class: "btn",
+                            onclick: move |_| {
+                                let name = preset_name().trim().to_string();
+                                if name.is_empty() {
+                                    return;
+                                }
+                                let current = curves_state.curves(curr_index());
+                                let mut presets = presets.write();
+                                match presets.iter_mut().find(|(existing, _)| *existing == name) {
+                                    Some((_, curves)) => *curves = current,
+                                    None => presets.push((name, current)),
+                                }
+                                if let Err(err) = store_setting(PRESETS_SETTING, &serialize_presets(&presets)) {
+                                    log(format!("Saving curve presets failed: {}", err));
+                                }
+                                preset_name.set(String::new());
+                            },
+                            "Save preset"
+                        }
+                    }
+                    div { class: "panel-button-row",
+                        button { class: "btn",
+                            onclick: move |_| {
+                                preset_text.set(curves_state.curves(curr_index()).serialize());
+                                preset_error.set(None);
+                            },
+                            "Export"
+                        }
+                        button { class: "btn",
+                            onclick: move |_| {
+                                match CurveSet::deserialize(&preset_text()) {
+                                    Ok(loaded) => {
+                                        edit(None, Box::new(move |set| *set = loaded));
+                                        preset_error.set(None);
+                                    }
+                                    Err(err) => preset_error.set(Some(err)),
+                                }
+                            },
+                            "Import"
+                        }
+                    }
+                    textarea { class: "panel-textarea",
+                        value: "{preset_text}",
+                        oninput: move |e| preset_text.set(e.value()),
+                    }
+                    if let Some(err) = preset_error() {
+                        p { class: "panel-hint", "{err}" }
+                    }
+                }
+        }
+    }
+}
@@ -1,10 +1,6 @@
 use dioxus::prelude::{rsx, *};
-use wasm_bindgen::prelude::Closure;
-use wasm_bindgen::JsCast;
-use web_sys::{MouseEvent, console, window};
 use crate::state::app_state::HSVState;
-use crate::dioxusui::GLOBAL_WINDOW_HANDLE;
-use std::rc::Rc;
+use crate::utils::platform::use_window_pointer;
 
 #[derive(Clone, Copy)]
 enum ResizeType {
@@ -46,23 +42,23 @@ pub fn DraggablePanel(props: DraggablePanelProps) -> Element {
     });
 
     // mouse move handler for dragging a panel by the title bar
-    let drag_handle = move |event: MouseEvent| {
+    let mut drag_handle = move |client_x: f64, client_y: f64| {
         if is_dragging() {
             let (start_x, start_y) = start_position();
-            let dx = event.client_x() as f64 - start_x;
-            let dy = event.client_y() as f64 - start_y;
-            start_position.set((event.client_x() as f64, event.client_y() as f64));
+            let dx = client_x - start_x;
+            let dy = client_y - start_y;
+            start_position.set((client_x, client_y));
             let (tx, ty) = translation();
             translation.set((tx + dx, ty + dy));
         }
     };
 
-    let resize_handle = move |event: MouseEvent| {
+    let mut resize_handle = move |client_x: f64, client_y: f64| {
         if let Some(resize_dir) = *resize_type.read() {
             let start_x = last_resize_x();
             let start_y = last_resize_y();
-            let dx = event.client_x() as f64 - start_x;
-            let dy = event.client_y() as f64 - start_y;
+            let dx = client_x - start_x;
+            let dy = client_y - start_y;
             
             let mut new_width = width();
             let mut new_height = height();
@@ -100,71 +96,25 @@ pub fn DraggablePanel(props: DraggablePanelProps) -> Element {
             if new_width >= 170.0 && new_width <= 600.0 {
                 width.set(new_width);
                 translation.set((tx, ty));
-                last_resize_x.set(event.client_x() as f64);
+                last_resize_x.set(client_x);
             }
 
             if new_height >= 200.0 && new_height <= 300.0 {
                 height.set(new_height);
                 translation.set((tx, ty));
-                last_resize_y.set(event.client_y() as f64);
+                last_resize_y.set(client_y);
             }
         }
     };
 
-    use_hook_with_cleanup(
-        move || {
-            let move_closure = Rc::new(Closure::wrap(Box::new(drag_handle) as Box<dyn FnMut(_)>));
-            let resize_closure = Rc::new(Closure::wrap(Box::new(resize_handle) as Box<dyn FnMut(_)>));
-
-            GLOBAL_WINDOW_HANDLE()
-                .add_event_listener_with_callback(
-                    "mousemove",
-                    resize_closure.as_ref().as_ref().unchecked_ref()
-                )
-                .unwrap();
-
-            GLOBAL_WINDOW_HANDLE()
-                .add_event_listener_with_callback(
-                    "mousemove",
-                    move_closure.as_ref().as_ref().unchecked_ref()
-                )
-                .unwrap();
-
-            let mut drag = is_dragging.clone();
-            let up_closure = Rc::new(Closure::wrap(Box::new(move |_event: MouseEvent| {
-                drag.set(false);
-                resize_type.set(None);
-            }) as Box<dyn FnMut(_)>));
-
-            GLOBAL_WINDOW_HANDLE()
-                .add_event_listener_with_callback(
-                    "mouseup",
-                    up_closure.as_ref().as_ref().unchecked_ref()
-                )
-                .unwrap();
-
-            (move_closure, up_closure, resize_closure)
+    use_window_pointer(
+        move |client_x, client_y| {
+            resize_handle(client_x, client_y);
+            drag_handle(client_x, client_y);
         },
-        move |(move_closure, up_closure, resize_closure)| {
-            if let Some(window) = window() {
-                window
-                    .remove_event_listener_with_callback(
-                        "mousemove",
-                        move_closure.as_ref().as_ref().unchecked_ref()
-                    )
-                    .unwrap();
-                window
-                    .remove_event_listener_with_callback(
-                        "mouseup",
-                        up_closure.as_ref().as_ref().unchecked_ref()
-                    )
-                    .unwrap();
-                window
-                    .remove_event_listener_with_callback(
-                        "mousemove", 
-                        resize_closure.as_ref().as_ref().unchecked_ref()
-                    ).unwrap();
-            }
+        move || {
+            is_dragging.set(false);
+            resize_type.set(None);
         },
     );
 
@@ -0,0 +1,154 @@
+use crate::components::draggable_panel::DraggablePanel;
+use crate::state::app_state::{ExportState, ImageVec, WGPUSignal};
+use crate::state::export::{ChromaSubsampling, ExportFormat, fill_template};
+use dioxus::prelude::*;
+use image::codecs::png::CompressionType;
+
+const PNG_COMPRESSION: [(&str, CompressionType); 3] = [
+    ("Fast", CompressionType::Fast),
+    ("Default", CompressionType::Default),
+    ("Best", CompressionType::Best),
+];
+
+#[component]
+pub fn ExportPanel() -> Element {
+    let mut format = use_context::<ExportState>().format;
+    let mut panel_visible = use_context::<ExportState>().panel_visible;
+    let mut save_signal = use_context::<WGPUSignal>().save_signal;
+    let mut name_template = use_context::<ExportState>().name_template;
+    let mut export_filename = use_context::<ExportState>().filename;
+    let mut keep_metadata = use_context::<ExportState>().keep_metadata;
+    let names = use_context::<ImageVec>().names;
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+
+    // the template filled in for the current image
+    let suggested_name = move || {
+        let index = curr_index();
+        let name = names.read().get(index).cloned().unwrap_or_else(|| String::from("image"));
+        fill_template(&name_template.read(), &name, &format.peek(), index)
+    };
+    let mut filename = use_signal(suggested_name);
+    // a new image or template replaces whatever was typed
+    use_effect(move || filename.set(suggested_name()));
+
+    let options = match format() {
+        ExportFormat::Png { compression } => rsx! {
+            div { class: "panel-slider-container",
+                p { "COMPRESSION" },
+                select { class: "panel-select",
+                    onchange: move |e| {
+                        if let Some((_, c)) = PNG_COMPRESSION.iter().find(|(name, _)| *name == e.value()) {
+                            format.set(ExportFormat::Png { compression: *c });
+                        }
+                    },
+                    for (name, c) in PNG_COMPRESSION {
+                        option { value: name, selected: compression == c, "{name}" }
+                    }
+                }
+            }
+        },
+        ExportFormat::Jpeg { quality, subsampling } => rsx! {
+            div { class: "panel-slider-container",
+                p { "QUALITY" },
+                input {
+                    class: "panel-slider",
+                    type: "range",
+                    min: 1,
+                    value: "{quality}",
+                    max: 100,
+                    step: 1,
+                    oninput: move |e| {
+                        if let Ok(parsed) = e.value().parse::<u8>() {
+                            format.set(ExportFormat::Jpeg { quality: parsed, subsampling });
+                        }
+                    },
+                }
+                p { class: "slider-progress", "{quality}" }
+            }
+            div { class: "panel-slider-container",
+                p { "CHROMA" },
+                select { class: "panel-select",
+                    onchange: move |e| {
+                        if let Some(s) = ChromaSubsampling::parse(&e.value()) {
+                            format.set(ExportFormat::Jpeg { quality, subsampling: s });
+                        }
+                    },
+                    for s in ChromaSubsampling::ALL {
+                        option { value: s.label(), selected: subsampling == s, "{s.label()}" }
+                    }
+                }
+            }
+            p { class: "panel-hint", "4:4:4 keeps full color detail, 4:2:0 gives the smallest files. Transparency is dropped." }
+        },
+        ExportFormat::WebP => rsx! {
+            p { class: "panel-hint", "Lossless, keeps transparency." }
+        },
+        ExportFormat::Tiff | ExportFormat::Bmp => rsx! {
+            p { class: "panel-hint", "Uncompressed RGBA." }
+        },
+    };
+
+    rsx! {
+        DraggablePanel {
+            title: String::from("Save as"),
+            PanelContent:
+                rsx! {
+                    div { class: "panel-slider-container",
+                        p { "FORMAT" },
+                        select { class: "panel-select",
+                            onchange: move |e| {
+                                if let Some(f) = ExportFormat::default_for(&e.value()) {
+                                    format.set(f);
+                                    let renamed = f.with_extension(&filename.peek());
+                                    filename.set(renamed);
+                                }
+                            },
+                            for name in ExportFormat::NAMES {
+                                option { value: name, selected: format().name() == name,
+                                    "{ExportFormat::default_for(name).map(|f| f.label()).unwrap_or(name)}"
+                                }
+                            }
+                        }
+                    }
+                    {options}
+                    if format().supports_16_bit() {
+                        p { class: "panel-hint", "16-bit and float images are written with 16 bits per channel." }
+                    } else {
+                        p { class: "panel-hint", "Written with 8 bits per channel." }
+                    }
+                    label { class: "panel-hint",
+                        input { type: "checkbox", checked: keep_metadata(),
+                            onchange: move |e| keep_metadata.set(e.checked()),
+                        }
+                        "Keep metadata (EXIF, ICC profile, XMP)"
+                    }
+                    if keep_metadata() && !format().keeps_metadata() {
+                        p { class: "panel-hint", "{format().label()} files are written without metadata." }
+                    }
+                    div { class: "panel-slider-container",
+                        p { "TEMPLATE" },
+                        input { class: "panel-text-input", type: "text", value: "{name_template}",
+                            oninput: move |e| name_template.set(e.value()),
+                        }
+                    }
+                    p { class: "panel-hint", "{{name}} is the source file name, {{ext}} the format's extension and {{index}} the image number." }
+                    div { class: "panel-slider-container",
+                        p { "FILE NAME" },
+                        input { class: "panel-text-input", type: "text", value: "{filename}",
+                            oninput: move |e| filename.set(e.value()),
+                        }
+                    }
+                    div { class: "panel-button-row",
+                        button { class: "btn",
+                            onclick: move |_| {
+                                export_filename.set(format().with_extension(&filename()));
+                                save_signal.set(save_signal() + 1);
+                                panel_visible.set(false);
+                            },
+                            "Save"
+                        }
+                    }
+                }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use crate::components::draggable_panel::DraggablePanel;
+use crate::state::app_state::{FilterState, HistoryState, ImageVec};
+use crate::state::filters::{Filter, FilterStack, MAX_CUSTOM_SIZE};
+use crate::utils::platform::log;
+use dioxus::prelude::*;
+
+// filters offered by the "Add" buttons: (name for Filter::default_for, button label)
+const ADDABLE_FILTERS: [(&str, &str); 4] = [
+    ("gaussian", "Gaussian"),
+    ("box", "Box"),
+    ("sharpen", "Sharpen"),
+    ("custom", "Custom"),
+];
+
+// the odd kernel size closest to `size`, within the supported range
+fn kernel_size(size: usize) -> usize {
+    let size = size.clamp(1, MAX_CUSTOM_SIZE);
+    if size.is_multiple_of(2) { size + 1 } else { size }
+}
+
+// the weights of a kernel resized to `size`, keeping the centered part of the old one
+fn resize_kernel(weights: &[f32], old_size: usize, size: usize) -> Vec<f32> {
+    let mut resized = vec![0.0; size * size];
+    for y in 0..size {
+        for x in 0..size {
+            let ox = x as isize + (old_size as isize - size as isize) / 2;
+            let oy = y as isize + (old_size as isize - size as isize) / 2;
+            if ox >= 0 && oy >= 0 && (ox as usize) < old_size && (oy as usize) < old_size {
+                resized[y * size + x] = weights[oy as usize * old_size + ox as usize];
+            }
+        }
+    }
+    resized
+}
+
+#[component]
+fn FilterRow(index: usize, filter: Filter) -> Element {
+    let filters = use_context::<FilterState>();
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+    let history = use_context::<HistoryState>();
+
+    // edits that would leave an invalid kernel are dropped
+    let edit = move |coalesce: Option<&'static str>, f: Box<dyn FnOnce(&mut Filter)>| {
+        filters.edit(curr_index(), history, coalesce, |stack| {
+            if let Some(filter) = stack.filters.get_mut(index) {
+                let mut edited = filter.clone();
+                f(&mut edited);
+                match edited.validate() {
+                    Ok(()) => *filter = edited,
+                    Err(err) => log(format!("Filter edit rejected: {}", err)),
+                }
+            }
+        });
+    };
+    let edit_stack = move |f: Box<dyn FnOnce(&mut FilterStack)>| {
+        filters.edit(curr_index(), history, None, f);
+    };
+
+    let controls = match filter.clone() {
+        Filter::GaussianBlur { radius } => rsx! {
+            div { class: "panel-slider-container",
+                p { "RADIUS" },
+                input {
+                    class: "panel-slider",
+                    type: "range",
+                    min: 0.0,
+                    value: "{radius.min(100.0)}",
+                    max: 100.0,
+                    step: 0.5,
+                    oninput: move |e| {
+                        if let Ok(parsed) = e.value().parse::<f32>() {
+                            edit(Some("filter-radius"), Box::new(move |f| *f = Filter::GaussianBlur { radius: parsed }));
+                        }
+                    },
+                    onchange: move |_| history.seal(curr_index()),
+                }
+                // wider blurs than the slider allows can be typed in
+                input { class: "panel-number-input", type: "number", min: 0, step: 0.5, value: "{radius}",
+                    oninput: move |e| {
+                        if let Ok(parsed) = e.value().parse::<f32>() {
+                            edit(Some("filter-radius"), Box::new(move |f| *f = Filter::GaussianBlur { radius: parsed.max(0.0) }));
+                        }
+                    },
+                    onchange: move |_| history.seal(curr_index()),
+                }
+            }
+        },
+        Filter::BoxBlur { radius } => rsx! {
+            div { class: "panel-slider-container",
+                p { "RADIUS" },
+                input {
+                    class: "panel-slider",
+                    type: "range",
+                    min: 0,
+                    value: "{radius}",
+                    max: 50,
+                    step: 1,
+                    oninput: move |e| {
+                        if let Ok(parsed) = e.value().parse::<u32>() {
+                            edit(Some("filter-radius"), Box::new(move |f| *f = Filter::BoxBlur { radius: parsed }));
+                        }
+                    },
+                    onchange: move |_| history.seal(curr_index()),
+                }
+                p { class: "slider-progress", "{radius}px" }
+            }
+        },
+        Filter::Sharpen { amount } => rsx! {
+            div { class: "panel-slider-container",
+                p { "AMOUNT" },
+                input {
+                    class: "panel-slider",
+                    type: "range",
+                    min: 0.0,
+                    value: "{amount}",
+                    max: 3.0,
+                    step: 0.01,
+                    oninput: move |e| {
+                        if let Ok(parsed) = e.value().parse::<f32>() {
+                            edit(Some("filter-amount"), Box::new(move |f| *f = Filter::Sharpen { amount: parsed }));
+                        }
+                    },
+                    onchange: move |_| history.seal(curr_index()),
+                }
+                p { class: "slider-progress", "{amount:.2}" }
+            }
+        },
+        Filter::Custom { size, weights, normalize } => rsx! {
+            div { class: "panel-slider-container",
+                p { "SIZE" },
+                input { class: "panel-number-input", type: "number", min: 1, max: MAX_CUSTOM_SIZE, step: 2, value: "{size}",
+                    oninput: move |e| {
+                        if let Ok(parsed) = e.value().parse::<usize>() {
+                            let new_size = kernel_size(parsed);
+                            edit(None, Box::new(move |f| {
+                                if let Filter::Custom { size, weights, .. } = f {
+                                    *weights = resize_kernel(weights, *size, new_size);
+                                    *size = new_size;
+                                }
+                            }));
+                        }
+                    }
+                }
+                label { class: "panel-hint",
+                    input { type: "checkbox", checked: normalize,
+                        onchange: move |e| {
+                            let checked = e.checked();
+                            edit(None, Box::new(move |f| {
+                                if let Filter::Custom { normalize, .. } = f {
+                                    *normalize = checked;
+                                }
+                            }));
+                        }
+                    }
+                    "Normalize"
+                }
+            }
+            div { class: "kernel-grid", style: "grid-template-columns: repeat({size}, 1fr);",
+                for (i, weight) in weights.iter().copied().enumerate() {
+                    input { class: "panel-number-input", type: "number", step: 0.1, value: "{weight}",
+                        oninput: move |e| {
+                            if let Ok(parsed) = e.value().parse::<f32>() {
+                                edit(Some("filter-weight"), Box::new(move |f| {
+                                    if let Filter::Custom { weights, .. } = f
+                                        && let Some(w) = weights.get_mut(i)
+                                    {
+                                        *w = parsed;
+                                    }
+                                }));
+                            }
+                        },
+                        onchange: move |_| history.seal(curr_index()),
+                    }
+                }
+            }
+        },
+    };
+
+    rsx! {
+        div { class: "layer-row",
+            p { class: "layer-name", "{filter.label()}" }
+            button { class: "btn", onclick: move |_| edit_stack(Box::new(move |stack| stack.move_earlier(index))), "▲" }
+            button { class: "btn", onclick: move |_| edit_stack(Box::new(move |stack| stack.move_later(index))), "▼" }
+            button { class: "btn",
+                onclick: move |_| edit_stack(Box::new(move |stack| {
+                    if index < stack.filters.len() {
+                        stack.filters.remove(index);
+                    }
+                })),
+                "✕"
+            }
+        }
+        {controls}
+    }
+}
+
+#[component]
+pub fn FiltersPanel() -> Element {
+    let filters = use_context::<FilterState>();
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+    let history = use_context::<HistoryState>();
+
+    let stack = filters.filters(curr_index());
+
+    rsx! {
+        DraggablePanel {
+            title: String::from("Filters"),
+            PanelContent:
+                rsx! {
+                    div { class: "panel-button-row",
+                        for (name, label) in ADDABLE_FILTERS {
+                            button { class: "btn",
+                                onclick: move |_| {
+                                    filters.edit(curr_index(), history, None, |stack| {
+                                        if let Some(filter) = Filter::default_for(name) {
+                                            stack.filters.push(filter);
+                                        }
+                                    });
+                                },
+                                "+ {label}"
+                            }
+                        }
+                    }
+                    if stack.filters.is_empty() {
+                        p { class: "panel-hint", "No filters" }
+                    }
+                    // applied top to bottom, before the color adjustments
+                    for (index, filter) in stack.filters.iter().cloned().enumerate() {
+                        FilterRow { key: "{index}", index, filter }
+                    }
+                }
+        }
+    }
+}
@@ -1,15 +1,33 @@
 use dioxus::prelude::*;
-use crate::state::app_state::{ImageZoom};
+use crate::state::app_state::{ImageVec, ImageZoom, TextureCacheState};
+use crate::state::texture_cache::MB;
 
 #[component]
 pub fn FootBar() -> Element {
     let mut zoom_signal = use_context::<ImageZoom>().zoom;
     let zoom_limits = use_context::<ImageZoom>().limits;
     let zoom_value = *zoom_signal.read();
+    let texture_cache = use_context::<TextureCacheState>();
+    let names = use_context::<ImageVec>().names;
+    let used_mb = (texture_cache.used)().div_ceil(MB);
+    let budget_mb = (texture_cache.budget_mb)();
+    // names of the images that will be uploaded again when shown
+    let evicted = texture_cache
+        .evicted
+        .read()
+        .iter()
+        .map(|&index| names.read().get(index).cloned().unwrap_or_else(|| format!("image {}", index)))
+        .collect::<Vec<_>>()
+        .join(", ");
 
     rsx! {
         div { class: "footer-main",
-            div { class: "footer-left"  },
+            div { class: "footer-left",
+                p { class: "footer-status", "GPU textures {used_mb} / {budget_mb} MB" }
+                if !evicted.is_empty() {
+                    p { class: "footer-status", title: "{evicted}", "Freed: {evicted}" }
+                }
+            },
             div { class: "footer-mid"   },
             div { class: "footer-right" ,
                 div { class: "zoom-slider-container",
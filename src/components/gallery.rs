@@ -2,7 +2,7 @@ use crate::app_router::Route;
 use crate::state::app_state::{GalleryState, ImageVec};
 use dioxus::html::col;
 use dioxus::html::g::dangerous_inner_html;
-use web_sys::console;
+use crate::utils::platform::log;
 use dioxus::prelude::*;
 use image::GenericImageView;
 const BACK_BUTTON: Asset = asset!("/assets/back-button.svg");
@@ -20,6 +20,7 @@ const CHEVRON_DOWN_SVG: &str = "<svg xmlns='http://www.w3.org/2000/svg' fill='no
 fn GalleryHeader() -> Element {
     let mut grid_size = use_context::<GalleryState>().grid_size;
     let mut dropdown_visible = use_context::<GalleryState>().visibility;
+    let mut selection = use_context::<GalleryState>().selection;
     let visibility = if dropdown_visible() {
         "display: flex;"
     } else {
@@ -36,6 +37,9 @@ fn GalleryHeader() -> Element {
                 }
             }
             p { "GALLERY" }
+            if !selection.read().is_empty() {
+                button { class: "btn", onclick: move |_| selection.write().clear(), "Clear selection ({selection.read().len()})" }
+            }
             div { class: "grid-size-select-container",
                 onclick: move |_| {
                     dropdown_visible.set(!dropdown_visible());
@@ -64,6 +68,7 @@ pub fn Gallery() -> Element {
     let img_vec = use_context::<ImageVec>().vector;
     let mut curr_index = use_context::<ImageVec>().curr_image_index;
     let grid_size = use_context::<GalleryState>().grid_size;
+    let mut selection = use_context::<GalleryState>().selection;
     let img_vector = img_vec_base64();
 
     let (column_width, image_width, image_height) = match &*grid_size() {
@@ -73,7 +78,7 @@ pub fn Gallery() -> Element {
         _ => (400, 360, 180)
     };
 
-    console::log_1(&format!("Current index: {}", curr_index()).into());
+    log(format!("Current index: {}", curr_index()));
 
     rsx! {
         div { class: "gallery-page",
@@ -92,11 +97,24 @@ pub fn Gallery() -> Element {
                                                 style: format!("width: {}px; height: {}px;", image_width, image_height),
                                                 onclick: move |_| {
                                                     curr_index.set(index);
-                                                    console::log_1(&format!("Clicked image index: {}", index).into());
+                                                    log(format!("Clicked image index: {}", index));
                                                 },
                                                 src: "{img_url}"
                                             }
                                         }
+                                        // picks the image for batch processing
+                                        label { class: "batch-select",
+                                            input { type: "checkbox", checked: selection.read().contains(&index),
+                                                onchange: move |e| {
+                                                    if e.checked() {
+                                                        selection.write().insert(index);
+                                                    } else {
+                                                        selection.write().remove(&index);
+                                                    }
+                                                }
+                                            }
+                                            "Batch"
+                                        }
                                     }
                                 }
                         )
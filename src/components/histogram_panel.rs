@@ -0,0 +1,87 @@
+use crate::components::draggable_panel::DraggablePanel;
+use crate::state::app_state::HistogramState;
+use crate::state::histogram::{BINS, Histogram, HistogramChannel};
+use dioxus::prelude::*;
+
+const GRAPH_HEIGHT: f32 = 100.0;
+
+// closed area under the bins, scaled so `peak` reaches the top of the graph
+fn histogram_path(bins: &[u32], peak: u32) -> String {
+    let mut path = format!("M 0 {}", GRAPH_HEIGHT);
+    for (i, &count) in bins.iter().enumerate() {
+        let height = (count as f32 / peak as f32).min(1.0) * GRAPH_HEIGHT;
+        path.push_str(&format!(" L {} {:.2}", i, GRAPH_HEIGHT - height));
+    }
+    path.push_str(&format!(" L {} {} Z", BINS - 1, GRAPH_HEIGHT));
+    path
+}
+
+// tallest bin of the shown channels, ignoring the clipped ends so they do not flatten the rest
+fn histogram_peak(histogram: &Histogram, shown: &[HistogramChannel]) -> u32 {
+    shown
+        .iter()
+        .flat_map(|&channel| histogram.channel(channel)[1..BINS - 1].iter().copied())
+        .max()
+        .unwrap_or(0)
+        .max(1)
+}
+
+#[component]
+pub fn HistogramPanel() -> Element {
+    let data = use_context::<HistogramState>().data;
+    let supported = use_context::<HistogramState>().supported;
+    let mut shown = use_signal(|| HistogramChannel::ALL.to_vec());
+
+    let channel_class = move |c: HistogramChannel| if shown.read().contains(&c) { "btn on" } else { "btn" };
+    let mut toggle_channel = move |c: HistogramChannel| {
+        let mut channels = shown.write();
+        match channels.iter().position(|&channel| channel == c) {
+            Some(i) => {
+                channels.remove(i);
+            }
+            None => channels.push(c),
+        }
+    };
+
+    let histogram = data();
+
+    rsx! {
+        DraggablePanel {
+            title: String::from("Histogram"),
+            PanelContent:
+                rsx! {
+                    div { class: "panel-button-row",
+                        for c in HistogramChannel::ALL {
+                            button { class: channel_class(c), onclick: move |_| toggle_channel(c), "{c.label()}" }
+                        }
+                    }
+                    if !supported() {
+                        p { class: "panel-hint", "The histogram needs compute shader support, which this browser's GPU backend does not offer." }
+                    } else if let Some(histogram) = histogram {
+                        svg { class: "histogram-graph",
+                            view_box: "0 0 {BINS - 1} {GRAPH_HEIGHT}",
+                            preserve_aspect_ratio: "none",
+                            for c in HistogramChannel::ALL {
+                                if shown.read().contains(&c) {
+                                    path { class: "histogram-area {c.name()}",
+                                        d: histogram_path(histogram.channel(c), histogram_peak(&histogram, &shown.read())),
+                                    }
+                                }
+                            }
+                        }
+                        for c in HistogramChannel::ALL {
+                            if shown.read().contains(&c) {
+                                div { class: "panel-slider-container",
+                                    p { "{c.label()}" }
+                                    p { class: "slider-progress", "Shadows {histogram.clipping(c).0:.2}%" }
+                                    p { class: "slider-progress", "Highlights {histogram.clipping(c).1:.2}%" }
+                                }
+                            }
+                        }
+                    } else {
+                        p { class: "panel-hint", "Waiting for the first render..." }
+                    }
+                }
+        }
+    }
+}
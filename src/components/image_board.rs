@@ -1,18 +1,26 @@
-use crate::dioxusui::GLOBAL_WINDOW_HANDLE;
-use crate::state::app_state::{DragSignal, HSVState, ImageVec, ImageZoom, NextImage, WGPUSignal};
-use crate::state::customlib::{Filesave_config, State};
+use crate::components::crop_panel::CropOverlay;
+use crate::state::app_state::{display_settings, export_settings, AdjustmentState, BatchState, BoardState, SideBarVisibility, BatchStatus, TextureCacheState, BrushState, ExportState, CropState, CurvesState, FilterState, HistogramState, LevelsState, DragSignal, HistoryState, ImageVec, ImageZoom, NextImage, TransformState, WGPUSignal};
+use crate::state::customlib::{BoardPlacement, RenderSettings, State};
+use crate::state::export::{file_stem, fill_template};
+use crate::state::history::{EditCommand, HistoryAction};
+use crate::state::metadata::{ImageMetadata, decode_with_metadata};
+use crate::state::reference;
+use crate::state::texture_cache::MB;
+use crate::utils::platform::{Viewport, choose_batch_target, log, use_viewport};
 use crate::utils::renderer::start_wgpu;
-use crate::utils::utils::{clamp_translate_value, get_scroll_value};
-use base64::Engine;
-use base64::engine::general_purpose::STANDARD as base64_engine;
+use crate::utils::utils::{clamp_translate_value, get_scroll_value, make_png_base64, make_thumbnail_base64, save_file_via_dialog};
 use dioxus::html::g::{scale, transform_origin};
-use dioxus::{html::HasFileData, prelude::*};
-use image::{DynamicImage, GenericImageView, load_from_memory};
+use dioxus::html::geometry::{PixelsRect, PixelsSize};
+use dioxus::html::{FileEngine, HasFileData};
+use dioxus::prelude::*;
+use image::{DynamicImage, GenericImageView};
 use std::cell::RefCell;
 use std::collections::VecDeque;
-use std::io::Cursor;
 use std::rc::Rc;
-use web_sys::{console, window};
+use std::sync::Arc;
+
+// images rendered before their readbacks are awaited, bounds the GPU memory a batch holds at once
+const BATCH_CONCURRENCY: usize = 4;
 
 #[component]
 pub fn ImageBoard() -> Element {
@@ -21,88 +29,397 @@ pub fn ImageBoard() -> Element {
     let scale_value: f64 = zoom_signal() as f64 / 100.0;
     let mut image_data_q = use_context::<ImageVec>().vector;
     let mut image_vector_base64 = use_context::<ImageVec>().base64_vector;
+    let mut image_names = use_context::<ImageVec>().names;
     let mut curr_index = use_context::<ImageVec>().curr_image_index;
     let mut translation = use_signal(|| (0.0, 0.0));
     let mut is_dragging = use_signal(|| false);
     let mut can_drag = use_context::<DragSignal>().can_drag;
     let mut start_position = use_signal(|| (0.0, 0.0));
-    let get_viewport_size = || {
-        let window = window().expect("No global window found.");
-        let width = window.inner_width().unwrap();
-        let height = window.inner_height().unwrap();
-        (width.as_f64().unwrap(), height.as_f64().unwrap())
-    };
-    let mut viewport_size = use_signal(|| get_viewport_size());
+    let viewport = use_viewport();
+    let viewport_size = move || (viewport().width, viewport().height);
+    let mut board_rect = use_context::<BoardState>().rect;
+    let mut board_mount = use_signal(|| None::<Rc<MountedData>>);
+    // where the board is laid out before panning, panning and zooming only transform it
+    let mut board_origin = use_signal(|| None::<(f64, f64)>);
+    let sidebar_visible = use_context::<SideBarVisibility>().state;
+    let mut import = use_context::<ImageVec>().import;
     let mut image_size = use_signal(|| (0.0, 0.0));
     let mut wgpu_on = use_context::<WGPUSignal>().signal;
     let mut next_img_signal = use_context::<NextImage>().count;
     let mut draw_signal = use_signal(|| false);
     let mut ready_signal = use_signal(|| false);
-    let mut hue = use_context::<HSVState>().hue;
-    let mut sat = use_context::<HSVState>().saturation;
-    let mut val = use_context::<HSVState>().value;
+    let mut adjustment_stacks = use_context::<AdjustmentState>().stacks;
+    let mut image_curves = use_context::<CurvesState>().curves;
+    let mut filter_stacks = use_context::<FilterState>().stacks;
+    let levels_state = use_context::<LevelsState>();
+    let mut histogram_supported = use_context::<HistogramState>().supported;
+    let histogram_visible = use_context::<HistogramState>().panel_visible;
+    let mut histogram_data = use_context::<HistogramState>().data;
+    let mut image_levels = levels_state.levels;
+    let mut eyedropper = levels_state.eyedropper;
     let zoom_speed = 1.15;
-    let mut wgpu_state_signal = use_signal::<Option<Rc<RefCell<State>>>>(|| None);
+    let mut wgpu_state_signal = use_context::<WGPUSignal>().state;
+    let texture_budget = use_context::<TextureCacheState>().budget_mb;
+    let mut texture_used = use_context::<TextureCacheState>().used;
+    let mut texture_evicted = use_context::<TextureCacheState>().evicted;
+    // without a GPU adapter the current image is rendered on the CPU and shown as a data url
+    let mut cpu_fallback = use_signal(|| false);
+    let mut cpu_preview = use_signal::<Option<String>>(|| None);
     let mut save_signal = use_context::<WGPUSignal>().save_signal;
+    let mut reload_signal = use_context::<WGPUSignal>().reload_signal;
+    let crop_visible = use_context::<CropState>().panel_visible;
+    let crops = use_context::<CropState>().crops;
+    let brush_visible = use_context::<BrushState>().panel_visible;
+    let brush_settings = use_context::<BrushState>().settings;
+    let mut is_painting = use_signal(|| false);
+    let history = use_context::<HistoryState>();
+    let mut history_pending = history.pending;
+    let mut history_stacks = history.stacks;
+    let mut crops_mut = use_context::<CropState>().crops;
+    let transforms = use_context::<TransformState>().transforms;
+    let mut transforms_mut = use_context::<TransformState>().transforms;
+    let batch = use_context::<BatchState>();
+    let mut batch_run = batch.run_signal;
+    let export_format = use_context::<ExportState>().format;
+    let export_template = use_context::<ExportState>().name_template;
+    let export_filename = use_context::<ExportState>().filename;
+    let keep_metadata = use_context::<ExportState>().keep_metadata;
+    let mut image_metadata = use_context::<ImageVec>().metadata;
+    // what an export of image `index` carries over from its source file
+    let export_metadata = move |index: usize| {
+        if *keep_metadata.peek() {
+            image_metadata.peek().get(index).cloned().unwrap_or_default()
+        } else {
+            ImageMetadata::default()
+        }
+    };
+
+    // shows how much of the texture budget is in use and which images had to leave it
+    let mut report_textures = move |wgpu_state: &mut State| {
+        let evicted = wgpu_state.take_evictions();
+        if !evicted.is_empty() {
+            log(format!("Evicted images {:?} from the texture cache", evicted));
+            texture_evicted.set(evicted);
+        }
+        texture_used.set(wgpu_state.texture_memory().0);
+    };
+
+    // redraws the shown image with its current settings and refreshes the histogram if it is open
+    let mut present = move |wgpu_state: &mut State, update_texture: bool| {
+        let settings = display_settings(wgpu_state.img_index as usize);
+        wgpu_state.draw(update_texture, &settings);
+        report_textures(wgpu_state);
+        if !histogram_visible() {
+            return;
+        }
+        if let Some(readback) = wgpu_state.histogram() {
+            spawn(async move {
+                match readback.into_histogram().await {
+                    Ok(histogram) => histogram_data.set(Some(histogram)),
+                    Err(err) => log(err),
+                }
+            });
+        }
+    };
+
+    // position of the pointer in source image pixels, if the canvas is up
+    let pointer_to_source = move |client_x: f64, client_y: f64| -> Option<(f64, f64)> {
+        let rect = board_rect()?;
+        let u = (client_x - rect.min_x()) / rect.width();
+        let v = (client_y - rect.min_y()) / rect.height();
+        let wgpu_state_rc = wgpu_state_signal.read().clone()?;
+        let source_pos = wgpu_state_rc.borrow().canvas_to_source(u, v);
+        Some(source_pos)
+    };
+
+    // the layout only changes with the image size, the window and the side bar
+    use_effect(move || {
+        let _ = (image_size(), viewport(), sidebar_visible());
+        let Some(mounted) = board_mount() else {
+            return;
+        };
+        spawn(async move {
+            if let Ok(rect) = mounted.get_client_rect().await {
+                let (tx, ty) = *translation.peek();
+                board_origin.set(Some((rect.min_x() - tx, rect.min_y() - ty)));
+            }
+        });
+    });
+
+    use_effect(move || {
+        let Some((x, y)) = board_origin() else {
+            board_rect.set(None);
+            return;
+        };
+        let (tx, ty) = translation();
+        let zoom = zoom_signal() as f64 / 100.0;
+        let size = PixelsSize::new(image_size().0 * zoom, image_size().1 * zoom);
+        board_rect.set(Some(PixelsRect::new((x + tx, y + ty).into(), size)));
+    });
+
+    // outside the browser the surface is the whole window, the image is drawn where the board is
+    use_effect(move || {
+        let (Some(rect), Some(wgpu_state_rc)) = (board_rect(), wgpu_state_signal()) else {
+            return;
+        };
+        if cfg!(target_arch = "wasm32") || !ready_signal() {
+            return;
+        }
+        let Viewport { width, height, pixel_ratio } = viewport();
+        let placement = BoardPlacement {
+            x: rect.min_x() * pixel_ratio,
+            y: rect.min_y() * pixel_ratio,
+            width: rect.width() * pixel_ratio,
+            height: rect.height() * pixel_ratio,
+            pixel_ratio,
+        };
+        let surface_size = ((width * pixel_ratio).round() as u32, (height * pixel_ratio).round() as u32);
+        let mut wgpu_state = wgpu_state_rc.borrow_mut();
+        wgpu_state.set_board(placement, surface_size);
+        present(&mut wgpu_state, false);
+    });
+
+    use_effect(move || {
+        let budget = texture_budget();
+        if let Some(wgpu_state_rc) = &*wgpu_state_signal.read() {
+            let mut wgpu_state = wgpu_state_rc.borrow_mut();
+            wgpu_state.set_texture_budget(budget * MB);
+            report_textures(&mut wgpu_state);
+        }
+    });
 
     #[allow(unused)]
     use_effect(move || {
         if wgpu_on() {
             spawn(async move {
-                hue.set(0.0);
-                sat.set(0.0);
-                val.set(0.0);
                 let mut image_datas: VecDeque<DynamicImage> = image_data_q.cloned();
-                console::log_1(&format!("Images : {}", image_datas.clone().len()).into());
-                console::log_1(&format!("Current index: {}", curr_index() as u32).into());
+                // coming back from the gallery or loading more images keeps the running renderer
+                if let Some(state) = wgpu_state_signal.peek().clone() {
+                    #[cfg(target_arch = "wasm32")]
+                    if let Err(err) = state.borrow_mut().attach_canvas() {
+                        log(err);
+                        return;
+                    }
+                    let wgpusender = state.borrow().sender();
+                    for img in image_datas.iter().skip(state.borrow().img_vec.len()) {
+                        wgpusender.send(img.clone());
+                    }
+                    state.borrow_mut().receive();
+                    state.borrow_mut().set_index(curr_index() as u32);
+                    ready_signal.set(true);
+                    present(&mut state.borrow_mut(), true);
+                    let (width, height) = state.borrow().output_dimensions();
+                    image_size.set((width as f64, height as f64));
+                    log("Switched to image on the running renderer");
+                    return;
+                }
+                log(format!("Images : {}", image_datas.clone().len()));
+                log(format!("Current index: {}", curr_index() as u32));
                 let first_img = image_datas.get(curr_index()).unwrap();
-                let state = Rc::new(RefCell::new(start_wgpu(first_img).await));
-
-                image_size.set((
-                    first_img.dimensions().0 as f64,
-                    first_img.dimensions().1 as f64,
-                ));
-                console::log_1(&"Started WGPU".into());
-                console::log_1(&format!("Images: {}", image_datas.len()).into());
+                let state = match start_wgpu(first_img).await {
+                    Ok(state) => Rc::new(RefCell::new(state)),
+                    Err(err) => {
+                        log(format!("{}, rendering on the CPU", err));
+                        cpu_fallback.set(true);
+                        return;
+                    }
+                };
+                log("Started WGPU");
+                log(format!("Images: {}", image_datas.len()));
                 let mut wgpusender = state.borrow().sender();
                 for (i, img) in image_datas.iter().enumerate() {
                     if i > 0 {
                         wgpusender.send(img.clone());
                     }
                 }
-                state.borrow_mut().receive().await;
+                histogram_supported.set(state.borrow().renderer.histogram_pass.is_some());
+                state.borrow_mut().receive();
+                // the renderer started on the current image, put image 0 back in its slot so indices line up
+                if curr_index() != 0 {
+                    state.borrow_mut().replace_image(0, image_datas[0].clone());
+                }
                 state.borrow_mut().set_index(curr_index() as u32);
                 ready_signal.set(true);
-                state.borrow_mut().draw(true, None);
+                present(&mut state.borrow_mut(), true);
+                let (width, height) = state.borrow().output_dimensions();
+                image_size.set((width as f64, height as f64));
                 wgpu_state_signal.set(Some(state.clone()));
-                console::log_1(&"Drew first image".into());
+                log("Drew first image");
             });
         };
     });
 
     use_effect(move || {
-        // track hue
-        let hue = hue();
-        let saturation = sat();
-        let value = val();
+        if !cpu_fallback() {
+            return;
+        }
+        // reading the settings tracks every edit of the current image
+        let index = curr_index();
+        let settings = display_settings(index);
+        let Some(image) = image_data_q.read().get(index).cloned() else {
+            return;
+        };
+        let rendered = reference::render(&image, None, &settings);
+        image_size.set((rendered.width() as f64, rendered.height() as f64));
+        cpu_preview.set(Some(make_png_base64(&rendered)));
+        log("Rendered preview on the CPU");
+    });
+
+    use_effect(move || {
+        // track filters, adjustment layers, levels and curves
+        let _ = filter_stacks.read();
+        let _ = adjustment_stacks.read();
+        let _ = image_levels.read();
+        let _ = image_curves.read();
+
+        if wgpu_on() && ready_signal() && let Some(wgpu_state_rc) = &*wgpu_state_signal.read() {
+            let mut wgpu_state = wgpu_state_rc.borrow_mut();
+            present(&mut wgpu_state, false);
+            log("Triggered re-render from adjustment change");
+        }
+    });
+
+    use_effect(move || {
+        // track crop tool, applied crops and transforms
+        let _ = crop_visible();
+        let _ = crops.read();
+        let _ = transforms.read();
+
+        if wgpu_on() && ready_signal() && let Some(wgpu_state_rc) = &*wgpu_state_signal.read() {
+            let mut wgpu_state = wgpu_state_rc.borrow_mut();
+            present(&mut wgpu_state, false);
+            let (width, height) = wgpu_state.output_dimensions();
+            image_size.set((width as f64, height as f64));
+            log("Triggered re-render from crop or transform change");
+        }
+    });
 
-        if wgpu_on() && ready_signal() {
+    use_effect(move || {
+        if let Some(index) = reload_signal() {
             if let Some(wgpu_state_rc) = &*wgpu_state_signal.read() {
                 let mut wgpu_state = wgpu_state_rc.borrow_mut();
-                wgpu_state.draw(false, None);
-                console::log_1(&"Triggered re-render from HSV change".into());
+                if let Some(img) = image_data_q.read().get(index) {
+                    wgpu_state.replace_image(index, img.clone());
+                }
+                if index == wgpu_state.img_index as usize {
+                    present(&mut wgpu_state, true);
+                    let (width, height) = wgpu_state.output_dimensions();
+                    image_size.set((width as f64, height as f64));
+                }
+                log("Triggered re-upload of edited image");
             }
+            reload_signal.set(None);
         }
     });
 
+    use_effect(move || {
+        let Some(action) = history_pending() else {
+            return;
+        };
+        history_pending.set(None);
+
+        let index = curr_index();
+        // applied by reference, the command stays on the other stack
+        let mut stacks = history_stacks.write();
+        let command = match stacks.get_mut(&index) {
+            Some(stack) => match action {
+                HistoryAction::Undo => stack.undo(),
+                HistoryAction::Redo => stack.redo(),
+            },
+            None => None,
+        };
+        let Some(command) = command else {
+            log("Nothing to undo / redo");
+            return;
+        };
+
+        match command {
+            EditCommand::Adjustments { after, .. } => {
+                adjustment_stacks.write().insert(index, after.clone());
+            }
+            EditCommand::Curves { after, .. } => {
+                image_curves.write().insert(index, after.clone());
+            }
+            EditCommand::Levels { after, .. } => {
+                image_levels.write().insert(index, *after);
+            }
+            EditCommand::Filters { after, .. } => {
+                filter_stacks.write().insert(index, after.clone());
+            }
+            EditCommand::Crop { after, .. } => {
+                match *after {
+                    Some(rect) => crops_mut.write().insert(index, rect),
+                    None => crops_mut.write().remove(&index),
+                };
+            }
+            EditCommand::Transform { after, crop_after, .. } => {
+                transforms_mut.write().insert(index, *after);
+                match *crop_after {
+                    Some(rect) => crops_mut.write().insert(index, rect),
+                    None => crops_mut.write().remove(&index),
+                };
+            }
+            EditCommand::Resize { source, size, filter, restore, crop_after, paint_after, .. } => {
+                match *crop_after {
+                    Some(rect) => crops_mut.write().insert(index, rect),
+                    None => crops_mut.write().remove(&index),
+                };
+                if let Some(wgpu_state_rc) = &*wgpu_state_signal.peek() {
+                    wgpu_state_rc.borrow_mut().set_paint_layer(index, paint_after.clone());
+                }
+                let image = if *restore {
+                    source.clone()
+                } else {
+                    source.resize_exact(size.0, size.1, *filter)
+                };
+                if let Some(thumbnail) = image_vector_base64.write().get_mut(index) {
+                    *thumbnail = make_thumbnail_base64(&image);
+                }
+                if let Some(slot) = image_data_q.write().get_mut(index) {
+                    *slot = image;
+                }
+                reload_signal.set(Some(index));
+            }
+            EditCommand::Paint { rect, after, .. } => {
+                if let Some(wgpu_state_rc) = &*wgpu_state_signal.peek() {
+                    let mut wgpu_state = wgpu_state_rc.borrow_mut();
+                    wgpu_state.apply_paint_patch(index, *rect, after);
+                    present(&mut wgpu_state, false);
+                }
+            }
+        }
+        log(format!("Applied {:?} on image {}", action, index));
+    });
+
     use_effect(move || {
         if wgpu_on() && save_signal() > 0 {
             if let Some(wgpu_state_rc) = &*wgpu_state_signal.read() {
                 let mut wgpu_state = wgpu_state_rc.borrow_mut();
-                wgpu_state.draw_to_texture(Filesave_config {
-                    path: String::from("image.png"),
+                let index = wgpu_state.img_index as usize;
+                let path = if export_filename.read().trim().is_empty() {
+                    let name = image_names.read().get(index).cloned().unwrap_or_else(|| String::from("image"));
+                    fill_template(&export_template.read(), &name, &export_format(), index)
+                } else {
+                    export_filename()
+                };
+                let format = export_format();
+                let metadata = export_metadata(index);
+                let rendered = wgpu_state.render_offscreen(index, &export_settings(index));
+                // the offscreen render re-uploads the shown image but leaves the surface without a frame
+                present(&mut wgpu_state, false);
+                spawn(async move {
+                    let result = match rendered {
+                        Ok(readback) => match readback.into_image().await {
+                            Ok(image) => save_file_via_dialog(&image, path, &format, &metadata).await,
+                            Err(err) => Err(err),
+                        },
+                        Err(err) => Err(err),
+                    };
+                    if let Err(err) = result {
+                        log(format!("Saving failed: {}", err));
+                    }
                 });
-                console::log_1(&"Triggered save from signal".into());
+                log("Triggered save from signal");
                 save_signal.set(0);
             }
         } else if save_signal() > 0 {
@@ -110,9 +427,160 @@ pub fn ImageBoard() -> Element {
         }
     });
 
+    use_effect(move || {
+        if !batch_run() {
+            return;
+        }
+        batch_run.set(false);
+
+        let Some(wgpu_state_rc) = wgpu_state_signal.peek().clone() else {
+            for item in batch.items.peek().clone() {
+                batch.set_status(item.index, BatchStatus::Failed(String::from("the renderer is not running")));
+            }
+            return;
+        };
+        // every image gets the current image's edits but keeps its own crop, transform and paint layer
+        let shown = *curr_index.peek();
+        let edits = export_settings(shown);
+        let format = *export_format.peek();
+        let template = export_template.peek().clone();
+        let indices: Vec<usize> = batch.items.peek().iter().map(|item| item.index).collect();
+
+        spawn(async move {
+            let target = match choose_batch_target(indices.len()).await {
+                Ok(target) => target,
+                Err(err) => {
+                    for &index in &indices {
+                        batch.set_status(index, BatchStatus::Failed(err.clone()));
+                    }
+                    return;
+                }
+            };
+            for chunk in indices.chunks(BATCH_CONCURRENCY) {
+                let mut pending = Vec::new();
+                {
+                    let mut wgpu_state = wgpu_state_rc.borrow_mut();
+                    for &index in chunk {
+                        batch.set_status(index, BatchStatus::Rendering);
+                        let settings = RenderSettings {
+                            crop: crops.peek().get(&index).copied(),
+                            transform: transforms.peek().get(&index).copied().unwrap_or_default(),
+                            ..edits.clone()
+                        };
+                        match wgpu_state.render_offscreen(index, &settings) {
+                            Ok(readback) => pending.push((index, readback)),
+                            Err(err) => batch.set_status(index, BatchStatus::Failed(err)),
+                        }
+                    }
+                    // put the shown image back before yielding, so redraws in between stay correct
+                    wgpu_state.set_index(shown as u32);
+                    present(&mut wgpu_state, true);
+                }
+
+                // the whole chunk is already in flight on the GPU
+                for (index, readback) in pending {
+                    match readback.into_image().await {
+                        Ok(image) => {
+                            let source_name = image_names.peek().get(index).cloned().unwrap_or_else(|| String::from("image"));
+                            let name = format.with_extension(&fill_template(&template, &source_name, &format, index));
+                            let saved = match format.encode(&image, &export_metadata(index)) {
+                                Ok(bytes) => target.save(bytes, name.clone()).await,
+                                Err(err) => Err(err),
+                            };
+                            match saved {
+                                Ok(()) => batch.set_status(index, BatchStatus::Saved(name)),
+                                Err(err) => batch.set_status(index, BatchStatus::Failed(err)),
+                            }
+                        }
+                        Err(err) => batch.set_status(index, BatchStatus::Failed(err)),
+                    }
+                }
+            }
+            log(format!("Batch of {} images finished", indices.len()));
+        });
+    });
+
+    // decodes dropped or picked files and appends them to the open images
+    let mut load_files = move |file_engine: Arc<dyn FileEngine>| {
+        let file_names = file_engine.files();
+
+        zoom_signal.set(100);
+
+        spawn(async move {
+            wgpu_on.set(false);
+            draw_signal.set(false);
+            ready_signal.set(false);
+            next_img_signal.set(0);
+            let mut image_datas = VecDeque::<DynamicImage>::new();
+            let mut image_datas_base64 = VecDeque::<String>::new();
+            let mut image_datas_names = VecDeque::<String>::new();
+            let mut image_datas_metadata = VecDeque::<ImageMetadata>::new();
+            for file_name in file_names{if let Some(bytes) = file_engine.read_file(&file_name).await {
+                match decode_with_metadata(&bytes) {
+                    Ok((img, metadata)) => {
+                        image_datas_base64.push_back(make_thumbnail_base64(&img));
+                        image_datas_names.push_back(file_stem(&file_name));
+                        image_datas_metadata.push_back(metadata);
+                        image_datas.push_back(img);
+                    },
+                    Err(err) => log(format!("Skipping {}, unsupported image format: {}", file_name, err)),
+                }
+            }}
+            // nothing decoded, keep showing what was open before
+            let Some(first) = image_datas.front() else {
+                log("None of the files could be opened");
+                wgpu_on.set(!image_data_q.peek().is_empty());
+                return;
+            };
+            image_size.set((first.dimensions().0 as f64, first.dimensions().1 as f64));
+            let mut img_vec = image_data_q();
+            img_vec.append(&mut image_datas);
+            image_data_q.set(img_vec);
+            let mut img_vec_base64 = image_vector_base64();
+            img_vec_base64.append(&mut image_datas_base64);
+            image_vector_base64.set(img_vec_base64);
+            image_names.write().append(&mut image_datas_names);
+            image_metadata.write().append(&mut image_datas_metadata);
+            wgpu_on.set(true);
+        });
+    };
+
+    use_effect(move || {
+        if let Some(file_engine) = import() {
+            import.set(None);
+            load_files(file_engine);
+        }
+    });
+
+    let mut end_paint = move || {
+        if is_painting() {
+            if let Some(wgpu_state_rc) = &*wgpu_state_signal.read() {
+                let mut wgpu_state = wgpu_state_rc.borrow_mut();
+                if let Some(patch) = wgpu_state.paint_end() {
+                    let command = EditCommand::Paint {
+                        rect: patch.rect,
+                        before: patch.before,
+                        after: patch.after,
+                    };
+                    history.record(wgpu_state.img_index as usize, command, None);
+                }
+            }
+            is_painting.set(false);
+        }
+    };
+
+    // rsx cannot infer the type of an else-if chain in an attribute
+    let cursor_style = if is_dragging() {
+        "cursor: grabbing;"
+    } else if eyedropper().is_some() || (brush_visible() && !can_drag()) {
+        "cursor: crosshair;"
+    } else {
+        "cursor: default;"
+    };
+
     rsx! {
         div { class: "image-container",
-            style: if is_dragging() { "cursor: grabbing;" } else {"cursor: default;"},
+            style: cursor_style,
             onwheel: move |evt| {
                 if wgpu_on() {
                     evt.prevent_default();
@@ -132,10 +600,11 @@ pub fn ImageBoard() -> Element {
                         return;
                     }
 
-                    let canvas_el = GLOBAL_WINDOW_HANDLE().document().unwrap().get_element_by_id("image-board").expect("Cannot find canvas element.");
-                    let rect = canvas_el.get_bounding_client_rect();
-                    let rect_left = rect.left();
-                    let rect_top = rect.top();
+                    let Some(rect) = board_rect() else {
+                        return;
+                    };
+                    let rect_left = rect.min_x();
+                    let rect_top = rect.min_y();
 
                     let client_x = evt.coordinates().client().x;
                     let client_y = evt.coordinates().client().y;
@@ -164,19 +633,50 @@ pub fn ImageBoard() -> Element {
                 }
             },
             onmousedown: move |evt| {
-                if can_drag() {
+                if let Some(picker) = eyedropper() {
+                    // eyedroppers read the colour the levels receive under the cursor
+                    let client = evt.coordinates().client();
+                    if let Some(pos) = pointer_to_source(client.x, client.y) {
+                        let settings = export_settings(curr_index());
+                        let color = image_data_q
+                            .read()
+                            .get(curr_index())
+                            .map(|img| reference::levels_input(img, &settings, pos));
+                        if let Some(color) = color {
+                            levels_state.edit(curr_index(), history, None, |levels| levels.pick(picker, color));
+                        }
+                    }
+                    eyedropper.set(None);
+                } else if brush_visible() && !can_drag() && wgpu_on() {
+                    let client = evt.coordinates().client();
+                    if let (Some(pos), Some(wgpu_state_rc)) = (pointer_to_source(client.x, client.y), wgpu_state_signal.read().clone()) {
+                        let mut wgpu_state = wgpu_state_rc.borrow_mut();
+                        wgpu_state.paint_begin(pos, brush_settings());
+                        present(&mut wgpu_state, false);
+                        is_painting.set(true);
+                    }
+                } else if can_drag() {
                     is_dragging.set(true);
                     start_position.set((evt.coordinates().client().x, evt.coordinates().client().y));
-                    viewport_size.set(get_viewport_size());
                 }
             },
             onmouseleave: move |_| {
                 is_dragging.set(false);
+                end_paint();
             },
             onmouseup: move |_| {
                 is_dragging.set(false);
+                end_paint();
             },
             onmousemove: move |evt| {
+                if is_painting() {
+                    let client = evt.coordinates().client();
+                    if let (Some(pos), Some(wgpu_state_rc)) = (pointer_to_source(client.x, client.y), wgpu_state_signal.read().clone()) {
+                        let mut wgpu_state = wgpu_state_rc.borrow_mut();
+                        wgpu_state.paint_to(pos);
+                        present(&mut wgpu_state, false);
+                    }
+                }
                 if is_dragging() && wgpu_on() {
                     let (start_x, start_y) = (start_position().0, start_position().1);
                     let dx = evt.coordinates().client().x - start_x;
@@ -192,49 +692,9 @@ pub fn ImageBoard() -> Element {
             },
             ondrop: move |evt| {
                 evt.prevent_default();
-
-                let file_engine = evt.files().unwrap();
-                let file_names = file_engine.files();
-
-                zoom_signal.set(100);
-
-                spawn(async move {
-                    wgpu_on.set(false);
-                    draw_signal.set(false);
-                    ready_signal.set(false);
-                    next_img_signal.set(0);
-                    let mut image_datas = VecDeque::<DynamicImage>::new();
-                    let mut image_datas_base64 = VecDeque::<String>::new();
-                    for file_name in file_names{if let Some(bytes) = file_engine.read_file(&file_name).await {
-                        match load_from_memory(&bytes) {
-                            Ok(img) => {
-                                let max_width = 480;
-                                let resized = img.resize(max_width, u32::MAX, image::imageops::FilterType::Triangle);
-                                let rgb_img = resized.to_rgb8();
-                                let dynamic_rgb = DynamicImage::ImageRgb8(rgb_img);
-                                let mut cursor = Cursor::new(Vec::new());
-                                if let Err(err) = dynamic_rgb.write_to(&mut cursor, image::ImageFormat::Jpeg) {
-                                    println!("Error during formatting: {err:?}");
-                                }
-
-                                let jpg_bytes = cursor.into_inner();
-                                let base64_str = base64_engine.encode(&jpg_bytes);
-
-                                image_datas_base64.push_back(format!("data:image/jpeg;base64,{}", base64_str));
-                                image_datas.push_back(img);
-                            },
-                            Err(err) => {println!("UNSUPPORTED IMAGE FORMAT: {err:?}");}
-                        }
-                    }}
-                    image_size.set((image_datas.front().unwrap().dimensions().0 as f64, image_datas.front().unwrap().dimensions().1 as f64));
-                    let mut img_vec = image_data_q();
-                    img_vec.append(&mut image_datas);
-                    image_data_q.set(img_vec);
-                    let mut img_vec_base64 = image_vector_base64();
-                    img_vec_base64.append(&mut image_datas_base64);
-                    image_vector_base64.set(img_vec_base64);
-                    wgpu_on.set(true);
-                });
+                if let Some(file_engine) = evt.files() {
+                    load_files(file_engine);
+                }
             },
 
             match *wgpu_on.read() {
@@ -242,18 +702,35 @@ pub fn ImageBoard() -> Element {
 
                     rsx!(
                     div { class: "image-inner",
-                        canvas {
-                            id: "image-board",
-                            draggable: false,
-                            width: format!("{}px",image_size().0),
-                            height: format!("{}px",image_size().1),
+                        div { class: "canvas-stack",
                             style: format!(
                                 "transform: translate({}px, {}px) scale({}); transform-origin: 0px 0px;",
                                 translation().0,
                                 translation().1,
                                 zoom_signal() as f64 / 100.0
                             ),
-                        },
+                            if let Some(preview) = cpu_preview() {
+                                img {
+                                    id: "image-board",
+                                    onmounted: move |evt| board_mount.set(Some(evt.data())),
+                                    draggable: false,
+                                    src: preview,
+                                    width: format!("{}px",image_size().0),
+                                    height: format!("{}px",image_size().1),
+                                }
+                            } else {
+                                canvas {
+                                    id: "image-board",
+                                    onmounted: move |evt| board_mount.set(Some(evt.data())),
+                                    draggable: false,
+                                    width: format!("{}px",image_size().0),
+                                    height: format!("{}px",image_size().1),
+                                }
+                            }
+                            if crop_visible() {
+                                CropOverlay {}
+                            }
+                        }
                     }
                 )
                 },
@@ -0,0 +1,109 @@
+use crate::components::draggable_panel::DraggablePanel;
+use crate::state::adjustments::{AdjustmentKind, AdjustmentStack};
+use crate::state::app_state::{AdjustmentState, HistoryState, ImageVec};
+use dioxus::prelude::*;
+
+// adjustment types offered by the "Add" buttons: (type name, button label)
+const ADDABLE_LAYERS: [(&str, &str); 2] = [("hsv", "HSV"), ("light", "Light")];
+
+#[component]
+pub fn LayersPanel() -> Element {
+    let adjustments = use_context::<AdjustmentState>();
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+    let history = use_context::<HistoryState>();
+    let mut preset_text = use_signal(|| String::new());
+    let mut preset_error = use_signal(|| None::<String>);
+
+    let stack = adjustments.stack(curr_index());
+    let edit = move |coalesce: Option<&'static str>, f: Box<dyn FnOnce(&mut AdjustmentStack)>| {
+        adjustments.edit(curr_index(), history, coalesce, f);
+    };
+
+    rsx! {
+        DraggablePanel {
+            title: String::from("Adjustment Layers"),
+            PanelContent:
+                rsx! {
+                    div { class: "panel-button-row",
+                        for (name, label) in ADDABLE_LAYERS {
+                            button { class: "btn",
+                                onclick: move |_| edit(None, Box::new(move |stack| {
+                                    if let Some(kind) = AdjustmentKind::default_for(name) {
+                                        stack.push(kind);
+                                    }
+                                })),
+                                "+ {label}"
+                            }
+                        }
+                    }
+                    if stack.layers.is_empty() {
+                        p { class: "panel-hint", "No adjustment layers" }
+                    }
+                    // listed top first, the order they are drawn over each other
+                    for layer in stack.layers.iter().rev().copied() {
+                        div { class: "layer-row", key: "{layer.id}",
+                            button { class: if layer.enabled { "btn on" } else { "btn" },
+                                onclick: move |_| edit(None, Box::new(move |stack| {
+                                    if let Some(l) = stack.layer_mut(layer.id) {
+                                        l.enabled = !l.enabled;
+                                    }
+                                })),
+                                if layer.enabled { "On" } else { "Off" }
+                            }
+                            p { class: "layer-name", "{layer.kind.label()}" }
+                            input {
+                                class: "panel-slider layer-opacity",
+                                type: "range",
+                                min: 0.0,
+                                value: "{layer.opacity}",
+                                max: 1.0,
+                                step: 0.01,
+                                oninput: move |e| {
+                                    if let Ok(parsed) = e.value().parse::<f32>() {
+                                        edit(Some("layer-opacity"), Box::new(move |stack| {
+                                            if let Some(l) = stack.layer_mut(layer.id) {
+                                                l.opacity = parsed;
+                                            }
+                                        }));
+                                    }
+                                },
+                                onchange: move |_| history.seal(curr_index()),
+                            }
+                            p { class: "slider-progress", "{layer.opacity * 100.0:.0}%" }
+                            button { class: "btn", onclick: move |_| edit(None, Box::new(move |stack| stack.move_up(layer.id))), "▲" }
+                            button { class: "btn", onclick: move |_| edit(None, Box::new(move |stack| stack.move_down(layer.id))), "▼" }
+                            button { class: "btn", onclick: move |_| edit(None, Box::new(move |stack| stack.remove(layer.id))), "✕" }
+                        }
+                    }
+                    div { class: "panel-button-row",
+                        button { class: "btn",
+                            onclick: move |_| {
+                                preset_text.set(adjustments.stack(curr_index()).serialize());
+                                preset_error.set(None);
+                            },
+                            "Export"
+                        }
+                        button { class: "btn",
+                            onclick: move |_| {
+                                match AdjustmentStack::deserialize(&preset_text()) {
+                                    Ok(loaded) => {
+                                        edit(None, Box::new(move |stack| *stack = loaded));
+                                        preset_error.set(None);
+                                    }
+                                    Err(err) => preset_error.set(Some(err)),
+                                }
+                            },
+                            "Import"
+                        }
+                    }
+                    textarea { class: "panel-textarea",
+                        value: "{preset_text}",
+                        oninput: move |e| preset_text.set(e.value()),
+                    }
+                    if let Some(err) = preset_error() {
+                        p { class: "panel-hint", "{err}" }
+                    }
+                }
+        }
+    }
+}
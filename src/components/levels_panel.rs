@@ -0,0 +1,137 @@
+use crate::components::draggable_panel::DraggablePanel;
+use crate::state::app_state::{HistoryState, ImageVec, LevelsState};
+use crate::state::curves::CurveChannel;
+use crate::state::levels::{ChannelLevels, Eyedropper};
+use dioxus::prelude::*;
+
+// (label, coalescing key) of each slider; levels are shown as 0-255, gamma as is
+const LEVEL_SLIDERS: [(&str, &str); 5] = [
+    ("IN BLACK", "levels-in-black"),
+    ("GAMMA", "levels-gamma"),
+    ("IN WHITE", "levels-in-white"),
+    ("OUT BLACK", "levels-out-black"),
+    ("OUT WHITE", "levels-out-white"),
+];
+const GAMMA_SLOT: usize = 1;
+
+fn level_value(levels: &ChannelLevels, slot: usize) -> f32 {
+    match slot {
+        0 => levels.in_black,
+        1 => levels.gamma,
+        2 => levels.in_white,
+        3 => levels.out_black,
+        _ => levels.out_white,
+    }
+}
+
+// the input black point always stays below the input white point
+fn set_level_value(levels: &mut ChannelLevels, slot: usize, value: f32) {
+    let step = 1.0 / 255.0;
+    match slot {
+        0 => levels.in_black = value.min(levels.in_white - step).max(0.0),
+        1 => levels.gamma = value,
+        2 => levels.in_white = value.max(levels.in_black + step).min(1.0),
+        3 => levels.out_black = value,
+        _ => levels.out_white = value,
+    }
+}
+
+#[component]
+pub fn LevelsPanel() -> Element {
+    let levels_state = use_context::<LevelsState>();
+    let mut eyedropper = levels_state.eyedropper;
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+    let history = use_context::<HistoryState>();
+    let mut channel = use_signal(|| CurveChannel::Master);
+
+    // closing the panel disarms a pending eyedropper
+    use_drop(move || eyedropper.set(None));
+
+    let current = *levels_state.levels(curr_index()).channel(channel());
+
+    let set_level = move |slot: usize, value: f32, key: &'static str| {
+        levels_state.edit(curr_index(), history, Some(key), |levels| {
+            set_level_value(levels.channel_mut(channel()), slot, value);
+        });
+    };
+
+    let channel_class = move |c: CurveChannel| if channel() == c { "btn on" } else { "btn" };
+    let picker_class = move |e: Eyedropper| if eyedropper() == Some(e) { "btn on" } else { "btn" };
+    let mut toggle_picker = move |e: Eyedropper| {
+        eyedropper.set(if eyedropper() == Some(e) { None } else { Some(e) });
+    };
+
+    rsx! {
+        DraggablePanel {
+            title: String::from("Levels"),
+            PanelContent:
+                rsx! {
+                    div { class: "panel-button-row",
+                        for c in CurveChannel::ALL {
+                            button { class: channel_class(c), onclick: move |_| channel.set(c), "{c.label()}" }
+                        }
+                    }
+                    for (slot, (label, key)) in LEVEL_SLIDERS.into_iter().enumerate() {
+                        div { class: "panel-slider-container",
+                            p { "{label}" },
+                            if slot == GAMMA_SLOT {
+                                input {
+                                    class: "panel-slider",
+                                    type: "range",
+                                    min: 0.1,
+                                    value: "{current.gamma}",
+                                    max: 5.0,
+                                    step: 0.01,
+                                    oninput: move |e| {
+                                        if let Ok(parsed) = e.value().parse::<f32>() {
+                                            set_level(slot, parsed, key);
+                                        }
+                                    },
+                                    onchange: move |_| history.seal(curr_index()),
+                                }
+                                p { class: "slider-progress", "{current.gamma:.2}" }
+                            } else {
+                                input {
+                                    class: "panel-slider",
+                                    type: "range",
+                                    min: 0,
+                                    value: "{(level_value(&current, slot) * 255.0).round()}",
+                                    max: 255,
+                                    step: 1,
+                                    oninput: move |e| {
+                                        if let Ok(parsed) = e.value().parse::<f32>() {
+                                            set_level(slot, parsed / 255.0, key);
+                                        }
+                                    },
+                                    onchange: move |_| history.seal(curr_index()),
+                                }
+                                p { class: "slider-progress", "{(level_value(&current, slot) * 255.0).round()}" }
+                            }
+                        }
+                    }
+                    p { class: "panel-hint", "Pick a point, then click on the image" }
+                    div { class: "panel-button-row",
+                        button { class: picker_class(Eyedropper::Black), onclick: move |_| toggle_picker(Eyedropper::Black), "Black point" }
+                        button { class: picker_class(Eyedropper::Gray), onclick: move |_| toggle_picker(Eyedropper::Gray), "Gray point" }
+                        button { class: picker_class(Eyedropper::White), onclick: move |_| toggle_picker(Eyedropper::White), "White point" }
+                    }
+                    div { class: "panel-button-row",
+                        button { class: "btn",
+                            onclick: move |_| {
+                                levels_state.edit(curr_index(), history, None, |levels| {
+                                    *levels.channel_mut(channel()) = ChannelLevels::default();
+                                });
+                            },
+                            "Reset {channel().label()}"
+                        }
+                        button { class: "btn",
+                            onclick: move |_| {
+                                levels_state.edit(curr_index(), history, None, |levels| *levels = Default::default());
+                            },
+                            "Reset all"
+                        }
+                    }
+                }
+        }
+    }
+}
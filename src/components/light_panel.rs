@@ -0,0 +1,99 @@
+use crate::components::draggable_panel::DraggablePanel;
+use crate::state::adjustments::AdjustmentKind;
+use crate::state::app_state::{AdjustmentState, HistoryState, ImageVec};
+use dioxus::prelude::*;
+
+// (label, coalescing key, min, max, step) of each slider, in the order of `light_values`
+const LIGHT_SLIDERS: [(&str, &str, f32, f32, f32); 5] = [
+    ("EXPOSURE", "exposure", -5.0, 5.0, 0.01),
+    ("BRIGHTNESS", "brightness", -1.0, 1.0, 0.001),
+    ("CONTRAST", "contrast", -1.0, 1.0, 0.001),
+    ("PIVOT", "pivot", 0.0, 1.0, 0.001),
+    ("GAMMA", "gamma", 0.1, 5.0, 0.01),
+];
+
+fn light_values(kind: Option<AdjustmentKind>) -> [f32; 5] {
+    match kind.or(AdjustmentKind::default_for("light")) {
+        Some(AdjustmentKind::Light {
+            exposure,
+            brightness,
+            contrast,
+            pivot,
+            gamma,
+        }) => [exposure, brightness, contrast, pivot, gamma],
+        _ => [0.0, 0.0, 0.0, 0.5, 1.0],
+    }
+}
+
+#[component]
+pub fn LightPanel() -> Element {
+    let adjustments = use_context::<AdjustmentState>();
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+    let history = use_context::<HistoryState>();
+
+    // the panel edits the topmost Light layer of the current image
+    let values = light_values(
+        adjustments
+            .stack(curr_index())
+            .find("light")
+            .map(|layer| layer.kind),
+    );
+
+    let set_light = move |slot: usize, parsed: f32, key: &'static str| {
+        let mut after = values;
+        after[slot] = parsed;
+        adjustments.edit(curr_index(), history, Some(key), |stack| {
+            if let Some(layer) = stack.find_or_push("light") {
+                layer.kind = AdjustmentKind::Light {
+                    exposure: after[0],
+                    brightness: after[1],
+                    contrast: after[2],
+                    pivot: after[3],
+                    gamma: after[4],
+                };
+            }
+        });
+    };
+
+    rsx! {
+        DraggablePanel {
+            title: String::from("Light"),
+            PanelContent:
+                rsx! {
+                    for (slot, (label, key, min, max, step)) in LIGHT_SLIDERS.into_iter().enumerate() {
+                        div { class: "panel-slider-container",
+                            p { "{label}" },
+                            input {
+                                class: "panel-slider",
+                                type: "range",
+                                min: min,
+                                value: "{values[slot]}",
+                                max: max,
+                                step: step,
+                                oninput: move |e| {
+                                    if let Ok(parsed) = e.value().parse::<f32>() {
+                                        set_light(slot, parsed, key);
+                                    }
+                                },
+                                onchange: move |_| history.seal(curr_index()),
+                            }
+                            p { class: "slider-progress", "{values[slot]:.2}" }
+                        }
+                    }
+                    div { class: "panel-button-row",
+                        button { class: "btn",
+                            onclick: move |_| {
+                                adjustments.edit(curr_index(), history, None, |stack| {
+                                    let id = stack.find("light").map(|layer| layer.id);
+                                    if let Some(layer) = id.and_then(|id| stack.layer_mut(id)) {
+                                        layer.kind = AdjustmentKind::default_for("light").unwrap();
+                                    }
+                                });
+                            },
+                            "Reset"
+                        }
+                    }
+                }
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use crate::{
     app_router::Route,
-    state::app_state::{SideBarVisibility, WGPUSignal},
+    state::app_state::{AdjustmentState, BatchState, CurvesState, ExportState, FilterState, HistogramState, HistoryState, ImageVec, LevelsState, SideBarVisibility, TextureCacheState},
+    state::history::HistoryAction,
+    utils::platform::use_shortcut_keys,
 };
+use dioxus::html::HasFileData;
 use dioxus::prelude::*;
 
 #[component]
@@ -10,19 +13,87 @@ pub fn MenuBar() -> Element {
     let mut toggle_signal = use_context::<SideBarVisibility>().state;
     let toggle = move |_| toggle_signal.set(!curr_state);
 
-    let curr_save = *use_context::<WGPUSignal>().save_signal.read();
-    let mut saver_signal = use_context::<WGPUSignal>().save_signal;
-    let saver = move |_| {
-        saver_signal.set(curr_save + 1);
-    };
+    let mut export_visible = use_context::<ExportState>().panel_visible;
+    let mut import = use_context::<ImageVec>().import;
+
+    let mut history_pending = use_context::<HistoryState>().pending;
+    let mut history_limit = use_context::<HistoryState>().limit;
+    let mut history_stacks = use_context::<HistoryState>().stacks;
+    let mut layers_visible = use_context::<AdjustmentState>().layers_panel_visible;
+    let mut curves_visible = use_context::<CurvesState>().panel_visible;
+    let mut levels_visible = use_context::<LevelsState>().panel_visible;
+    let mut histogram_visible = use_context::<HistogramState>().panel_visible;
+    let mut filters_visible = use_context::<FilterState>().panel_visible;
+    let mut batch_visible = use_context::<BatchState>().panel_visible;
+    let mut texture_budget = use_context::<TextureCacheState>().budget_mb;
+
+    // Ctrl+Z / Ctrl+Shift+Z (and Ctrl+Y) step through the edit history
+    use_shortcut_keys(&["z", "y"], move |key, shift| match key {
+        "z" if shift => history_pending.set(Some(HistoryAction::Redo)),
+        "z" => history_pending.set(Some(HistoryAction::Undo)),
+        _ => history_pending.set(Some(HistoryAction::Redo)),
+    });
 
     rsx! {
         div { class: "menubar-container",
             div { class: "dropdown-button-container",
                 button {class: "btn", "File" }
                 div { class: "dropdown-content",
-                    button { class: "btn", "Load" }
-                    button { onclick: saver, class: "btn", "Save as" }
+                    // the label opens the file picker of the hidden input, a native dialog on the desktop
+                    label { class: "btn",
+                        "Load"
+                        input {
+                            r#type: "file",
+                            accept: "image/*",
+                            multiple: true,
+                            style: "display: none;",
+                            onchange: move |evt| {
+                                if let Some(file_engine) = evt.files() {
+                                    import.set(Some(file_engine));
+                                }
+                            },
+                        }
+                    }
+                    button { onclick: move |_| export_visible.set(!export_visible()),
+                        class: if export_visible() { "btn on" } else { "btn" }, "Save as" }
+                    button { onclick: move |_| batch_visible.set(!batch_visible()),
+                        class: if batch_visible() { "btn on" } else { "btn" }, "Batch apply" }
+                }
+            }
+            div { class: "dropdown-button-container",
+                button { class: "btn", "Edit" }
+                div { class: "dropdown-content",
+                    button { onclick: move |_| history_pending.set(Some(HistoryAction::Undo)),
+                        class: "btn", "Undo (Ctrl+Z)" }
+                    button { onclick: move |_| history_pending.set(Some(HistoryAction::Redo)),
+                        class: "btn", "Redo (Ctrl+Shift+Z)" }
+                    div { class: "panel-slider-container",
+                        p { "History depth" }
+                        input { class: "panel-number-input", type: "number", min: 1, value: "{history_limit}",
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<usize>() {
+                                    let limit = parsed.max(1);
+                                    history_limit.set(limit);
+                                    for history in history_stacks.write().values_mut() {
+                                        history.truncate(limit);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            div { class: "dropdown-button-container",
+                button { class: "btn", "Adjust" }
+                div { class: "dropdown-content",
+                    button { onclick: move |_| layers_visible.set(!layers_visible()),
+                        class: if layers_visible() { "btn on" } else { "btn" }, "Adjustment Layers" }
+                    button { onclick: move |_| curves_visible.set(!curves_visible()),
+                        class: if curves_visible() { "btn on" } else { "btn" }, "Curves" }
+                    button { onclick: move |_| levels_visible.set(!levels_visible()),
+                        class: if levels_visible() { "btn on" } else { "btn" }, "Levels" }
+                    button { onclick: move |_| filters_visible.set(!filters_visible()),
+                        class: if filters_visible() { "btn on" } else { "btn" }, "Filters" }
                 }
             }
             div { class: "dropdown-button-container",
@@ -30,7 +101,18 @@ pub fn MenuBar() -> Element {
                 div { class: "dropdown-content",
                     button { onclick: toggle,
                         class: "btn", "Toggle Sidebar" }
-                    button { class: "btn", "Click me!" }
+                    button { onclick: move |_| histogram_visible.set(!histogram_visible()),
+                        class: if histogram_visible() { "btn on" } else { "btn" }, "Histogram" }
+                    div { class: "panel-slider-container",
+                        p { "GPU memory (MB)" }
+                        input { class: "panel-number-input", type: "number", min: 64, value: "{texture_budget}",
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<u64>() {
+                                    texture_budget.set(parsed.max(64));
+                                }
+                            }
+                        }
+                    }
                     button { class: "btn", "Click me!" }
                 }
             }
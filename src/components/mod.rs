@@ -4,3 +4,15 @@ pub mod image_board;
 pub mod footer;
 pub mod gallery;
 pub mod draggable_panel;
+pub mod crop_panel;
+pub mod resize_panel;
+pub mod brush_panel;
+pub mod layers_panel;
+pub mod light_panel;
+pub mod curves_panel;
+pub mod levels_panel;
+pub mod histogram_panel;
+pub mod filters_panel;
+pub mod batch_panel;
+pub mod export_panel;
+pub mod transform_panel;
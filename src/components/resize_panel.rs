@@ -0,0 +1,251 @@
+use crate::components::draggable_panel::DraggablePanel;
+use crate::state::app_state::{CropState, HistoryState, ImageVec, ResizeState, TransformState, WGPUSignal};
+use crate::state::customlib::CropRect;
+use crate::state::history::EditCommand;
+use crate::utils::utils::{clamp_crop, make_thumbnail_base64};
+use dioxus::prelude::*;
+use image::GenericImageView;
+use image::imageops::FilterType;
+
+const FILTERS: [(&str, FilterType); 5] = [
+    ("Nearest", FilterType::Nearest),
+    ("Triangle", FilterType::Triangle),
+    ("CatmullRom", FilterType::CatmullRom),
+    ("Gaussian", FilterType::Gaussian),
+    ("Lanczos3", FilterType::Lanczos3),
+];
+
+// larger targets allocate buffers the tab cannot hold, 256 megapixels is about 1GB as rgba
+const MAX_SIDE: u32 = 32768;
+const MAX_PIXELS: u64 = 256 * 1024 * 1024;
+
+// why a typed target size cannot be applied, if it cannot
+fn check_target((width, height): (u32, u32)) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err(String::from("Width and height must be at least 1 pixel"));
+    }
+    if width > MAX_SIDE || height > MAX_SIDE {
+        return Err(format!("Sides are limited to {MAX_SIDE} pixels"));
+    }
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(format!("Images are limited to {} megapixels", MAX_PIXELS / (1024 * 1024)));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ResizeUnit {
+    Pixels,
+    Percent,
+}
+
+#[component]
+pub fn ResizePanel() -> Element {
+    let mut panel_visible = use_context::<ResizeState>().panel_visible;
+    let mut filter = use_context::<ResizeState>().filter;
+    let mut image_vec = use_context::<ImageVec>().vector;
+    let mut image_vec_base64 = use_context::<ImageVec>().base64_vector;
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+    let mut crops = use_context::<CropState>().crops;
+    let transforms = use_context::<TransformState>();
+    let mut reload_signal = use_context::<WGPUSignal>().reload_signal;
+    let wgpu_state = use_context::<WGPUSignal>().state;
+    let history = use_context::<HistoryState>();
+
+    let source_size = move || {
+        image_vec
+            .read()
+            .get(curr_index())
+            .map(|img| img.dimensions())
+            .unwrap_or((1, 1))
+    };
+
+    let mut unit = use_signal(|| ResizeUnit::Pixels);
+    let mut lock_aspect = use_signal(|| true);
+    let mut target_w = use_signal(move || source_size().0 as f64);
+    let mut target_h = use_signal(move || source_size().1 as f64);
+
+    // target size in pixels, independent of the displayed unit
+    let target_pixels = move || {
+        let (src_w, src_h) = source_size();
+        match unit() {
+            ResizeUnit::Pixels => (target_w().round() as u32, target_h().round() as u32),
+            ResizeUnit::Percent => (
+                (src_w as f64 * target_w() / 100.0).round() as u32,
+                (src_h as f64 * target_h() / 100.0).round() as u32,
+            ),
+        }
+    };
+
+    let mut set_unit = move |new_unit: ResizeUnit| {
+        if unit() == new_unit {
+            return;
+        }
+        let (src_w, src_h) = source_size();
+        let (w, h) = target_pixels();
+        match new_unit {
+            ResizeUnit::Pixels => {
+                target_w.set(w as f64);
+                target_h.set(h as f64);
+            }
+            ResizeUnit::Percent => {
+                target_w.set(w as f64 / src_w as f64 * 100.0);
+                target_h.set(h as f64 / src_h as f64 * 100.0);
+            }
+        }
+        unit.set(new_unit);
+    };
+
+    let apply = move |_| {
+        let (src_w, src_h) = source_size();
+        let (new_w, new_h) = target_pixels();
+        if check_target((new_w, new_h)).is_err() || (new_w, new_h) == (src_w, src_h) {
+            return;
+        }
+        let index = curr_index();
+        let Some(original) = image_vec.read().get(index).cloned() else {
+            return;
+        };
+        let resized = original.resize_exact(new_w, new_h, filter());
+        let crop_before = crops.read().get(&index).copied();
+
+        // keep an applied crop on the same region of the resized image, in the rotated frame
+        let transform = transforms.transform(index);
+        let (old_w, old_h) = transform.oriented_dimensions((src_w, src_h));
+        let (frame_w, frame_h) = transform.oriented_dimensions((new_w, new_h));
+        let scaled_crop = crop_before.map(|rect| {
+            let sx = frame_w as f64 / old_w as f64;
+            let sy = frame_h as f64 / old_h as f64;
+            clamp_crop(
+                CropRect {
+                    x: (rect.x as f64 * sx).round() as u32,
+                    y: (rect.y as f64 * sy).round() as u32,
+                    width: (rect.width as f64 * sx).round() as u32,
+                    height: (rect.height as f64 * sy).round() as u32,
+                },
+                (frame_w, frame_h),
+            )
+        });
+        if let Some(rect) = scaled_crop {
+            crops.write().insert(index, rect);
+        }
+
+        // the paint layer is resampled once here, undo and redo put back the stored layers
+        let mut paint_before = None;
+        let mut paint_after = None;
+        if let Some(state_rc) = &*wgpu_state.read() {
+            let mut state = state_rc.borrow_mut();
+            paint_before = state.paint_layer(index).cloned();
+            paint_after = paint_before
+                .as_ref()
+                .map(|layer| image::imageops::resize(layer, new_w, new_h, filter()));
+            state.set_paint_layer(index, paint_after.clone());
+        }
+
+        history.record(
+            index,
+            EditCommand::Resize {
+                source: original,
+                size: (new_w, new_h),
+                filter: filter(),
+                restore: false,
+                crop_before,
+                crop_after: scaled_crop,
+                paint_before,
+                paint_after,
+            },
+            None,
+        );
+
+        if let Some(thumbnail) = image_vec_base64.write().get_mut(index) {
+            *thumbnail = make_thumbnail_base64(&resized);
+        }
+        if let Some(slot) = image_vec.write().get_mut(index) {
+            *slot = resized;
+        }
+        reload_signal.set(Some(index));
+        panel_visible.set(false);
+    };
+
+    let (px_w, px_h) = target_pixels();
+    let target_error = check_target((px_w, px_h)).err();
+    let (step, shown_w, shown_h) = match unit() {
+        ResizeUnit::Pixels => ("1", format!("{:.0}", target_w()), format!("{:.0}", target_h())),
+        ResizeUnit::Percent => ("0.1", format!("{:.1}", target_w()), format!("{:.1}", target_h())),
+    };
+
+    rsx! {
+        DraggablePanel {
+            title: String::from("Resize"),
+            PanelContent:
+                rsx! {
+                    div { class: "panel-button-row",
+                        button { class: if unit() == ResizeUnit::Pixels { "btn on" } else { "btn" },
+                            onclick: move |_| set_unit(ResizeUnit::Pixels),
+                            "px"
+                        }
+                        button { class: if unit() == ResizeUnit::Percent { "btn on" } else { "btn" },
+                            onclick: move |_| set_unit(ResizeUnit::Percent),
+                            "%"
+                        }
+                        button { class: if lock_aspect() { "btn on" } else { "btn" },
+                            onclick: move |_| lock_aspect.set(!lock_aspect()),
+                            "Lock aspect"
+                        }
+                    }
+                    div { class: "panel-slider-container",
+                        p { "W" },
+                        input { class: "panel-number-input", type: "number", min: 1, step: step, value: "{shown_w}",
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<f64>() {
+                                    if lock_aspect() {
+                                        let (src_w, src_h) = source_size();
+                                        target_h.set(match unit() {
+                                            ResizeUnit::Pixels => parsed * src_h as f64 / src_w as f64,
+                                            ResizeUnit::Percent => parsed,
+                                        });
+                                    }
+                                    target_w.set(parsed);
+                                }
+                            }
+                        }
+                        p { "H" },
+                        input { class: "panel-number-input", type: "number", min: 1, step: step, value: "{shown_h}",
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<f64>() {
+                                    if lock_aspect() {
+                                        let (src_w, src_h) = source_size();
+                                        target_w.set(match unit() {
+                                            ResizeUnit::Pixels => parsed * src_w as f64 / src_h as f64,
+                                            ResizeUnit::Percent => parsed,
+                                        });
+                                    }
+                                    target_h.set(parsed);
+                                }
+                            }
+                        }
+                        p { class: "slider-progress", "{px_w}x{px_h}" }
+                    }
+                    if let Some(err) = &target_error {
+                        p { class: "panel-hint", "{err}" }
+                    }
+                    div { class: "panel-slider-container",
+                        p { "FILTER" },
+                        select { class: "panel-select",
+                            onchange: move |e| {
+                                if let Some((_, f)) = FILTERS.iter().find(|(name, _)| *name == e.value()) {
+                                    filter.set(*f);
+                                }
+                            },
+                            for (name, f) in FILTERS {
+                                option { value: name, selected: filter() == f, "{name}" }
+                            }
+                        }
+                    }
+                    div { class: "panel-button-row",
+                        button { class: "btn", disabled: target_error.is_some(), onclick: apply, "Apply" }
+                    }
+                }
+        }
+    }
+}
@@ -1,25 +1,53 @@
+use crate::components::batch_panel::BatchPanel;
+use crate::components::brush_panel::BrushPanel;
+use crate::components::crop_panel::CropPanel;
+use crate::components::curves_panel::CurvesPanel;
 use crate::components::draggable_panel::DraggablePanel;
-use crate::state::app_state::{HSVState, TestPanelVisibility, SideBarVisibility, DragSignal};
+use crate::components::export_panel::ExportPanel;
+use crate::components::filters_panel::FiltersPanel;
+use crate::components::histogram_panel::HistogramPanel;
+use crate::components::layers_panel::LayersPanel;
+use crate::components::levels_panel::LevelsPanel;
+use crate::components::light_panel::LightPanel;
+use crate::components::resize_panel::ResizePanel;
+use crate::components::transform_panel::TransformPanel;
+use crate::state::adjustments::AdjustmentKind;
+use crate::state::app_state::{AdjustmentState, BatchState, BrushState, CropState, ExportState, CurvesState, FilterState, HSVState, HistogramState, HistoryState, ImageVec, LevelsState, LightState, ResizeState, SideBarVisibility, DragSignal, TransformState};
 use dioxus::prelude::*;
-use std::rc::Rc;
-use wasm_bindgen::JsCast;
-use wasm_bindgen::prelude::Closure;
-use web_sys::{MouseEvent, console, window};
 
 const ADJUST_BUTTON_SVG: Asset = asset!("/assets/adjust_button.svg");
 const CROP_BUTTON_SVG: Asset = asset!("/assets/crop_button.svg");
 const RESIZE_BUTTON_SVG: Asset = asset!("/assets/resize_button.svg");
+const ROTATE_BUTTON_SVG: Asset = asset!("/assets/rotate_button.svg");
 const BRUSH_BUTTON_SVG: Asset = asset!("/assets/brush_button.svg");
 const DRAG_BUTTON_SVG: Asset = asset!("/assets/drag_button.svg");
 
 #[component]
 pub fn HSVPanel() -> Element {
-    let mut hue = use_context::<HSVState>().hue;
-    let mut hue_slider_value = use_signal(|| 0.0);
-    let mut sat = use_context::<HSVState>().saturation;
-    let mut sat_slider_value = use_signal(|| 0.0);
-    let mut val = use_context::<HSVState>().value;
-    let mut val_slider_value = use_signal(|| 0.0);
+    let adjustments = use_context::<AdjustmentState>();
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+    let history = use_context::<HistoryState>();
+
+    // the panel edits the topmost HSV layer of the current image
+    let (hue, sat, val) = match adjustments.stack(curr_index()).find("hsv").map(|layer| layer.kind) {
+        Some(AdjustmentKind::Hsv { hue, saturation, value }) => (hue, saturation, value),
+        _ => (0.0, 0.0, 0.0),
+    };
+    let hue_slider_value = hue / std::f32::consts::PI;
+    let sat_slider_value = sat;
+    let val_slider_value = val;
+
+    let set_hsv = move |after: [f32; 3], key: &'static str| {
+        adjustments.edit(curr_index(), history, Some(key), |stack| {
+            if let Some(layer) = stack.find_or_push("hsv") {
+                layer.kind = AdjustmentKind::Hsv {
+                    hue: after[0],
+                    saturation: after[1],
+                    value: after[2],
+                };
+            }
+        });
+    };
 
     rsx! {
         DraggablePanel {
@@ -37,10 +65,10 @@ pub fn HSVPanel() -> Element {
                             step: 0.001,
                             oninput: move |e|{
                                 if let Ok(parsed) = e.value().parse::<f32>() {
-                                    hue_slider_value.set(parsed);
-                                    hue.set(parsed * std::f32::consts::PI);
+                                    set_hsv([parsed * std::f32::consts::PI, sat, val], "hue");
                                 }
                             },
+                            onchange: move |_| history.seal(curr_index()),
                         }
                         p { class: "slider-progress", "{hue_slider_value * 100.0:.2}" }
                     },
@@ -55,10 +83,10 @@ pub fn HSVPanel() -> Element {
                             step: 0.001,
                             oninput: move |e| {
                                 if let Ok(parsed) = e.value().parse::<f32>() {
-                                    sat_slider_value.set(parsed);
-                                    sat.set(parsed);
+                                    set_hsv([hue, parsed, val], "saturation");
                                 }
                             },
+                            onchange: move |_| history.seal(curr_index()),
                         }
                         p { class: "slider-progress", "{sat_slider_value}" }
                     },
@@ -73,10 +101,10 @@ pub fn HSVPanel() -> Element {
                             step: 0.001,
                             oninput: move |e| {
                                 if let Ok(parsed) = e.value().parse::<f32>() {
-                                    val_slider_value.set(parsed);
-                                    val.set(parsed);
+                                    set_hsv([hue, sat, parsed], "value");
                                 }
                             },
+                            onchange: move |_| history.seal(curr_index()),
                         }
                         p { class: "slider-progress", "{val_slider_value}" }
                     }
@@ -85,19 +113,6 @@ pub fn HSVPanel() -> Element {
     }
 }
 
-#[component]
-fn TestPanel() -> Element {
-    rsx! {
-        DraggablePanel {
-            title: String::from("Crop"),
-            PanelContent:
-                rsx! {
-                    div { "PLACEHOLDER" }
-                }
-        }
-    }
-}
-
 #[component]
 pub fn SideBar() -> Element {
     let is_visible = *use_context::<SideBarVisibility>().state.read();
@@ -109,7 +124,18 @@ pub fn SideBar() -> Element {
     };
 
     let mut hsv_is_visible = use_context::<HSVState>().panel_visible;
-    let mut test_panel_visibility = use_context::<TestPanelVisibility>().visibility;
+    let mut light_is_visible = use_context::<LightState>().panel_visible;
+    let mut crop_is_visible = use_context::<CropState>().panel_visible;
+    let mut resize_is_visible = use_context::<ResizeState>().panel_visible;
+    let mut transform_is_visible = use_context::<TransformState>().panel_visible;
+    let mut brush_is_visible = use_context::<BrushState>().panel_visible;
+    let layers_is_visible = use_context::<AdjustmentState>().layers_panel_visible;
+    let curves_is_visible = use_context::<CurvesState>().panel_visible;
+    let levels_is_visible = use_context::<LevelsState>().panel_visible;
+    let histogram_is_visible = use_context::<HistogramState>().panel_visible;
+    let filters_is_visible = use_context::<FilterState>().panel_visible;
+    let batch_is_visible = use_context::<BatchState>().panel_visible;
+    let export_is_visible = use_context::<ExportState>().panel_visible;
 
     rsx! {
         div { class: "sidebar-container", style: sidebar_style,
@@ -122,22 +148,46 @@ pub fn SideBar() -> Element {
                 }
                 span { class: "button-text", "HSV" }
             }
-            button { class: if test_panel_visibility() { "btn on" } else { "btn" },
+            button { class: if light_is_visible() { "btn on" } else { "btn" },
+                onclick: move |_| {
+                    light_is_visible.set(!light_is_visible());
+                },
+                img { class: "button-svg-container",
+                    src: ADJUST_BUTTON_SVG,
+                }
+                span { class: "button-text", "Light" }
+            }
+            button { class: if crop_is_visible() { "btn on" } else { "btn" },
                 onclick: move |_| {
-                    test_panel_visibility.set(!test_panel_visibility());
+                    crop_is_visible.set(!crop_is_visible());
                 },
                 img { class: "button-svg-container",
                     src: CROP_BUTTON_SVG
                 }
                 span { class: "button-text", "Crop" }
             }
-            button { class: "btn",
+            button { class: if resize_is_visible() { "btn on" } else { "btn" },
+                onclick: move |_| {
+                    resize_is_visible.set(!resize_is_visible());
+                },
                 img { class: "button-svg-container",
                     src: RESIZE_BUTTON_SVG,
                 }
                 span { class: "button-text", "Resize" }
             }
-            button { class: "btn",
+            button { class: if transform_is_visible() { "btn on" } else { "btn" },
+                onclick: move |_| {
+                    transform_is_visible.set(!transform_is_visible());
+                },
+                img { class: "button-svg-container",
+                    src: ROTATE_BUTTON_SVG,
+                }
+                span { class: "button-text", "Rotate" }
+            }
+            button { class: if brush_is_visible() { "btn on" } else { "btn" },
+                onclick: move |_| {
+                    brush_is_visible.set(!brush_is_visible());
+                },
                 img { class: "button-svg-container",
                     src: BRUSH_BUTTON_SVG,
                 }
@@ -156,8 +206,41 @@ pub fn SideBar() -> Element {
         if hsv_is_visible() {
             HSVPanel {  }
         }
-        if test_panel_visibility() {
-            TestPanel {  }
+        if light_is_visible() {
+            LightPanel {  }
+        }
+        if crop_is_visible() {
+            CropPanel {  }
+        }
+        if resize_is_visible() {
+            ResizePanel {  }
+        }
+        if transform_is_visible() {
+            TransformPanel {  }
+        }
+        if brush_is_visible() {
+            BrushPanel {  }
+        }
+        if layers_is_visible() {
+            LayersPanel {  }
+        }
+        if curves_is_visible() {
+            CurvesPanel {  }
+        }
+        if levels_is_visible() {
+            LevelsPanel {  }
+        }
+        if histogram_is_visible() {
+            HistogramPanel {  }
+        }
+        if filters_is_visible() {
+            FiltersPanel {  }
+        }
+        if batch_is_visible() {
+            BatchPanel {  }
+        }
+        if export_is_visible() {
+            ExportPanel {  }
         }
     }
 }
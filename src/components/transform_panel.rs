@@ -0,0 +1,75 @@
+use crate::components::draggable_panel::DraggablePanel;
+use crate::state::app_state::{CropState, HistoryState, ImageVec, TransformState};
+use crate::state::transform::{MAX_STRAIGHTEN, Transform};
+use dioxus::prelude::*;
+use image::GenericImageView;
+
+#[component]
+pub fn TransformPanel() -> Element {
+    let transforms = use_context::<TransformState>();
+    let crops = use_context::<CropState>().crops;
+    let image_vec = use_context::<ImageVec>().vector;
+    let curr_index = use_context::<ImageVec>().curr_image_index;
+    let history = use_context::<HistoryState>();
+
+    let source = move || {
+        image_vec
+            .read()
+            .get(curr_index())
+            .map(|img| img.dimensions())
+            .unwrap_or((1, 1))
+    };
+    let edit = move |coalesce: Option<&'static str>, f: Box<dyn FnOnce(&mut Transform)>| {
+        transforms.edit(curr_index(), source(), crops, history, coalesce, f);
+    };
+
+    let current = transforms.transform(curr_index());
+
+    rsx! {
+        DraggablePanel {
+            title: String::from("Rotate"),
+            PanelContent:
+                rsx! {
+                    div { class: "panel-button-row",
+                        button { class: "btn", onclick: move |_| edit(None, Box::new(|t| t.rotate_counter_clockwise())), "↺ 90°" }
+                        button { class: "btn", onclick: move |_| edit(None, Box::new(|t| t.rotate_clockwise())), "↻ 90°" }
+                        button { class: "btn", onclick: move |_| edit(None, Box::new(|t| t.flip_horizontal())), "Flip ⇆" }
+                        button { class: "btn", onclick: move |_| edit(None, Box::new(|t| t.flip_vertical())), "Flip ⇅" }
+                    }
+                    div { class: "panel-slider-container",
+                        p { "STRAIGHTEN" },
+                        input {
+                            class: "panel-slider",
+                            type: "range",
+                            min: -MAX_STRAIGHTEN,
+                            value: "{current.straighten}",
+                            max: MAX_STRAIGHTEN,
+                            step: 0.1,
+                            oninput: move |e| {
+                                if let Ok(parsed) = e.value().parse::<f32>() {
+                                    edit(Some("straighten"), Box::new(move |t| t.straighten = parsed.clamp(-MAX_STRAIGHTEN, MAX_STRAIGHTEN)));
+                                }
+                            },
+                            onchange: move |_| history.seal(curr_index()),
+                        }
+                        p { class: "slider-progress", "{current.straighten:.1}°" }
+                    }
+                    label { class: "panel-hint",
+                        input { type: "checkbox", checked: current.auto_crop,
+                            onchange: move |e| {
+                                let checked = e.checked();
+                                edit(None, Box::new(move |t| t.auto_crop = checked));
+                            }
+                        }
+                        "Crop to the straightened image"
+                    }
+                    if !current.auto_crop && current.straighten != 0.0 {
+                        p { class: "panel-hint", "Uncovered corners are left transparent." }
+                    }
+                    div { class: "panel-button-row",
+                        button { class: "btn", onclick: move |_| edit(None, Box::new(|t| *t = Transform::default())), "Reset" }
+                    }
+                }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use crate::app_router::Route;
 use crate::components::{
@@ -6,15 +6,27 @@ use crate::components::{
     side_bar::SideBar,
 };
 use crate::state::app_state::{
-    GalleryState, HSVState, ImageVec, ImageZoom, NextImage, SideBarVisibility, WGPUSignal, TestPanelVisibility, DragSignal
+    AdjustmentState, AspectRatio, BatchState, BoardState, BrushState, ExportState, CropState, CurvesState, FilterState, LevelsState, HistoryState, ResizeState, GalleryState, HSVState, HistogramState, LightState, ImageVec, TransformState, ImageZoom, NextImage, SideBarVisibility, TextureCacheState, WGPUSignal, DragSignal
 };
+use crate::state::adjustments::AdjustmentStack;
+use crate::state::brush::BrushSettings;
+use crate::state::curves::{CurveSet, PRESETS_SETTING, builtin_presets, deserialize_presets};
+use crate::state::export::{DEFAULT_NAME_TEMPLATE, ExportFormat};
+use crate::state::metadata::ImageMetadata;
+use crate::state::transform::Transform;
+use crate::state::filters::FilterStack;
+use crate::state::levels::Levels;
+use crate::state::customlib::CropRect;
+use crate::state::history::{DEFAULT_HISTORY_LIMIT, EditHistory, HistoryAction};
+use crate::state::texture_cache::DEFAULT_TEXTURE_BUDGET_MB;
+use crate::utils::platform::{load_setting, log};
 use dioxus::prelude::*;
 use image::DynamicImage;
-use web_sys::{Window, console, window};
+use image::imageops::FilterType;
 
 const MAIN_CSS: Asset = asset!("/assets/main.css");
-pub static GLOBAL_WINDOW_HANDLE: GlobalSignal<Window> =
-    Signal::global(|| window().expect("No global window found"));
+// the desktop build draws the image under a transparent webview
+const DESKTOP_CSS: &str = "html, body { background-color: transparent; } canvas#image-board { background: none; }";
 
 #[component]
 pub fn App() -> Element {
@@ -24,40 +36,130 @@ pub fn App() -> Element {
     let IMG_SCALE_LIMITS: Signal<(i64, i64)> = use_signal(|| (20, 3000));
     let image_vector = use_signal(|| VecDeque::<DynamicImage>::new());
     let image_vector_base64 = use_signal(|| VecDeque::<String>::new());
+    let image_names = use_signal(|| VecDeque::<String>::new());
+    let image_metadata = use_signal(|| VecDeque::<ImageMetadata>::new());
     let image_index = use_signal(|| 0 as usize);
+    let image_import = use_signal(|| None);
+    let board_rect = use_signal(|| None);
     let img_next = use_signal(|| false);
     let img_iter = use_signal(|| 0 as u32);
 
     let wgpu_signal = use_signal(|| false);
+    let wgpu_state = use_signal(|| None);
+    let texture_budget = use_signal(|| DEFAULT_TEXTURE_BUDGET_MB);
+    let texture_used = use_signal(|| 0);
+    let texture_evicted = use_signal(|| Vec::new());
 
     let grid_size = use_signal(|| String::from("medium"));
 
     let dropdown_visible = use_signal(|| false);
+    let gallery_selection = use_signal(|| HashSet::<usize>::new());
 
     let hsv_visible = use_signal(|| false);
-    let hue = use_signal(|| 0 as f32);
-    let saturation = use_signal(|| 0 as f32);
-    let value = use_signal(|| 0 as f32);
-
-    let panel_visibility = use_signal(|| false);
+    let layers_visible = use_signal(|| false);
+    let light_visible = use_signal(|| false);
+    let curves_visible = use_signal(|| false);
+    let image_curves = use_signal(|| HashMap::<usize, CurveSet>::new());
+    // presets saved in an earlier session replace the built-in list
+    let curve_presets = use_signal(|| {
+        load_setting(PRESETS_SETTING)
+            .and_then(|text| match deserialize_presets(&text) {
+                Ok(presets) => Some(presets),
+                Err(err) => {
+                    log(format!("Ignoring saved curve presets: {}", err));
+                    None
+                }
+            })
+            .unwrap_or_else(builtin_presets)
+    });
+    let levels_visible = use_signal(|| false);
+    let image_levels = use_signal(|| HashMap::<usize, Levels>::new());
+    let eyedropper = use_signal(|| None);
+    let histogram_visible = use_signal(|| false);
+    let histogram_data = use_signal(|| None);
+    let histogram_supported = use_signal(|| true);
+    let filters_visible = use_signal(|| false);
+    let filter_stacks = use_signal(|| HashMap::<usize, FilterStack>::new());
+    let adjustment_stacks = use_signal(|| HashMap::<usize, AdjustmentStack>::new());
+    let batch_visible = use_signal(|| false);
+    let batch_run = use_signal(|| false);
+    let batch_items = use_signal(|| Vec::new());
+
+    let crop_visible = use_signal(|| false);
+    let crop_rect = use_signal(|| CropRect::full((0, 0)));
+    let crop_aspect = use_signal(|| AspectRatio::Free);
+    let crops = use_signal(|| HashMap::<usize, CropRect>::new());
+    let transform_visible = use_signal(|| false);
+    let transforms = use_signal(|| HashMap::<usize, Transform>::new());
 
     let save_signal = use_signal(|| 0 as i64);
+    let reload_signal = use_signal(|| None::<usize>);
+    let export_visible = use_signal(|| false);
+    let export_format = use_signal(|| ExportFormat::default());
+    let export_keep_metadata = use_signal(|| true);
+    let export_template = use_signal(|| String::from(DEFAULT_NAME_TEMPLATE));
+    let export_filename = use_signal(|| String::new());
+
+    let resize_visible = use_signal(|| false);
+    let resize_filter = use_signal(|| FilterType::Lanczos3);
+
+    let brush_visible = use_signal(|| false);
+    let brush_settings = use_signal(|| BrushSettings::default());
+
+    let history_stacks = use_signal(|| HashMap::<usize, EditHistory>::new());
+    let history_limit = use_signal(|| DEFAULT_HISTORY_LIMIT);
+    let history_pending = use_signal(|| None::<HistoryAction>);
 
     let can_drag = use_signal(|| false);
 
     use_context_provider(|| DragSignal {
         can_drag,
     });
-    use_context_provider(|| TestPanelVisibility {
-        visibility: panel_visibility,
+    use_context_provider(|| CropState {
+        panel_visible: crop_visible,
+        rect: crop_rect,
+        aspect: crop_aspect,
+        crops,
+    });
+    use_context_provider(|| TransformState {
+        panel_visible: transform_visible,
+        transforms,
     });
     use_context_provider(|| GalleryState {
         grid_size,
         visibility: dropdown_visible,
+        selection: gallery_selection,
     });
     use_context_provider(|| WGPUSignal {
         signal: wgpu_signal,
         save_signal: save_signal,
+        reload_signal,
+        state: wgpu_state,
+    });
+    use_context_provider(|| TextureCacheState {
+        budget_mb: texture_budget,
+        used: texture_used,
+        evicted: texture_evicted,
+    });
+    use_context_provider(|| ExportState {
+        panel_visible: export_visible,
+        format: export_format,
+        name_template: export_template,
+        filename: export_filename,
+        keep_metadata: export_keep_metadata,
+    });
+    use_context_provider(|| ResizeState {
+        panel_visible: resize_visible,
+        filter: resize_filter,
+    });
+    use_context_provider(|| BrushState {
+        panel_visible: brush_visible,
+        settings: brush_settings,
+    });
+    use_context_provider(|| HistoryState {
+        stacks: history_stacks,
+        limit: history_limit,
+        pending: history_pending,
     });
     use_context_provider(|| SideBarVisibility { state: visibility });
     use_context_provider(|| ImageZoom {
@@ -72,17 +174,52 @@ pub fn App() -> Element {
         vector: image_vector,
         curr_image_index: image_index,
         base64_vector: image_vector_base64,
+        names: image_names,
+        metadata: image_metadata,
+        import: image_import,
     });
+    use_context_provider(|| BoardState { rect: board_rect });
     use_context_provider(|| HSVState {
         panel_visible: hsv_visible,
-        hue,
-        saturation,
-        value,
+    });
+    use_context_provider(|| LightState {
+        panel_visible: light_visible,
+    });
+    use_context_provider(|| CurvesState {
+        panel_visible: curves_visible,
+        curves: image_curves,
+        presets: curve_presets,
+    });
+    use_context_provider(|| LevelsState {
+        panel_visible: levels_visible,
+        levels: image_levels,
+        eyedropper,
+    });
+    use_context_provider(|| HistogramState {
+        panel_visible: histogram_visible,
+        data: histogram_data,
+        supported: histogram_supported,
+    });
+    use_context_provider(|| FilterState {
+        panel_visible: filters_visible,
+        stacks: filter_stacks,
+    });
+    use_context_provider(|| BatchState {
+        panel_visible: batch_visible,
+        run_signal: batch_run,
+        items: batch_items,
+    });
+    use_context_provider(|| AdjustmentState {
+        layers_panel_visible: layers_visible,
+        stacks: adjustment_stacks,
     });
 
     rsx! {
 
         document::Stylesheet { rel: "stylesheet", href: MAIN_CSS }
+        if cfg!(not(target_arch = "wasm32")) {
+            document::Style { "{DESKTOP_CSS}" }
+        }
         Router::<Route> {}
 
     }
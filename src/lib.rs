@@ -0,0 +1,7 @@
+pub mod dioxusui;
+pub mod state;
+pub mod components;
+pub mod utils;
+pub mod app_router;
+
+use dioxus::prelude::*;
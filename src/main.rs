@@ -1,15 +1,23 @@
-mod dioxusui;
-mod state;
-mod components;
-mod utils;
-mod app_router;
-
-use dioxusui::App;
-use dioxus::prelude::*;
-use std::any::Any;
-use wgpu::{Features, Limits};
+#[cfg(any(target_arch = "wasm32", feature = "desktop"))]
+use parallel_image_editor_in_rust::dioxusui::App;
 
 fn main() {
     #[cfg(target_arch = "wasm32")]
     dioxus::launch(App);
+
+    // the renderer draws to the window itself, behind a transparent webview holding the UI
+    #[cfg(all(not(target_arch = "wasm32"), feature = "desktop"))]
+    {
+        use dioxus::desktop::{Config, WindowBuilder};
+        let window = WindowBuilder::new()
+            .with_title("Parallel Image Editor")
+            .with_transparent(true);
+        dioxus::LaunchBuilder::desktop()
+            .with_cfg(
+                Config::new()
+                    .with_window(window)
+                    .with_background_color((0, 0, 0, 0)),
+            )
+            .launch(App);
+    }
 }
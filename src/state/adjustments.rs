@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+
+// must match the array length of `Adjustments` in shader.wgsl
+pub const MAX_LAYERS: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AdjustmentKind {
+    Hsv {
+        hue: f32, // radians
+        saturation: f32,
+        value: f32,
+    },
+    Light {
+        exposure: f32,   // stops
+        brightness: f32, // offset added to every channel
+        contrast: f32,   // 0 keeps the image unchanged
+        pivot: f32,      // level that contrast scales around
+        gamma: f32,
+    },
+}
+
+impl AdjustmentKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AdjustmentKind::Hsv { .. } => "hsv",
+            AdjustmentKind::Light { .. } => "light",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AdjustmentKind::Hsv { .. } => "HSV",
+            AdjustmentKind::Light { .. } => "Light",
+        }
+    }
+
+    // the neutral version of each adjustment, used when a layer is added
+    pub fn default_for(name: &str) -> Option<AdjustmentKind> {
+        match name {
+            "hsv" => Some(AdjustmentKind::Hsv {
+                hue: 0.0,
+                saturation: 0.0,
+                value: 0.0,
+            }),
+            "light" => Some(AdjustmentKind::Light {
+                exposure: 0.0,
+                brightness: 0.0,
+                contrast: 0.0,
+                pivot: 0.5,
+                gamma: 1.0,
+            }),
+            _ => None,
+        }
+    }
+
+    // id of the branch in `apply_layer` in shader.wgsl
+    fn gpu_kind(&self) -> u32 {
+        match self {
+            AdjustmentKind::Hsv { .. } => 0,
+            AdjustmentKind::Light { .. } => 1,
+        }
+    }
+
+    fn gpu_params(&self) -> [[f32; 4]; 2] {
+        match *self {
+            AdjustmentKind::Hsv {
+                hue,
+                saturation,
+                value,
+            } => [[hue, saturation, value, 0.0], [0.0; 4]],
+            AdjustmentKind::Light {
+                exposure,
+                brightness,
+                contrast,
+                pivot,
+                gamma,
+            } => [[exposure, brightness, contrast, pivot], [gamma, 0.0, 0.0, 0.0]],
+        }
+    }
+
+    fn params(&self) -> Vec<(&'static str, f32)> {
+        match *self {
+            AdjustmentKind::Hsv {
+                hue,
+                saturation,
+                value,
+            } => vec![("hue", hue), ("saturation", saturation), ("value", value)],
+            AdjustmentKind::Light {
+                exposure,
+                brightness,
+                contrast,
+                pivot,
+                gamma,
+            } => vec![
+                ("exposure", exposure),
+                ("brightness", brightness),
+                ("contrast", contrast),
+                ("pivot", pivot),
+                ("gamma", gamma),
+            ],
+        }
+    }
+
+    fn from_params(name: &str, params: &HashMap<&str, f32>) -> Option<AdjustmentKind> {
+        let mut kind = AdjustmentKind::default_for(name)?;
+        let get = |key: &str, default: f32| params.get(key).copied().unwrap_or(default);
+        match &mut kind {
+            AdjustmentKind::Hsv {
+                hue,
+                saturation,
+                value,
+            } => {
+                *hue = get("hue", *hue);
+                *saturation = get("saturation", *saturation);
+                *value = get("value", *value);
+            }
+            AdjustmentKind::Light {
+                exposure,
+                brightness,
+                contrast,
+                pivot,
+                gamma,
+            } => {
+                *exposure = get("exposure", *exposure);
+                *brightness = get("brightness", *brightness);
+                *contrast = get("contrast", *contrast);
+                *pivot = get("pivot", *pivot);
+                *gamma = get("gamma", *gamma).max(0.01);
+            }
+        }
+        Some(kind)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AdjustmentLayer {
+    pub id: u32,
+    pub kind: AdjustmentKind,
+    pub enabled: bool,
+    pub opacity: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuLayer {
+    pub kind: u32,
+    pub opacity: f32,
+    pub _pad: [u32; 2],
+    pub params: [[f32; 4]; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct AdjustmentUniform {
+    pub count: u32,
+    pub _pad: [u32; 3],
+    pub layers: [GpuLayer; MAX_LAYERS],
+}
+
+// ordered list of adjustments, evaluated bottom (index 0) to top
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct AdjustmentStack {
+    pub layers: Vec<AdjustmentLayer>,
+    next_id: u32,
+}
+
+impl AdjustmentStack {
+    pub fn push(&mut self, kind: AdjustmentKind) -> Option<u32> {
+        if self.layers.len() >= MAX_LAYERS {
+            return None;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.layers.push(AdjustmentLayer {
+            id,
+            kind,
+            enabled: true,
+            opacity: 1.0,
+        });
+        Some(id)
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.layers.retain(|layer| layer.id != id);
+    }
+
+    // moves a layer one step towards the top (later in the evaluation order)
+    pub fn move_up(&mut self, id: u32) {
+        if let Some(i) = self.position(id)
+            && i + 1 < self.layers.len()
+        {
+            self.layers.swap(i, i + 1);
+        }
+    }
+
+    pub fn move_down(&mut self, id: u32) {
+        if let Some(i) = self.position(id)
+            && i > 0
+        {
+            self.layers.swap(i, i - 1);
+        }
+    }
+
+    pub fn layer_mut(&mut self, id: u32) -> Option<&mut AdjustmentLayer> {
+        self.layers.iter_mut().find(|layer| layer.id == id)
+    }
+
+    // topmost layer of the given type, which the tool panels edit
+    pub fn find(&self, name: &str) -> Option<&AdjustmentLayer> {
+        self.layers.iter().rev().find(|layer| layer.kind.name() == name)
+    }
+
+    // returns the topmost layer of the given type, adding a neutral one if there is none
+    pub fn find_or_push(&mut self, name: &str) -> Option<&mut AdjustmentLayer> {
+        let id = match self.find(name) {
+            Some(layer) => layer.id,
+            None => self.push(AdjustmentKind::default_for(name)?)?,
+        };
+        self.layer_mut(id)
+    }
+
+    fn position(&self, id: u32) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.id == id)
+    }
+
+    pub fn to_gpu(&self) -> AdjustmentUniform {
+        let mut uniform = AdjustmentUniform {
+            count: 0,
+            _pad: [0; 3],
+            layers: [GpuLayer {
+                kind: 0,
+                opacity: 0.0,
+                _pad: [0; 2],
+                params: [[0.0; 4]; 2],
+            }; MAX_LAYERS],
+        };
+        for layer in self.layers.iter().filter(|layer| layer.enabled) {
+            uniform.layers[uniform.count as usize] = GpuLayer {
+                kind: layer.kind.gpu_kind(),
+                opacity: layer.opacity.clamp(0.0, 1.0),
+                _pad: [0; 2],
+                params: layer.kind.gpu_params(),
+            };
+            uniform.count += 1;
+        }
+        uniform
+    }
+
+    // one layer per line: `<type> enabled=<0|1> opacity=<f> <param>=<f> ...`
+    pub fn serialize(&self) -> String {
+        self.layers
+            .iter()
+            .map(|layer| {
+                let mut line = format!(
+                    "{} enabled={} opacity={}",
+                    layer.kind.name(),
+                    layer.enabled as u8,
+                    layer.opacity
+                );
+                for (key, value) in layer.kind.params() {
+                    line.push_str(&format!(" {}={}", key, value));
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn deserialize(text: &str) -> Result<AdjustmentStack, String> {
+        let mut stack = AdjustmentStack::default();
+        for (line_no, line) in text.lines().enumerate() {
+            let mut parts = line.split_whitespace();
+            let Some(name) = parts.next() else {
+                continue;
+            };
+            let mut params = HashMap::new();
+            for part in parts {
+                let (key, value) = part
+                    .split_once('=')
+                    .ok_or_else(|| format!("line {}: expected key=value, got '{}'", line_no + 1, part))?;
+                let value = value
+                    .parse::<f32>()
+                    .map_err(|_| format!("line {}: invalid number '{}'", line_no + 1, value))?;
+                params.insert(key, value);
+            }
+            let kind = AdjustmentKind::from_params(name, &params)
+                .ok_or_else(|| format!("line {}: unknown adjustment '{}'", line_no + 1, name))?;
+            let id = stack
+                .push(kind)
+                .ok_or_else(|| format!("more than {} layers", MAX_LAYERS))?;
+            if let Some(layer) = stack.layer_mut(id) {
+                layer.enabled = params.get("enabled").copied().unwrap_or(1.0) != 0.0;
+                layer.opacity = params.get("opacity").copied().unwrap_or(1.0).clamp(0.0, 1.0);
+            }
+        }
+        Ok(stack)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn light(exposure: f32) -> AdjustmentKind {
+        AdjustmentKind::Light {
+            exposure,
+            brightness: 0.1,
+            contrast: -0.25,
+            pivot: 0.4,
+            gamma: 1.2,
+        }
+    }
+
+    fn hsv(hue: f32) -> AdjustmentKind {
+        AdjustmentKind::Hsv {
+            hue,
+            saturation: 0.5,
+            value: -0.125,
+        }
+    }
+
+    fn ids(stack: &AdjustmentStack) -> Vec<u32> {
+        stack.layers.iter().map(|layer| layer.id).collect()
+    }
+
+    #[test]
+    fn serialize_round_trips() {
+        let mut stack = AdjustmentStack::default();
+        stack.push(hsv(0.3));
+        let id = stack.push(light(1.5)).unwrap();
+        let layer = stack.layer_mut(id).unwrap();
+        layer.enabled = false;
+        layer.opacity = 0.75;
+
+        let restored = AdjustmentStack::deserialize(&stack.serialize()).unwrap();
+        assert_eq!(restored, stack);
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        let missing_value = AdjustmentStack::deserialize("hsv hue");
+        assert!(missing_value.unwrap_err().starts_with("line 1:"));
+        let bad_number = AdjustmentStack::deserialize("hsv hue=0\nlight exposure=lots");
+        assert!(bad_number.unwrap_err().starts_with("line 2:"));
+        let unknown = AdjustmentStack::deserialize("vignette amount=1");
+        assert!(unknown.unwrap_err().contains("unknown adjustment 'vignette'"));
+    }
+
+    #[test]
+    fn layers_past_the_limit_are_rejected() {
+        let mut stack = AdjustmentStack::default();
+        for _ in 0..MAX_LAYERS {
+            assert!(stack.push(hsv(0.0)).is_some());
+        }
+        assert_eq!(stack.push(hsv(0.0)), None);
+        assert_eq!(stack.layers.len(), MAX_LAYERS);
+
+        let text = vec!["hsv"; MAX_LAYERS + 1].join("\n");
+        assert!(AdjustmentStack::deserialize(&text).is_err());
+    }
+
+    #[test]
+    fn moving_past_either_end_does_nothing() {
+        let mut stack = AdjustmentStack::default();
+        let bottom = stack.push(hsv(0.0)).unwrap();
+        let top = stack.push(light(0.0)).unwrap();
+        stack.move_up(top);
+        stack.move_down(bottom);
+        assert_eq!(ids(&stack), vec![bottom, top]);
+        stack.move_up(bottom);
+        assert_eq!(ids(&stack), vec![top, bottom]);
+        stack.move_down(bottom);
+        assert_eq!(ids(&stack), vec![bottom, top]);
+    }
+
+    #[test]
+    fn disabled_layers_are_not_sent_to_the_gpu() {
+        let mut stack = AdjustmentStack::default();
+        stack.push(hsv(0.0));
+        let hidden = stack.push(light(2.0)).unwrap();
+        stack.push(light(-1.0));
+        stack.layer_mut(hidden).unwrap().enabled = false;
+
+        let uniform = stack.to_gpu();
+        assert_eq!(uniform.count, 2);
+        assert_eq!(uniform.layers[0].kind, 0);
+        assert_eq!(uniform.layers[1].kind, 1);
+        assert_eq!(uniform.layers[1].params[0][0], -1.0);
+    }
+}
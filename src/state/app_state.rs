@@ -1,7 +1,24 @@
-use std::collections::VecDeque;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+use std::sync::Arc;
 
+use crate::state::adjustments::AdjustmentStack;
+use crate::state::brush::BrushSettings;
+use crate::state::curves::CurveSet;
+use crate::state::export::ExportFormat;
+use crate::state::filters::FilterStack;
+use crate::state::histogram::Histogram;
+use crate::state::levels::{Eyedropper, Levels};
+use crate::state::metadata::ImageMetadata;
+use crate::state::transform::Transform;
+use crate::state::customlib::{CropRect, RenderSettings, State};
+use crate::state::history::{EditCommand, EditHistory, HistoryAction};
+use dioxus::html::FileEngine;
+use dioxus::html::geometry::PixelsRect;
 use dioxus::prelude::*;
 use image::DynamicImage;
+use image::imageops::FilterType;
 
 #[derive(Clone, Copy)]
 pub struct SideBarVisibility {
@@ -14,6 +31,12 @@ pub struct ImageZoom {
     pub limits: Signal<(i64, i64)>,
 }
 
+// where the image sits in the window, in client pixels, once it is shown
+#[derive(Clone, Copy)]
+pub struct BoardState {
+    pub rect: Signal<Option<PixelsRect>>,
+}
+
 #[derive(Clone, Copy)]
 pub struct NextImage {
     pub pressed: Signal<bool>,
@@ -23,35 +46,350 @@ pub struct NextImage {
 pub struct ImageVec {
     pub vector: Signal<VecDeque<DynamicImage>>,
     pub base64_vector: Signal<VecDeque<String>>,
+    pub names: Signal<VecDeque<String>>, // source file name without extension, per image
+    pub metadata: Signal<VecDeque<ImageMetadata>>, // EXIF, ICC and XMP of the source file, per image
     pub curr_image_index: Signal<usize>,
+    pub import: Signal<Option<Arc<dyn FileEngine>>>, // files picked with the load button, waiting to be decoded
 }
 
 #[derive(Clone, Copy)]
 pub struct WGPUSignal {
     pub signal: Signal<bool>,
     pub save_signal: Signal<i64>,
+    pub reload_signal: Signal<Option<usize>>, // index of an edited image that needs re-uploading
+    pub state: Signal<Option<Rc<RefCell<State>>>>, // outlives the image board so leaving for the gallery keeps the GPU textures
+}
+
+#[derive(Clone, Copy)]
+pub struct TextureCacheState {
+    pub budget_mb: Signal<u64>, // video memory the cached image textures may take
+    pub used: Signal<u64>,      // bytes, as of the last draw
+    pub evicted: Signal<Vec<usize>>, // images dropped from the cache at the last eviction
 }
 
 #[derive(Clone, Copy)]
 pub struct GalleryState {
     pub grid_size: Signal<String>,
     pub visibility: Signal<bool>,
+    pub selection: Signal<HashSet<usize>>, // images picked for batch processing
+}
+
+#[derive(Clone, Copy)]
+pub struct ExportState {
+    pub panel_visible: Signal<bool>,
+    pub format: Signal<ExportFormat>, // used by Save as and batch exports
+    pub name_template: Signal<String>,
+    pub filename: Signal<String>, // name confirmed in the Save as dialog
+    pub keep_metadata: Signal<bool>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum BatchStatus {
+    Queued,
+    Rendering,
+    Saved(String), // output file name
+    Failed(String),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct BatchItem {
+    pub index: usize,
+    pub status: BatchStatus,
+}
+
+#[derive(Clone, Copy)]
+pub struct BatchState {
+    pub panel_visible: Signal<bool>,
+    pub run_signal: Signal<bool>, // set by the panel, picked up by the image board
+    pub items: Signal<Vec<BatchItem>>, // progress of the current or last batch
+}
+
+impl BatchState {
+    pub fn running(&self) -> bool {
+        self.items.read().iter().any(|item| {
+            matches!(item.status, BatchStatus::Queued | BatchStatus::Rendering)
+        })
+    }
+
+    pub fn set_status(mut self, index: usize, status: BatchStatus) {
+        if let Some(item) = self.items.write().iter_mut().find(|item| item.index == index) {
+            item.status = status;
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct HSVState {
     pub panel_visible: Signal<bool>,
-    pub hue: Signal<f32>,
-    pub saturation: Signal<f32>,
-    pub value: Signal<f32>,
 }
 
 #[derive(Clone, Copy)]
-pub struct TestPanelVisibility {
-    pub visibility: Signal<bool>,
+pub struct HistogramState {
+    pub panel_visible: Signal<bool>,
+    pub data: Signal<Option<Histogram>>, // latest readback of the rendered image
+    pub supported: Signal<bool>,          // false when the GPU backend cannot run compute shaders
+}
+
+#[derive(Clone, Copy)]
+pub struct LightState {
+    pub panel_visible: Signal<bool>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AspectRatio {
+    Free,
+    Square,
+    FourThree,
+    SixteenNine,
+    Custom(u32, u32),
+}
+
+impl AspectRatio {
+    pub fn ratio(&self) -> Option<f64> {
+        match self {
+            AspectRatio::Free => None,
+            AspectRatio::Square => Some(1.0),
+            AspectRatio::FourThree => Some(4.0 / 3.0),
+            AspectRatio::SixteenNine => Some(16.0 / 9.0),
+            AspectRatio::Custom(w, h) if *w > 0 && *h > 0 => Some(*w as f64 / *h as f64),
+            AspectRatio::Custom(_, _) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct CropState {
+    pub panel_visible: Signal<bool>,
+    pub rect: Signal<CropRect>,
+    pub aspect: Signal<AspectRatio>,
+    pub crops: Signal<HashMap<usize, CropRect>>, // applied crop per image index
+}
+
+#[derive(Clone, Copy)]
+pub struct TransformState {
+    pub panel_visible: Signal<bool>,
+    pub transforms: Signal<HashMap<usize, Transform>>, // rotation, flips and straighten per image index
+}
+
+impl TransformState {
+    pub fn transform(&self, index: usize) -> Transform {
+        self.transforms.read().get(&index).copied().unwrap_or_default()
+    }
+
+    // edits the transform of one image, carries its crop into the new frame and records both for undo
+    pub fn edit(
+        mut self,
+        index: usize,
+        source: (u32, u32),
+        mut crops: Signal<HashMap<usize, CropRect>>,
+        history: HistoryState,
+        coalesce: Option<&'static str>,
+        f: impl FnOnce(&mut Transform),
+    ) {
+        let before = self.transform(index);
+        let mut after = before;
+        f(&mut after);
+        if after == before {
+            return;
+        }
+        let crop_before = crops.read().get(&index).copied();
+        let crop_after = crop_before.map(|rect| before.remap_crop(rect, &after, source));
+        self.transforms.write().insert(index, after);
+        if let Some(rect) = crop_after {
+            crops.write().insert(index, rect);
+        }
+        history.record(
+            index,
+            EditCommand::Transform {
+                before,
+                after,
+                crop_before,
+                crop_after,
+            },
+            coalesce,
+        );
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct DragSignal {
     pub can_drag: Signal<bool>,
 }
+
+#[derive(Clone, Copy)]
+pub struct ResizeState {
+    pub panel_visible: Signal<bool>,
+    pub filter: Signal<FilterType>,
+}
+
+#[derive(Clone, Copy)]
+pub struct BrushState {
+    pub panel_visible: Signal<bool>,
+    pub settings: Signal<BrushSettings>,
+}
+
+#[derive(Clone, Copy)]
+pub struct HistoryState {
+    pub stacks: Signal<HashMap<usize, EditHistory>>, // one history per image index
+    pub limit: Signal<usize>,
+    pub pending: Signal<Option<HistoryAction>>,
+}
+
+impl HistoryState {
+    pub fn record(mut self, index: usize, command: EditCommand, coalesce: Option<&'static str>) {
+        let limit = (self.limit)();
+        self.stacks
+            .write()
+            .entry(index)
+            .or_default()
+            .push(command, coalesce, limit);
+    }
+
+    // ends a coalesced gesture such as a slider drag
+    pub fn seal(mut self, index: usize) {
+        if let Some(history) = self.stacks.write().get_mut(&index) {
+            history.seal();
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct AdjustmentState {
+    pub layers_panel_visible: Signal<bool>,
+    pub stacks: Signal<HashMap<usize, AdjustmentStack>>, // adjustment layers per image index
+}
+
+impl AdjustmentState {
+    pub fn stack(&self, index: usize) -> AdjustmentStack {
+        self.stacks.read().get(&index).cloned().unwrap_or_default()
+    }
+
+    // edits the stack of one image and records the change for undo
+    pub fn edit(
+        mut self,
+        index: usize,
+        history: HistoryState,
+        coalesce: Option<&'static str>,
+        f: impl FnOnce(&mut AdjustmentStack),
+    ) {
+        let before = self.stack(index);
+        let mut after = before.clone();
+        f(&mut after);
+        if after != before {
+            self.stacks.write().insert(index, after.clone());
+            history.record(index, EditCommand::Adjustments { before, after }, coalesce);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct CurvesState {
+    pub panel_visible: Signal<bool>,
+    pub curves: Signal<HashMap<usize, CurveSet>>, // curves per image index
+    pub presets: Signal<Vec<(String, CurveSet)>>,
+}
+
+impl CurvesState {
+    pub fn curves(&self, index: usize) -> CurveSet {
+        self.curves.read().get(&index).cloned().unwrap_or_default()
+    }
+
+    // edits the curves of one image and records the change for undo
+    pub fn edit(
+        mut self,
+        index: usize,
+        history: HistoryState,
+        coalesce: Option<&'static str>,
+        f: impl FnOnce(&mut CurveSet),
+    ) {
+        let before = self.curves(index);
+        let mut after = before.clone();
+        f(&mut after);
+        if after != before {
+            self.curves.write().insert(index, after.clone());
+            history.record(index, EditCommand::Curves { before, after }, coalesce);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct LevelsState {
+    pub panel_visible: Signal<bool>,
+    pub levels: Signal<HashMap<usize, Levels>>, // levels per image index
+    pub eyedropper: Signal<Option<Eyedropper>>, // armed until the next click on the canvas
+}
+
+impl LevelsState {
+    pub fn levels(&self, index: usize) -> Levels {
+        self.levels.read().get(&index).copied().unwrap_or_default()
+    }
+
+    // edits the levels of one image and records the change for undo
+    pub fn edit(
+        mut self,
+        index: usize,
+        history: HistoryState,
+        coalesce: Option<&'static str>,
+        f: impl FnOnce(&mut Levels),
+    ) {
+        let before = self.levels(index);
+        let mut after = before;
+        f(&mut after);
+        if after != before {
+            self.levels.write().insert(index, after);
+            history.record(index, EditCommand::Levels { before, after }, coalesce);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct FilterState {
+    pub panel_visible: Signal<bool>,
+    pub stacks: Signal<HashMap<usize, FilterStack>>, // convolution filters per image index
+}
+
+impl FilterState {
+    pub fn filters(&self, index: usize) -> FilterStack {
+        self.stacks.read().get(&index).cloned().unwrap_or_default()
+    }
+
+    // edits the filters of one image and records the change for undo
+    pub fn edit(
+        mut self,
+        index: usize,
+        history: HistoryState,
+        coalesce: Option<&'static str>,
+        f: impl FnOnce(&mut FilterStack),
+    ) {
+        let before = self.filters(index);
+        let mut after = before.clone();
+        f(&mut after);
+        if after != before {
+            self.stacks.write().insert(index, after.clone());
+            history.record(index, EditCommand::Filters { before, after }, coalesce);
+        }
+    }
+}
+
+// the settings image `index` is exported with, for the renderer which knows nothing of signals.
+// Called from effects and handlers, so the contexts are looked up rather than hooked
+pub fn export_settings(index: usize) -> RenderSettings {
+    RenderSettings {
+        crop: consume_context::<CropState>().crops.read().get(&index).copied(),
+        transform: consume_context::<TransformState>().transform(index),
+        filters: consume_context::<FilterState>().filters(index),
+        adjustments: consume_context::<AdjustmentState>().stack(index),
+        levels: consume_context::<LevelsState>().levels(index),
+        curves: consume_context::<CurvesState>().curves(index),
+    }
+}
+
+// the settings the editor shows image `index` with: the full image while the crop tool is open,
+// so the rectangle can be re-edited
+pub fn display_settings(index: usize) -> RenderSettings {
+    let mut settings = export_settings(index);
+    if (consume_context::<CropState>().panel_visible)() {
+        settings.crop = None;
+    }
+    settings
+}
@@ -0,0 +1,365 @@
+use image::{Rgba, RgbaImage, imageops};
+use std::collections::HashMap;
+
+// side of the blocks of the layer a stroke saves before it first paints over them
+const BASE_TILE: u32 = 64;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BrushSettings {
+    pub size: f32,     // diameter in image pixels
+    pub hardness: f32, // 0..1, fraction of the radius painted at full strength
+    pub opacity: f32,  // 0..1, maximum coverage of a single stroke
+    pub flow: f32,     // 0..1, coverage added by each dab
+    pub spacing: f32,  // distance between dabs as a fraction of the size
+    pub color: [u8; 3],
+    pub eraser: bool,
+}
+
+impl Default for BrushSettings {
+    fn default() -> Self {
+        Self {
+            size: 20.0,
+            hardness: 0.8,
+            opacity: 1.0,
+            flow: 0.5,
+            spacing: 0.1,
+            color: [255, 255, 255],
+            eraser: false,
+        }
+    }
+}
+
+// region of the layer touched by a dab, in pixels
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DirtyRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DirtyRect {
+    pub fn union(self, other: DirtyRect) -> DirtyRect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        DirtyRect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+// accumulated coverage of a stroke, capped by opacity, over the bounding box it has reached
+#[derive(Default)]
+struct Coverage {
+    rect: Option<DirtyRect>,
+    values: Vec<f32>,
+}
+
+impl Coverage {
+    // makes room for `rect`, growing by half the current size at least so long strokes
+    // reallocate a logarithmic number of times
+    fn grow(&mut self, rect: DirtyRect, bounds: (u32, u32)) {
+        let grown = match self.rect {
+            Some(current) if current.union(rect) == current => return,
+            Some(current) => {
+                // padded only on the sides the stroke moved past
+                let pad = BASE_TILE.max(current.width.max(current.height) / 2);
+                let pad_if = |grew: bool| if grew { pad } else { 0 };
+                let (right, bottom) = (current.x + current.width, current.y + current.height);
+                let wanted = current.union(rect);
+                let x = wanted.x.saturating_sub(pad_if(rect.x < current.x));
+                let y = wanted.y.saturating_sub(pad_if(rect.y < current.y));
+                let x1 = wanted.x + wanted.width + pad_if(rect.x + rect.width > right);
+                let y1 = wanted.y + wanted.height + pad_if(rect.y + rect.height > bottom);
+                DirtyRect {
+                    x,
+                    y,
+                    width: x1.min(bounds.0) - x,
+                    height: y1.min(bounds.1) - y,
+                }
+            }
+            None => rect,
+        };
+
+        let mut values = vec![0.0; (grown.width * grown.height) as usize];
+        if let Some(current) = self.rect {
+            for row in 0..current.height {
+                let from = (row * current.width) as usize;
+                let to = ((current.y + row - grown.y) * grown.width + current.x - grown.x) as usize;
+                values[to..to + current.width as usize]
+                    .copy_from_slice(&self.values[from..from + current.width as usize]);
+            }
+        }
+        self.rect = Some(grown);
+        self.values = values;
+    }
+
+    // coverage at a layer pixel inside the grown rect
+    fn at(&mut self, x: u32, y: u32) -> &mut f32 {
+        let rect = self.rect.expect("coverage grown before use");
+        &mut self.values[((y - rect.y) * rect.width + x - rect.x) as usize]
+    }
+}
+
+// the area a finished stroke changed, before and after it, as kept for undo
+pub struct StrokePatch {
+    pub rect: DirtyRect,
+    pub before: RgbaImage,
+    pub after: RgbaImage,
+}
+
+struct Stroke {
+    settings: BrushSettings,
+    base: HashMap<(u32, u32), RgbaImage>, // layer contents before the stroke, per touched tile
+    coverage: Coverage,
+    dirty: Option<DirtyRect>, // union of the dabs so far
+    last: (f32, f32),
+    distance_left: f32, // distance until the next dab along the path
+}
+
+impl Stroke {
+    // the pixel before the stroke, when the stroke has painted its tile
+    fn base_pixel(&self, x: u32, y: u32) -> Option<Rgba<u8>> {
+        self.base
+            .get(&(x / BASE_TILE, y / BASE_TILE))
+            .map(|tile| *tile.get_pixel(x % BASE_TILE, y % BASE_TILE))
+    }
+}
+
+// straight-alpha RGBA layer painted on top of the image
+pub struct PaintLayer {
+    pub pixels: RgbaImage,
+    stroke: Option<Stroke>,
+}
+
+impl PaintLayer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::from_pixels(RgbaImage::new(width, height))
+    }
+
+    pub fn from_pixels(pixels: RgbaImage) -> Self {
+        Self { pixels, stroke: None }
+    }
+
+    pub fn is_painting(&self) -> bool {
+        self.stroke.is_some()
+    }
+
+    pub fn begin_stroke(&mut self, pos: (f32, f32), settings: BrushSettings) -> Option<DirtyRect> {
+        self.stroke = Some(Stroke {
+            settings,
+            base: HashMap::new(),
+            coverage: Coverage::default(),
+            dirty: None,
+            last: pos,
+            distance_left: (settings.size * settings.spacing).max(0.5),
+        });
+        self.dab(pos)
+    }
+
+    // places dabs along the segment from the previous sample so fast strokes stay continuous
+    pub fn stroke_to(&mut self, pos: (f32, f32)) -> Option<DirtyRect> {
+        let (last, distance_left, step) = match &self.stroke {
+            Some(stroke) => (
+                stroke.last,
+                stroke.distance_left,
+                (stroke.settings.size * stroke.settings.spacing).max(0.5),
+            ),
+            None => return None,
+        };
+
+        let (dx, dy) = (pos.0 - last.0, pos.1 - last.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        let mut dirty: Option<DirtyRect> = None;
+        let mut travelled = distance_left;
+
+        while travelled <= length {
+            let t = travelled / length;
+            let dab_pos = (last.0 + dx * t, last.1 + dy * t);
+            if let Some(rect) = self.dab(dab_pos) {
+                dirty = Some(dirty.map_or(rect, |d| d.union(rect)));
+            }
+            travelled += step;
+        }
+        if let Some(stroke) = &mut self.stroke {
+            stroke.last = pos;
+            stroke.distance_left = travelled - length;
+        }
+        dirty
+    }
+
+    // finishes the stroke and returns what it changed, None when it changed nothing
+    pub fn end_stroke(&mut self) -> Option<StrokePatch> {
+        let stroke = self.stroke.take()?;
+        let rect = stroke.dirty?;
+        let after = imageops::crop_imm(&self.pixels, rect.x, rect.y, rect.width, rect.height).to_image();
+        // corners of the bounding box no dab reached keep their pixels
+        let before = RgbaImage::from_fn(rect.width, rect.height, |x, y| {
+            stroke
+                .base_pixel(rect.x + x, rect.y + y)
+                .unwrap_or(*after.get_pixel(x, y))
+        });
+        Some(StrokePatch { rect, before, after })
+    }
+
+    // writes `pixels` over `rect`, as undo and redo of a stroke do
+    pub fn write_patch(&mut self, rect: DirtyRect, pixels: &RgbaImage) {
+        self.stroke = None;
+        imageops::replace(&mut self.pixels, pixels, rect.x as i64, rect.y as i64);
+    }
+
+    pub fn clear(&mut self) {
+        self.stroke = None;
+        for pixel in self.pixels.pixels_mut() {
+            *pixel = Rgba([0, 0, 0, 0]);
+        }
+    }
+
+    fn dab(&mut self, center: (f32, f32)) -> Option<DirtyRect> {
+        let (width, height) = self.pixels.dimensions();
+        let stroke = self.stroke.as_mut()?;
+        let settings = stroke.settings;
+        let radius = (settings.size / 2.0).max(0.5);
+
+        let x0 = (center.0 - radius).floor().max(0.0) as u32;
+        let y0 = (center.1 - radius).floor().max(0.0) as u32;
+        let x1 = ((center.0 + radius).ceil().max(0.0) as u32).min(width);
+        let y1 = ((center.1 + radius).ceil().max(0.0) as u32).min(height);
+        if x0 >= x1 || y0 >= y1 {
+            return None;
+        }
+        let rect = DirtyRect {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        };
+
+        stroke.coverage.grow(rect, (width, height));
+        stroke.dirty = Some(stroke.dirty.map_or(rect, |d| d.union(rect)));
+        for ty in y0 / BASE_TILE..=(y1 - 1) / BASE_TILE {
+            for tx in x0 / BASE_TILE..=(x1 - 1) / BASE_TILE {
+                stroke.base.entry((tx, ty)).or_insert_with(|| {
+                    let (x, y) = (tx * BASE_TILE, ty * BASE_TILE);
+                    let tile = (BASE_TILE.min(width - x), BASE_TILE.min(height - y));
+                    imageops::crop_imm(&self.pixels, x, y, tile.0, tile.1).to_image()
+                });
+            }
+        }
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let dx = x as f32 + 0.5 - center.0;
+                let dy = y as f32 + 0.5 - center.1;
+                let falloff = brush_falloff((dx * dx + dy * dy).sqrt() / radius, settings.hardness);
+                if falloff <= 0.0 {
+                    continue;
+                }
+
+                let added = falloff * settings.flow;
+                let coverage = stroke.coverage.at(x, y);
+                *coverage = (*coverage + added * (1.0 - *coverage)).min(settings.opacity);
+                let coverage = *coverage;
+
+                let base = stroke.base_pixel(x, y).expect("tiles under a dab are saved");
+                self.pixels.put_pixel(x, y, composite(base, coverage, &settings));
+            }
+        }
+
+        Some(rect)
+    }
+}
+
+// 1 inside the hard core, smooth falloff to 0 at the edge
+pub fn brush_falloff(r: f32, hardness: f32) -> f32 {
+    if r >= 1.0 {
+        return 0.0;
+    }
+    let hardness = hardness.clamp(0.0, 1.0);
+    if r <= hardness {
+        return 1.0;
+    }
+    let t = (r - hardness) / (1.0 - hardness);
+    1.0 - t * t * (3.0 - 2.0 * t)
+}
+
+fn composite(base: Rgba<u8>, coverage: f32, settings: &BrushSettings) -> Rgba<u8> {
+    let base_a = base[3] as f32 / 255.0;
+    if settings.eraser {
+        let out_a = base_a * (1.0 - coverage);
+        return Rgba([base[0], base[1], base[2], (out_a * 255.0).round() as u8]);
+    }
+
+    let out_a = coverage + base_a * (1.0 - coverage);
+    if out_a <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let mut out = [0u8; 4];
+    for c in 0..3 {
+        let painted = settings.color[c] as f32 * coverage;
+        let under = base[c] as f32 * base_a * (1.0 - coverage);
+        out[c] = ((painted + under) / out_a).round().clamp(0.0, 255.0) as u8;
+    }
+    out[3] = (out_a * 255.0).round() as u8;
+    Rgba(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> BrushSettings {
+        BrushSettings {
+            size: 10.0,
+            hardness: 1.0,
+            opacity: 0.5,
+            flow: 1.0,
+            color: [255, 0, 0],
+            ..BrushSettings::default()
+        }
+    }
+
+    #[test]
+    fn overlapping_dabs_stay_capped_across_tiles() {
+        let mut layer = PaintLayer::new(200, 100);
+        layer.begin_stroke((60.0, 50.0), settings());
+        // crosses the tile border at x=64 and comes back over the start
+        layer.stroke_to((140.0, 50.0));
+        layer.stroke_to((60.0, 50.0));
+        let patch = layer.end_stroke().unwrap();
+        for x in [60, 64, 100, 140] {
+            assert_eq!(*layer.pixels.get_pixel(x, 50), Rgba([255, 0, 0, 128]), "x={x}");
+        }
+        assert_eq!(layer.pixels.get_pixel(100, 10)[3], 0);
+
+        // the patch covers the stroke and undoes it
+        assert_eq!(patch.rect, DirtyRect { x: 55, y: 45, width: 90, height: 10 });
+        layer.write_patch(patch.rect, &patch.before);
+        assert!(layer.pixels.pixels().all(|p| p[3] == 0));
+        layer.write_patch(patch.rect, &patch.after);
+        assert_eq!(*layer.pixels.get_pixel(100, 50), Rgba([255, 0, 0, 128]));
+    }
+
+    #[test]
+    fn coverage_only_covers_the_stroke() {
+        let mut layer = PaintLayer::new(4000, 3000);
+        layer.begin_stroke((1000.0, 1000.0), settings());
+        layer.stroke_to((1200.0, 1000.0));
+        let stroke = layer.stroke.as_ref().unwrap();
+        assert!(stroke.coverage.values.len() < 400 * 200);
+        assert!(stroke.base.len() <= 6);
+    }
+
+    #[test]
+    fn growing_coverage_keeps_its_values() {
+        let mut coverage = Coverage::default();
+        coverage.grow(DirtyRect { x: 10, y: 10, width: 4, height: 4 }, (100, 100));
+        *coverage.at(12, 13) = 0.25;
+        coverage.grow(DirtyRect { x: 0, y: 30, width: 4, height: 4 }, (100, 100));
+        assert_eq!(*coverage.at(12, 13), 0.25);
+        assert_eq!(*coverage.at(1, 31), 0.0);
+    }
+}
@@ -0,0 +1,117 @@
+// sRGB transfer functions, the same curves as srgb_encode / srgb_decode in shader.wgsl
+use half::f16;
+use image::DynamicImage;
+
+pub fn srgb_to_linear(v: f32) -> f32 {
+    let v = v.max(0.0);
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(v: f32) -> f32 {
+    let v = v.max(0.0);
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// quantizes a 0 to 1 value the way a unorm render target stores it
+pub fn to_u8(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// 16-bit and float images, which lose precision in an 8-bit texture
+pub fn is_high_bit_depth(image: &DynamicImage) -> bool {
+    image.color().bytes_per_pixel() > image.color().channel_count()
+}
+
+// texel bytes for a half float texture, decoded to linear light like an sRGB texture would be
+pub fn linear_half_texels(image: &DynamicImage) -> Vec<u8> {
+    let rgba = image.to_rgba32f();
+    let mut texels = Vec::with_capacity(rgba.as_raw().len() * 2);
+    for pixel in rgba.pixels() {
+        let [r, g, b, a] = pixel.0;
+        for v in [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a.clamp(0.0, 1.0)] {
+            texels.extend_from_slice(&f16::from_f32(v).to_bits().to_ne_bytes());
+        }
+    }
+    texels
+}
+
+// quantizes a 0 to 1 value to 16 bits
+pub fn to_u16(v: f32) -> u16 {
+    (v.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+// one channel of a linear half float texel as a 16-bit sRGB value, `bits` as read back from the GPU
+pub fn half_to_u16(bits: u16, is_alpha: bool) -> u16 {
+    let v = f16::from_bits(bits).to_f32();
+    if is_alpha { to_u16(v) } else { to_u16(linear_to_srgb(v)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::curves::{CurveSet, LUT_SIZE};
+    use crate::state::levels::Levels;
+
+    // mirrors curves_lookup in shader.wgsl
+    fn lut_lookup(lut: &[f16], channel: usize, x: f32) -> f32 {
+        let last = (LUT_SIZE - 1) as f32;
+        let pos = x.clamp(0.0, 1.0) * last;
+        let i = pos.floor() as usize;
+        let j = (i + 1).min(LUT_SIZE - 1);
+        let a = lut[i * 4 + channel].to_f32();
+        let b = lut[j * 4 + channel].to_f32();
+        a + (b - a) * pos.fract()
+    }
+
+    #[test]
+    fn srgb_round_trip_is_lossless() {
+        for v in 0..=255u8 {
+            let linear = srgb_to_linear(v as f32 / 255.0);
+            assert_eq!(to_u8(linear_to_srgb(linear)), v);
+        }
+    }
+
+    #[test]
+    fn linear_values_are_monotonic() {
+        let mut previous = -1.0;
+        for v in 0..=255u8 {
+            let linear = srgb_to_linear(v as f32 / 255.0);
+            assert!(linear > previous);
+            previous = linear;
+        }
+    }
+
+    // half floats hold linear light, so 16-bit values come back far closer than 8 bits could carry
+    #[test]
+    fn half_float_round_trip_beats_8_bit() {
+        let mut worst = 0;
+        for v in (0..=65535u16).step_by(7) {
+            let linear = f16::from_f32(srgb_to_linear(v as f32 / 65535.0));
+            let back = half_to_u16(linear.to_bits(), false);
+            worst = worst.max(back.abs_diff(v));
+        }
+        // one 8-bit step is 257 16-bit steps
+        assert!(worst <= 16, "worst error {worst}");
+    }
+
+    // decode on upload, identity tone table, encode on output: every 8-bit value comes back unchanged
+    #[test]
+    fn unadjusted_pipeline_is_lossless() {
+        let lut = CurveSet::default().bake(&Levels::default());
+        for v in 0..=255u8 {
+            let linear = srgb_to_linear(v as f32 / 255.0);
+            for channel in 0..3 {
+                let mapped = srgb_to_linear(lut_lookup(&lut, channel, linear_to_srgb(linear)));
+                assert_eq!(to_u8(linear_to_srgb(mapped)), v, "channel {channel}");
+            }
+        }
+    }
+}
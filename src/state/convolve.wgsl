@@ -0,0 +1,56 @@
+// one convolution pass; separable filters run as a horizontal and a vertical pass
+
+struct Convolution {
+    size: vec2<u32>, // kernel width and height, both odd
+    blur_alpha: u32, // 1 when alpha is convolved too, otherwise each pixel keeps its own
+    _pad: u32,
+    weights: array<vec4<f32>, 256>, // row-major, four weights per element
+}
+
+@group(0) @binding(0)
+var t_src: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> conv: Convolution;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+// a single triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+fn weight(i: u32) -> f32 {
+    return conv.weights[i / 4u][i % 4u];
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dims = vec2<i32>(textureDimensions(t_src));
+    let center = vec2<i32>(in.clip_position.xy);
+    let half_size = vec2<i32>(conv.size / 2u);
+    var sum = vec4<f32>(0.0);
+    for (var y = 0u; y < conv.size.y; y++) {
+        for (var x = 0u; x < conv.size.x; x++) {
+            // edges are extended by clamping to the border pixels
+            let offset = vec2<i32>(i32(x), i32(y)) - half_size;
+            let coord = clamp(center + offset, vec2<i32>(0), dims - vec2<i32>(1));
+            // premultiplied, so transparent pixels do not bleed their color into the result
+            let texel = textureLoad(t_src, coord, 0);
+            sum += vec4<f32>(texel.rgb * texel.a, texel.a) * weight(y * conv.size.x + x);
+        }
+    }
+    var alpha = clamp(sum.a, 0.0, 1.0);
+    if (conv.blur_alpha == 0u) {
+        alpha = textureLoad(t_src, center, 0).a;
+    }
+    if (alpha <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    return vec4<f32>(clamp(sum.rgb / alpha, vec3<f32>(0.0), vec3<f32>(1.0)), alpha);
+}
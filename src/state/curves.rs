@@ -0,0 +1,365 @@
+use crate::state::levels::Levels;
+use half::f16;
+
+// number of entries in the lookup texture sampled by the fragment shader
+pub const LUT_SIZE: usize = 256;
+// smallest x distance between neighbouring points, one LUT entry
+const MIN_GAP: f32 = 1.0 / LUT_SIZE as f32;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CurveChannel {
+    Master,
+    Red,
+    Green,
+    Blue,
+}
+
+impl CurveChannel {
+    pub const ALL: [CurveChannel; 4] = [
+        CurveChannel::Master,
+        CurveChannel::Red,
+        CurveChannel::Green,
+        CurveChannel::Blue,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CurveChannel::Master => "master",
+            CurveChannel::Red => "red",
+            CurveChannel::Green => "green",
+            CurveChannel::Blue => "blue",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CurveChannel::Master => "RGB",
+            CurveChannel::Red => "R",
+            CurveChannel::Green => "G",
+            CurveChannel::Blue => "B",
+        }
+    }
+}
+
+// control points in 0..1, sorted by x; the end points always sit at x = 0 and x = 1
+#[derive(Clone, PartialEq, Debug)]
+pub struct Curve {
+    pub points: Vec<(f32, f32)>,
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Self {
+            points: vec![(0.0, 0.0), (1.0, 1.0)],
+        }
+    }
+}
+
+impl Curve {
+    pub fn is_identity(&self) -> bool {
+        self.points.iter().all(|&(x, y)| x == y)
+    }
+
+    // monotone cubic interpolation (Fritsch-Carlson), so the curve never overshoots its points
+    pub fn evaluate(&self, x: f32) -> f32 {
+        let points = &self.points;
+        let n = points.len();
+        let x = x.clamp(0.0, 1.0);
+        if n == 1 {
+            return points[0].1;
+        }
+
+        let secants: Vec<f32> = points
+            .windows(2)
+            .map(|w| (w[1].1 - w[0].1) / (w[1].0 - w[0].0).max(f32::EPSILON))
+            .collect();
+        let mut tangents = vec![0.0; n];
+        tangents[0] = secants[0];
+        tangents[n - 1] = secants[n - 2];
+        for k in 1..n - 1 {
+            if secants[k - 1] * secants[k] > 0.0 {
+                tangents[k] = (secants[k - 1] + secants[k]) / 2.0;
+            }
+        }
+        for k in 0..n - 1 {
+            if secants[k] == 0.0 {
+                tangents[k] = 0.0;
+                tangents[k + 1] = 0.0;
+                continue;
+            }
+            let a = tangents[k] / secants[k];
+            let b = tangents[k + 1] / secants[k];
+            let s = a * a + b * b;
+            if s > 9.0 {
+                let t = 3.0 / s.sqrt();
+                tangents[k] = t * a * secants[k];
+                tangents[k + 1] = t * b * secants[k];
+            }
+        }
+
+        let k = points
+            .windows(2)
+            .position(|w| x <= w[1].0)
+            .unwrap_or(n - 2);
+        let (x0, y0) = points[k];
+        let (x1, y1) = points[k + 1];
+        let h = x1 - x0;
+        if h <= f32::EPSILON {
+            return y1.clamp(0.0, 1.0);
+        }
+        let t = ((x - x0) / h).clamp(0.0, 1.0);
+        let t2 = t * t;
+        let t3 = t2 * t;
+        let y = (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * h * tangents[k]
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * h * tangents[k + 1];
+        y.clamp(0.0, 1.0)
+    }
+
+    // adds a point and returns its index. Between neighbours too close to fit another point
+    // nothing is added and the nearer neighbour's index is returned
+    pub fn insert(&mut self, x: f32, y: f32) -> usize {
+        let x = x.clamp(0.0, 1.0);
+        let index = self
+            .points
+            .iter()
+            .position(|&(px, _)| px > x)
+            .unwrap_or(self.points.len() - 1)
+            .max(1);
+        let (prev, next) = (self.points[index - 1].0, self.points[index].0);
+        if next - prev < 2.0 * MIN_GAP {
+            return if x - prev <= next - x { index - 1 } else { index };
+        }
+        self.points.insert(index, (x, y));
+        self.move_point(index, x, y);
+        index
+    }
+
+    // points keep their order: x is clamped between the neighbours and end points only move vertically
+    pub fn move_point(&mut self, index: usize, x: f32, y: f32) {
+        let last = self.points.len() - 1;
+        let x = if index == 0 {
+            0.0
+        } else if index == last {
+            1.0
+        } else {
+            x.clamp(self.points[index - 1].0 + MIN_GAP, self.points[index + 1].0 - MIN_GAP)
+        };
+        if let Some(point) = self.points.get_mut(index) {
+            *point = (x, y.clamp(0.0, 1.0));
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index > 0 && index < self.points.len() - 1 {
+            self.points.remove(index);
+        }
+    }
+
+    // index of the point within `radius` of (x, y), if any
+    pub fn hit_test(&self, x: f32, y: f32, radius: f32) -> Option<usize> {
+        self.points
+            .iter()
+            .enumerate()
+            .map(|(i, &(px, py))| (i, (px - x).hypot(py - y)))
+            .filter(|&(_, distance)| distance <= radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct CurveSet {
+    pub master: Curve,
+    pub red: Curve,
+    pub green: Curve,
+    pub blue: Curve,
+}
+
+impl CurveSet {
+    pub fn curve(&self, channel: CurveChannel) -> &Curve {
+        match channel {
+            CurveChannel::Master => &self.master,
+            CurveChannel::Red => &self.red,
+            CurveChannel::Green => &self.green,
+            CurveChannel::Blue => &self.blue,
+        }
+    }
+
+    pub fn curve_mut(&mut self, channel: CurveChannel) -> &mut Curve {
+        match channel {
+            CurveChannel::Master => &mut self.master,
+            CurveChannel::Red => &mut self.red,
+            CurveChannel::Green => &mut self.green,
+            CurveChannel::Blue => &mut self.blue,
+        }
+    }
+
+    // half float RGBA row of LUT_SIZE texels: the levels go first, then the master curve, then each
+    // channel's own curve. Half floats keep 16-bit sources from being stepped to 256 output levels
+    pub fn bake(&self, levels: &Levels) -> Vec<f16> {
+        let mut lut = Vec::with_capacity(LUT_SIZE * 4);
+        for i in 0..LUT_SIZE {
+            let leveled = levels.apply(i as f32 / (LUT_SIZE - 1) as f32);
+            for (curve, x) in [&self.red, &self.green, &self.blue].into_iter().zip(leveled) {
+                let y = curve.evaluate(self.master.evaluate(x));
+                lut.push(f16::from_f32(y));
+            }
+            lut.push(f16::ONE);
+        }
+        lut
+    }
+
+    // one curve per line: `<channel> x,y x,y ...`
+    pub fn serialize(&self) -> String {
+        CurveChannel::ALL
+            .iter()
+            .map(|&channel| {
+                let points: Vec<String> = self
+                    .curve(channel)
+                    .points
+                    .iter()
+                    .map(|(x, y)| format!("{},{}", x, y))
+                    .collect();
+                format!("{} {}", channel.name(), points.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn deserialize(text: &str) -> Result<CurveSet, String> {
+        let mut set = CurveSet::default();
+        for (line_no, line) in text.lines().enumerate() {
+            let mut parts = line.split_whitespace();
+            let Some(name) = parts.next() else {
+                continue;
+            };
+            let channel = CurveChannel::ALL
+                .into_iter()
+                .find(|channel| channel.name() == name)
+                .ok_or_else(|| format!("line {}: unknown channel '{}'", line_no + 1, name))?;
+            let mut points = Vec::new();
+            for part in parts {
+                let parsed = part
+                    .split_once(',')
+                    .and_then(|(x, y)| Some((x.parse::<f32>().ok()?, y.parse::<f32>().ok()?)));
+                let (x, y) = parsed
+                    .ok_or_else(|| format!("line {}: expected x,y, got '{}'", line_no + 1, part))?;
+                points.push((x.clamp(0.0, 1.0), y.clamp(0.0, 1.0)));
+            }
+            points.sort_by(|a, b| a.0.total_cmp(&b.0));
+            if points.len() < 2 || points[0].0 != 0.0 || points[points.len() - 1].0 != 1.0 {
+                return Err(format!("line {}: the curve must start at x=0 and end at x=1", line_no + 1));
+            }
+            if points.windows(2).any(|w| w[1].0 - w[0].0 < MIN_GAP) {
+                return Err(format!("line {}: points must be at least 1/{} apart in x", line_no + 1, LUT_SIZE));
+            }
+            set.curve_mut(channel).points = points;
+        }
+        Ok(set)
+    }
+}
+
+// presets every session starts with: (name, master curve points)
+pub fn builtin_presets() -> Vec<(String, CurveSet)> {
+    let master = |points: &[(f32, f32)]| CurveSet {
+        master: Curve {
+            points: points.to_vec(),
+        },
+        ..CurveSet::default()
+    };
+    vec![
+        (String::from("Linear"), CurveSet::default()),
+        (
+            String::from("Medium contrast"),
+            master(&[(0.0, 0.0), (0.25, 0.2), (0.75, 0.8), (1.0, 1.0)]),
+        ),
+        (
+            String::from("Strong contrast"),
+            master(&[(0.0, 0.0), (0.25, 0.15), (0.75, 0.85), (1.0, 1.0)]),
+        ),
+        (
+            String::from("Fade"),
+            master(&[(0.0, 0.1), (0.5, 0.5), (1.0, 0.92)]),
+        ),
+        (
+            String::from("Negative"),
+            master(&[(0.0, 1.0), (1.0, 0.0)]),
+        ),
+    ]
+}
+
+// setting the presets are kept under between sessions
+pub const PRESETS_SETTING: &str = "curve-presets";
+
+// presets one after the other, each a `preset <name>` line followed by its serialized curves
+pub fn serialize_presets(presets: &[(String, CurveSet)]) -> String {
+    presets
+        .iter()
+        .map(|(name, curves)| format!("preset {}\n{}", name, curves.serialize()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn deserialize_presets(text: &str) -> Result<Vec<(String, CurveSet)>, String> {
+    let mut presets: Vec<(String, String)> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix("preset "), presets.last_mut()) {
+            (Some(name), _) => presets.push((name.trim().to_string(), String::new())),
+            (None, Some((_, curves))) => {
+                curves.push_str(line);
+                curves.push('\n');
+            }
+            (None, None) if line.trim().is_empty() => {}
+            (None, None) => return Err(String::from("expected a 'preset <name>' line first")),
+        }
+    }
+    presets
+        .into_iter()
+        .map(|(name, curves)| {
+            let set = CurveSet::deserialize(&curves).map_err(|err| format!("preset '{}': {}", name, err))?;
+            Ok((name, set))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserting_between_close_points_is_refused() {
+        let mut curve = Curve::default();
+        let index = curve.insert(0.2, 0.5);
+        // dragged onto the left edge, right next to the end point
+        curve.move_point(index, 0.0, 0.5);
+        let points = curve.points.clone();
+        assert_eq!(curve.insert(0.0, 0.9), 0);
+        assert_eq!(curve.points, points);
+        // with room left a point is added
+        assert_eq!(curve.insert(0.5, 0.9), 2);
+        assert_eq!(curve.points.len(), 4);
+    }
+
+    #[test]
+    fn deserialize_rejects_points_closer_than_a_lut_entry() {
+        assert!(CurveSet::deserialize("master 0,0 0.5,0.4 0.5,0.6 1,1").is_err());
+        assert!(CurveSet::deserialize("master 0,0 0.001,0.4 1,1").is_err());
+        let set = CurveSet::deserialize("master 0,0 0.5,0.4 1,1").unwrap();
+        assert_eq!(set.master.points, vec![(0.0, 0.0), (0.5, 0.4), (1.0, 1.0)]);
+    }
+
+    #[test]
+    fn presets_round_trip() {
+        let mut presets = builtin_presets();
+        let mut custom = CurveSet::default();
+        custom.red.points = vec![(0.0, 0.1), (0.4, 0.6), (1.0, 1.0)];
+        presets.push((String::from("Warm shadows"), custom));
+
+        let restored = deserialize_presets(&serialize_presets(&presets)).unwrap();
+        assert_eq!(restored, presets);
+        assert!(deserialize_presets("master 0,0 1,1").is_err());
+        assert!(deserialize_presets("preset Broken\nmaster 0,0 0.5").is_err());
+    }
+}
@@ -1,41 +1,113 @@
-use crate::state::app_state::HSVState;
-use crate::utils::utils::{align_to_256, save_file_via_dialog};
-use dioxus::hooks::use_context;
-use dioxus::html::output;
-use dioxus::html::u::is;
+use crate::state::adjustments::AdjustmentStack;
+use crate::state::brush::{BrushSettings, DirtyRect, PaintLayer, StrokePatch};
+use crate::state::curves::CurveSet;
+use crate::state::filters::FilterStack;
+use crate::state::histogram::HistogramReadback;
+use crate::state::levels::Levels;
+use crate::state::offscreen::{Capabilities, ImageTextures, PendingRender, Renderer, TiledRender, request_device};
+use crate::state::texture_cache::{DEFAULT_TEXTURE_BUDGET_MB, MB, TextureCache};
+use crate::state::tiles::fit_within;
+use crate::state::transform::Transform;
 use image::DynamicImage;
 use image::GenericImageView;
-use image::{ImageBuffer, Rgba};
-use std::collections::VecDeque;
+use image::RgbaImage;
+use std::collections::{HashMap, VecDeque};
 use std::sync::mpsc::channel;
 use std::sync::mpsc::{self, RecvError};
 use std::sync::mpsc::{Receiver, Sender};
-use std::thread::spawn;
+use crate::utils::platform::log;
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen::JsCast;
-use wasm_bindgen_futures::spawn_local;
-use web_sys::*;
-use wgpu::util::DeviceExt;
+#[cfg(target_arch = "wasm32")]
+use web_sys::{HtmlCanvasElement, window};
 use wgpu::*;
 
-#[repr(C)]
-#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
-struct Globals {
-    pub hsv: [f32; 3], //12bytes data
-    pub _pad: f32,     //4bytes padding for alignment
+// background of the page around the image, #212529, for windows the renderer shares with the UI
+const PAGE_BACKGROUND: wgpu::Color = wgpu::Color {
+    r: 0.01521,
+    g: 0.01714,
+    b: 0.02122,
+    a: 1.0,
+};
+// checkerboard cells in CSS pixels, matching the one behind the browser canvas
+const CHECKER_SIZE: f64 = 8.0;
+
+// where the image is shown in a window it shares with the UI, in physical pixels
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoardPlacement {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub pixel_ratio: f64,
 }
 
-impl Globals {
-    pub fn new(h: f32, s: f32, v: f32) -> Self {
+// the part of a board inside the window
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoardClip {
+    pub viewport: [f32; 4],     // x, y, width and height in physical pixels
+    pub range: [(f64, f64); 2], // normalized canvas position of its top left and bottom right
+}
+
+impl BoardPlacement {
+    // the part of the board inside a `window` sized target; None when the board is scrolled
+    // out of view
+    pub fn clip(&self, window: (u32, u32)) -> Option<BoardClip> {
+        let x0 = self.x.max(0.0);
+        let y0 = self.y.max(0.0);
+        let x1 = (self.x + self.width).min(window.0 as f64);
+        let y1 = (self.y + self.height).min(window.1 as f64);
+        if x1 - x0 < 1.0 || y1 - y0 < 1.0 {
+            return None;
+        }
+        let viewport = [x0 as f32, y0 as f32, (x1 - x0) as f32, (y1 - y0) as f32];
+        let range = [
+            ((x0 - self.x) / self.width, (y0 - self.y) / self.height),
+            ((x1 - self.x) / self.width, (y1 - self.y) / self.height),
+        ];
+        Some(BoardClip { viewport, range })
+    }
+}
+
+// restricts a uv matrix over the whole canvas to the canvas range `[from, to]`
+pub fn uv_sub_range(matrix: [[f32; 4]; 2], [from, to]: [(f64, f64); 2]) -> [[f32; 4]; 2] {
+    let (sx, sy) = ((to.0 - from.0) as f32, (to.1 - from.1) as f32);
+    let (ox, oy) = (from.0 as f32, from.1 as f32);
+    matrix.map(|[a, b, c, d]| [a * sx, b * sy, a * ox + b * oy + c, d])
+}
+
+// crop bounds in source image pixels
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CropRect {
+    pub fn full(dimensions: (u32, u32)) -> Self {
         Self {
-            hsv: [h, s, v],
-            _pad: 0.0,
+            x: 0,
+            y: 0,
+            width: dimensions.0,
+            height: dimensions.1,
         }
     }
 }
-#[derive(Clone)]
-pub struct Filesave_config {
-    pub path: String,
+
+// everything an image is rendered with besides its pixels and paint layer
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct RenderSettings {
+    pub crop: Option<CropRect>,
+    pub transform: Transform,
+    pub filters: FilterStack,
+    pub adjustments: AdjustmentStack,
+    pub levels: Levels,
+    pub curves: CurveSet,
 }
+
+// presents a Renderer on the page's canvas or the desktop window, holding the open images
 pub struct State {
     tx: Sender<DynamicImage>,
     rx: Receiver<DynamicImage>,
@@ -43,338 +115,351 @@ pub struct State {
     pub img_vec: VecDeque<DynamicImage>,
     pub img_index: u32,
     pub surface: wgpu::Surface<'static>,
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
     pub is_surface_configured: bool,
-    pub render_pipeline: wgpu::RenderPipeline,
-    pub vertex_buffer: wgpu::Buffer,
-    pub index_buffer: wgpu::Buffer,
-    pub num_indices: u32,
-    pub diffuse_bind_group: wgpu::BindGroup,
-    pub globals_buffer: wgpu::Buffer,
-    pub output_buffer: wgpu::Buffer,
+    pub renderer: Renderer,
+    pub paint_layers: HashMap<usize, PaintLayer>,
+    pub analysis_texture: Option<wgpu::Texture>,
+    pub board: Option<BoardPlacement>, // set when the surface is a whole window rather than the image
+    pub textures: TextureCache<ImageTextures>, // uploaded images by index, so switching back does not upload again
+    evicted: Vec<usize>,                       // images dropped from `textures` since the UI last asked
+    #[cfg(target_arch = "wasm32")]
+    instance: wgpu::Instance,
+    #[cfg(target_arch = "wasm32")]
+    canvas: Option<HtmlCanvasElement>, // the canvas `surface` draws to
 }
 
 impl State {
-    pub fn load_image_to_gpu(&mut self) {
-        let diffuse_image = self.img_vec.get(self.img_index as usize).unwrap();
-        let diffuse_rgba = diffuse_image.to_rgba8();
-        let dimensions = diffuse_image.dimensions();
+    pub fn source_dimensions(&self) -> (u32, u32) {
+        self.img_vec.get(self.img_index as usize).unwrap().dimensions()
+    }
 
-        self.config.width = dimensions.0;
-        self.config.height = dimensions.1;
+    // size of the rendered (and exported) image after transforming and cropping
+    pub fn output_dimensions(&self) -> (u32, u32) {
+        match self.renderer.crop {
+            Some(rect) => (rect.width, rect.height),
+            None => self.renderer.transform.oriented_dimensions(self.source_dimensions()),
+        }
+    }
 
-        let texture_size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
-        };
-        let diffuse_texture = self.device.create_texture(&wgpu::TextureDescriptor {
-            size: texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: self.config.format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            label: Some("diffuse_texture"),
-            view_formats: &[],
-        });
+    // normalized source coordinates at normalized canvas position `p`
+    fn canvas_to_uv(&self, p: (f64, f64)) -> (f64, f64) {
+        self.renderer.transform.canvas_to_uv(self.renderer.crop, p, self.source_dimensions())
+    }
 
-        self.queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: &diffuse_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &diffuse_rgba,
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            texture_size,
-        );
-
-        let diffuse_texture_view =
-            diffuse_texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-        // reuse sampler, layout
-        self.diffuse_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &self.render_pipeline.get_bind_group_layout(0),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(
-                        &self
-                            .device
-                            .create_sampler(&wgpu::SamplerDescriptor::default()),
-                    ),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: self.globals_buffer.as_entire_binding(),
-                },
-            ],
-            label: Some("updated_diffuse_bind_group"),
-        });
+    // places the image inside a surface of `surface_size`, which then follows the window instead of the image
+    pub fn set_board(&mut self, placement: BoardPlacement, surface_size: (u32, u32)) {
+        self.board = Some(placement);
+        let (width, height) = (surface_size.0.max(1), surface_size.1.max(1));
+        if self.config.width != width || self.config.height != height {
+            self.config.width = width;
+            self.config.height = height;
+            self.surface.configure(&self.renderer.device, &self.config);
+        }
     }
 
-    pub fn draw_to_texture(&mut self, filesave_config: Filesave_config) {
-        self.draw(true, Some(filesave_config.clone()));
-        console::log_1(&format!("File saved to: {}", filesave_config.path).into());
-        self.draw(false, None); // rerender
+    pub fn set_geometry(&mut self, crop: Option<CropRect>, transform: Transform) {
+        self.renderer.crop = crop;
+        self.renderer.transform = transform;
+        if self.board.is_some() {
+            return;
+        }
+        // a canvas past the texture limit shows the image downscaled
+        let (width, height) = fit_within(self.output_dimensions(), self.renderer.max_texture_dimension);
+        if self.config.width != width || self.config.height != height {
+            self.config.width = width;
+            self.config.height = height;
+            self.surface.configure(&self.renderer.device, &self.config);
+        }
     }
 
-    pub fn draw(&mut self, update_texture: bool, filesave_config: Option<Filesave_config>) {
-        if update_texture {
-            self.load_image_to_gpu(); // only use this when image is changed
+    pub fn load_image_to_gpu(&mut self) {
+        let index = self.img_index as usize;
+        // a cached image only needs its textures bound again, strokes went to its paint texture
+        if let Some(textures) = self.textures.get(index) {
+            self.renderer.bind_image_textures(&textures);
+            return;
+        }
+        let textures = self.renderer.create_image_textures(&self.img_vec[index]);
+        self.renderer.bind_image_textures(&textures);
+        if let Some(layer) = self.paint_layers.get(&index) {
+            let (width, height) = layer.pixels.dimensions();
+            self.renderer.upload_paint(&layer.pixels, DirtyRect { x: 0, y: 0, width, height });
         }
+        let bytes = textures.bytes();
+        let evicted = self.textures.insert(index, textures, bytes);
+        self.evicted.extend(evicted);
+    }
 
-        // read hsv values
-        let hue = use_context::<HSVState>().hue;
-        let sat = use_context::<HSVState>().saturation;
-        let val = use_context::<HSVState>().value;
+    // bytes held by cached image textures and the budget they are kept under
+    pub fn texture_memory(&self) -> (u64, u64) {
+        (self.textures.used(), self.textures.budget())
+    }
 
-        let globals = Globals::new(hue(), sat(), val());
-        self.queue
-            .write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+    pub fn set_texture_budget(&mut self, bytes: u64) {
+        let evicted = self.textures.set_budget(bytes);
+        self.evicted.extend(evicted);
+    }
 
-        // start rendering the new frame
+    // indices of the images whose textures were evicted since the last call
+    pub fn take_evictions(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.evicted)
+    }
 
-        let mut frame = match self.surface.get_current_texture() {
-            Ok(frame) => frame,
-            Err(err) => {
-                console::log_1(&format!("Surface error: {:?}", err).into());
-                return;
-            }
+    // renders the adjusted image offscreen and bins it on the GPU; None without compute shaders
+    pub fn histogram(&mut self) -> Option<HistogramReadback> {
+        let renderer = &self.renderer;
+        let pass = renderer.histogram_pass.as_ref()?;
+        let (width, height) = fit_within(self.output_dimensions(), renderer.max_texture_dimension);
+        let stale = match &self.analysis_texture {
+            Some(texture) => texture.width() != width || texture.height() != height,
+            None => true,
         };
-
-        let render_target_texture: &Texture;
-        let temp_texture: Texture;
-        let mut frame_texture = frame.texture.clone();
-        let width = self.config.width;
-        let height = self.config.height;
-
-        if filesave_config.is_some() {
-            temp_texture = self.device.create_texture(&wgpu::TextureDescriptor {
-                label: Some("Filesaver Texture"),
+        if stale {
+            self.analysis_texture = Some(renderer.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Analysis Texture"),
                 size: wgpu::Extent3d {
                     width,
                     height,
                     depth_or_array_layers: 1,
                 },
-                format: wgpu::TextureFormat::Rgba8Unorm,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                format: renderer.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                view_formats: &[self.config.format],
+                view_formats: &[],
+            }));
+        }
+        let view = self
+            .analysis_texture
+            .as_ref()
+            .unwrap()
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        renderer.write_globals(renderer.uv_matrix(), false, renderer.format, 0.0);
+        let mut encoder = renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Histogram Encoder"),
             });
-            render_target_texture = &temp_texture;
-        } else {
-            render_target_texture = &frame.texture;
+        renderer.encode_render_pass(&mut encoder, &view, renderer.format, wgpu::Color::TRANSPARENT, None);
+        // an sRGB target decodes to linear on load, the bins are of encoded values
+        let linear = renderer.format.is_srgb();
+        let readback = pass.encode(&renderer.device, &mut encoder, &view, (width, height), linear);
+        renderer.queue.submit(Some(encoder.finish()));
+        Some(HistogramReadback::new(&renderer.device, readback))
+    }
+
+    // presents the current image rendered with `settings`
+    pub fn draw(&mut self, update_texture: bool, settings: &RenderSettings) {
+        if update_texture {
+            self.load_image_to_gpu(); // only use this when image is changed
         }
 
-        let view = render_target_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.set_geometry(settings.crop, settings.transform);
+        self.renderer.apply(settings);
 
-        let mut encoder = self
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(err) => {
+                log(format!("Surface error: {:?}", err));
+                return;
+            }
+        };
+        let renderer = &self.renderer;
+        let mut encoder = renderer
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
+        let premultiply = self.config.alpha_mode == wgpu::CompositeAlphaMode::PreMultiplied;
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        match self.board {
+            // only the visible part of the board is drawn, wgpu rejects viewports outside the target
+            Some(board) => match board.clip((self.config.width, self.config.height)) {
+                Some(clip) => {
+                    let matrix = uv_sub_range(renderer.uv_matrix(), clip.range);
+                    let checker = (CHECKER_SIZE * board.pixel_ratio) as f32;
+                    renderer.write_globals(matrix, premultiply, self.config.format, checker);
+                    renderer.encode_render_pass(&mut encoder, &view, self.config.format, PAGE_BACKGROUND, Some(clip.viewport));
+                }
+                // scrolled out of view, only the background is left
+                None => clear_target(&mut encoder, &view, PAGE_BACKGROUND),
+            },
+            None => {
+                renderer.write_globals(renderer.uv_matrix(), premultiply, self.config.format, 0.0);
+                renderer.encode_render_pass(&mut encoder, &view, self.config.format, wgpu::Color::TRANSPARENT, None);
+            }
+        }
+        renderer.queue.submit(Some(encoder.finish()));
+        frame.present();
+    }
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    depth_slice: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.6,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    // renders image `index` with `settings` into its own readback, leaving the surface alone.
+    // Images past the texture limit are rendered in tiles on a renderer of their own once awaited
+    pub fn render_offscreen(&mut self, index: usize, settings: &RenderSettings) -> Result<PendingRender, String> {
+        let Some(image) = self.img_vec.get(index) else {
+            return Err(format!("no image at index {}", index));
+        };
+        if self.renderer.check_size(image.dimensions()).is_err() {
+            return Ok(PendingRender::Tiled(Box::new(TiledRender {
+                renderer: self.renderer.sibling(),
+                image: image.clone(),
+                paint: self.paint_layers.get(&index).map(|layer| layer.pixels.clone()),
+                settings: settings.clone(),
+            })));
         }
-        console::log_1(&format!("Prepared frame of size: {}x{}", width, height).into());
-        let unpadded_bytes_per_row = 4 * width;
-        let padded_bytes_per_row = align_to_256(unpadded_bytes_per_row);
-        console::log_1(
-            &format!(
-                "unpadded_bytes_per_row: {}, padded_bytes_per_row: {}",
-                unpadded_bytes_per_row, padded_bytes_per_row
-            )
-            .into(),
-        );
-        if filesave_config.is_some() {
-            let buffer_size = height * padded_bytes_per_row; // for RGBA8
-            self.output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-                label: Some("Output Buffer"),
-                size: buffer_size as u64,
-                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-                mapped_at_creation: false,
-            });
 
-            encoder.copy_texture_to_buffer(
-                wgpu::TexelCopyTextureInfo {
-                    texture: &render_target_texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d::ZERO,
-                    aspect: wgpu::TextureAspect::All,
-                },
-                wgpu::TexelCopyBufferInfo {
-                    buffer: &self.output_buffer,
-                    layout: wgpu::TexelCopyBufferLayout {
-                        offset: 0,
-                        bytes_per_row: Some(padded_bytes_per_row),
-                        rows_per_image: Some(height),
-                    },
-                },
-                wgpu::Extent3d {
-                    width,
-                    height,
-                    depth_or_array_layers: 1,
-                },
-            );
-
-            self.queue.submit(Some(encoder.finish()));
-            let buffer_length = self.output_buffer.size();
-            console::log_1(
-                &format!(
-                    "Buffer length: {}, should be: {}",
-                    buffer_length, buffer_size
-                )
-                .into(),
-            );
-            let buffer_slice = self.output_buffer.slice(..);
-
-            let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
-
-            let output_buffer_clone = self.output_buffer.clone();
-            let device_clone = self.device.clone();
-
-            buffer_slice.map_async(wgpu::MapMode::Read, move |res| {
-                sender.send(res).unwrap();
-            });
+        self.img_index = index as u32;
+        self.load_image_to_gpu();
+        self.renderer.apply(settings);
 
-            spawn_local(async move {
-                if receiver.receive().await.unwrap().is_ok() {
-                    let buffer_slice = output_buffer_clone.slice(..);
-                    let data = buffer_slice.get_mapped_range();
-                    let _ = device_clone.poll(wgpu::PollType::Wait);
-                    let mut image_bytes = Vec::with_capacity((width * height * 4) as usize);
-                    for y in 0..height {
-                        let row_start = (y * padded_bytes_per_row) as usize;
-                        let row_end = row_start + unpadded_bytes_per_row as usize;
-                        let row = &data[row_start..row_end];
-                        image_bytes.extend_from_slice(row);
-                    }
-                    let buffer =
-                        ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, image_bytes).unwrap();
-
-                    save_file_via_dialog(
-                        buffer.to_vec(),
-                        width,
-                        height,
-                        filesave_config.unwrap().path,
-                    );
-
-                    drop(data);
-                    drop(buffer);
-
-                    output_buffer_clone.unmap();
-                }
+        let texture = self.renderer.render_uploaded();
+        let encoder = self
+            .renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
             });
-        } else {
-            self.queue.submit(Some(encoder.finish()));
-            frame.present();
-        }
+        Ok(PendingRender::Whole(self.renderer.submit_readback(encoder, &texture)))
     }
 
     pub fn sender(&self) -> Sender<DynamicImage> {
         self.tx.clone()
     }
 
-    pub async fn receive(&mut self) {
+    pub fn receive(&mut self) {
         loop {
             match self.rx.try_recv() {
                 Err(_) => {
-                    console::log_1(&"Recieving failed / stopped".into());
+                    log("Recieving failed / stopped");
                     return;
                 }
                 Ok(input_file) => {
-                    console::log_1(&"File recieved".into());
+                    log("File recieved");
                     self.img_vec.push_back(input_file);
                 }
             };
         }
     }
+    pub fn replace_image(&mut self, i: usize, img: DynamicImage) {
+        // a resize hands over the paint layer for the new size through `set_paint_layer`,
+        // one that still does not fit would be uploaded out of bounds
+        if self.paint_layers.get(&i).is_some_and(|layer| layer.pixels.dimensions() != img.dimensions()) {
+            self.paint_layers.remove(&i);
+        }
+        self.textures.remove(i);
+        if let Some(slot) = self.img_vec.get_mut(i) {
+            *slot = img;
+            log(format!("Replaced image at index: {}", i));
+        }
+    }
+
+    // maps a normalized position on the canvas to source image pixels
+    pub fn canvas_to_source(&self, u: f64, v: f64) -> (f64, f64) {
+        let (width, height) = self.source_dimensions();
+        let (x, y) = self.canvas_to_uv((u, v));
+        (x * width as f64, y * height as f64)
+    }
+
+    pub fn paint_begin(&mut self, pos: (f64, f64), settings: BrushSettings) {
+        let index = self.img_index as usize;
+        let (width, height) = self.source_dimensions();
+        let layer = self
+            .paint_layers
+            .entry(index)
+            .or_insert_with(|| PaintLayer::new(width, height));
+        let dirty = layer.begin_stroke((pos.0 as f32, pos.1 as f32), settings);
+        if let Some(rect) = dirty {
+            self.renderer.upload_paint(&self.paint_layers[&index].pixels, rect);
+        }
+    }
+
+    pub fn paint_to(&mut self, pos: (f64, f64)) {
+        let index = self.img_index as usize;
+        let dirty = match self.paint_layers.get_mut(&index) {
+            Some(layer) => layer.stroke_to((pos.0 as f32, pos.1 as f32)),
+            None => None,
+        };
+        if let Some(rect) = dirty {
+            self.renderer.upload_paint(&self.paint_layers[&index].pixels, rect);
+        }
+    }
+
+    // writes a stroke's undo or redo pixels into the paint layer of `index`
+    pub fn apply_paint_patch(&mut self, index: usize, rect: DirtyRect, pixels: &RgbaImage) {
+        let (width, height) = match self.img_vec.get(index) {
+            Some(img) => img.dimensions(),
+            None => return,
+        };
+        // a resize since the stroke leaves the patch nowhere to go
+        if rect.x + rect.width > width || rect.y + rect.height > height {
+            return;
+        }
+        let layer = self
+            .paint_layers
+            .entry(index)
+            .or_insert_with(|| PaintLayer::new(width, height));
+        layer.write_patch(rect, pixels);
+        if index == self.img_index as usize {
+            self.renderer.upload_paint(&layer.pixels, rect);
+        } else {
+            // uploaded with the restored layer when the image is shown again
+            self.textures.remove(index);
+        }
+    }
+
+    pub fn paint_layer(&self, index: usize) -> Option<&RgbaImage> {
+        self.paint_layers.get(&index).map(|layer| &layer.pixels)
+    }
+
+    // swaps in the whole paint layer of `index`, as a resize and its undo do
+    pub fn set_paint_layer(&mut self, index: usize, pixels: Option<RgbaImage>) {
+        match pixels {
+            Some(pixels) => self.paint_layers.insert(index, PaintLayer::from_pixels(pixels)),
+            None => self.paint_layers.remove(&index),
+        };
+        // uploaded with the image once it is re-uploaded
+        self.textures.remove(index);
+    }
+
+    // finishes the stroke and returns what it changed, for undo
+    pub fn paint_end(&mut self) -> Option<StrokePatch> {
+        self.paint_layers
+            .get_mut(&(self.img_index as usize))
+            .and_then(|layer| layer.end_stroke())
+    }
+
     pub fn set_index(&mut self, i: u32) {
         if i < self.img_vec.len() as u32 {
             self.img_index = i;
-            console::log_1(&format!("Set index to: {}", i).into());
+            log(format!("Set index to: {}", i));
         } else {
-            console::log_1(&format!("The index: {}, vec size: {}", i, self.img_vec.len()).into());
-            console::log_1(&"The index is out of bounds".into());
+            log(format!("The index: {}, vec size: {}", i, self.img_vec.len()));
+            log("The index is out of bounds");
         }
     }
 
-    pub async fn new(initial_dyn_image: &DynamicImage) -> State {
-        let (tx, rx): (Sender<DynamicImage>, Receiver<DynamicImage>) = mpsc::channel();
-        let img_index: u32 = 0;
-        let mut img_vec = VecDeque::<DynamicImage>::new();
-        img_vec.push_back(initial_dyn_image.clone());
-        let diffuse_rgba = img_vec.back().unwrap().to_rgba8();
-        use image::GenericImageView;
-        let dimensions = img_vec.back().unwrap().dimensions();
-        let texture_size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            // All textures are stored as 3D, we represent our 2D texture
-            // by setting depth to 1.
-            depth_or_array_layers: 1,
-        };
-
+    // fails when the browser offers no adapter, the editor then previews on the CPU
+    #[cfg(target_arch = "wasm32")]
+    pub async fn new(initial_dyn_image: &DynamicImage) -> Result<State, String> {
         let window = window().unwrap();
         let document = window.document().unwrap();
 
         loop {
             match document.get_element_by_id("image-board").is_some() {
                 false => {
-                    console::log_1(&"Waiting for canvas".into());
+                    log("Waiting for canvas");
                 }
                 true => {
-                    console::log_1(&"Found canvas".into());
+                    log("Found canvas");
                     break;
                 }
             }
         }
 
-        let canvas = document
-            .get_element_by_id("image-board")
-            .unwrap()
-            .dyn_into::<HtmlCanvasElement>()
-            .unwrap();
+        let canvas = board_canvas().unwrap();
 
         let width = canvas.width();
         let height = canvas.height();
@@ -384,9 +469,64 @@ impl State {
             ..InstanceDescriptor::default()
         });
 
-        let surface_target = SurfaceTarget::Canvas(canvas);
+        let surface_target = SurfaceTarget::Canvas(canvas.clone());
+
+        let surface = instance
+            .create_surface(surface_target)
+            .map_err(|e| format!("No surface on the canvas: {e}"))?;
+
+        let mut state = Self::with_surface(&instance, surface, (width, height), initial_dyn_image).await?;
+        state.canvas = Some(canvas);
+        Ok(state)
+    }
+
+    // draws to the board's canvas again once it was re-created, coming back from the gallery or
+    // after loading more images; the device and cached textures are kept
+    #[cfg(target_arch = "wasm32")]
+    pub fn attach_canvas(&mut self) -> Result<(), String> {
+        let canvas = board_canvas().ok_or_else(|| String::from("No canvas on the page"))?;
+        if self.canvas.as_ref() == Some(&canvas) {
+            return Ok(());
+        }
+        self.surface = self
+            .instance
+            .create_surface(SurfaceTarget::Canvas(canvas.clone()))
+            .map_err(|e| format!("No surface on the canvas: {e}"))?;
+        self.surface.configure(&self.renderer.device, &self.config);
+        self.canvas = Some(canvas);
+        Ok(())
+    }
+
+    // draws to the whole native `window` of `size` physical pixels, under its transparent webview; see set_board
+    #[cfg(all(not(target_arch = "wasm32"), feature = "desktop"))]
+    pub async fn new(
+        window: impl Into<SurfaceTarget<'static>>,
+        size: (u32, u32),
+        initial_dyn_image: &DynamicImage,
+    ) -> Result<State, String> {
+        let instance = wgpu::Instance::new(&InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..InstanceDescriptor::default()
+        });
+
+        let surface = instance
+            .create_surface(window)
+            .map_err(|e| format!("No surface on the window: {e}"))?;
 
-        let surface = unsafe { instance.create_surface(surface_target).unwrap() };
+        Self::with_surface(&instance, surface, (size.0.max(1), size.1.max(1)), initial_dyn_image).await
+    }
+
+    // everything past the surface is the same on every platform
+    #[cfg_attr(not(any(target_arch = "wasm32", feature = "desktop")), allow(dead_code))]
+    async fn with_surface(
+        instance: &wgpu::Instance,
+        surface: wgpu::Surface<'static>,
+        (width, height): (u32, u32),
+        initial_dyn_image: &DynamicImage,
+    ) -> Result<State, String> {
+        let (tx, rx): (Sender<DynamicImage>, Receiver<DynamicImage>) = mpsc::channel();
+        let mut img_vec = VecDeque::<DynamicImage>::new();
+        img_vec.push_back(initial_dyn_image.clone());
 
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
@@ -395,21 +535,9 @@ impl State {
                 force_fallback_adapter: false,
             })
             .await
-            .expect("No adapter found");
-
-        //Mozilla Firefox fix
-        let limits = adapter.limits();
-
-        let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::empty(),
-                required_limits: limits.clone(),
-                label: None,
-                trace: wgpu::Trace::Off,
-                memory_hints: wgpu::MemoryHints::Performance,
-            })
-            .await
-            .unwrap();
+            .map_err(|e| format!("No adapter found: {e}"))?;
+
+        let (device, queue) = request_device(&adapter).await?;
 
         // keep a list of preferred formats here
         let formats = surface.get_capabilities(&adapter).formats;
@@ -425,9 +553,17 @@ impl State {
             }
         }
 
-        console::log_1(
-            &format!("Formats: {:?}", surface.get_capabilities(&adapter).formats).into(),
-        );
+        log(format!("Formats: {:?}", surface.get_capabilities(&adapter).formats));
+
+        // transparent pixels show the page (and its checkerboard) through the canvas
+        let alpha_modes = surface.get_capabilities(&adapter).alpha_modes;
+        let alpha_mode = if alpha_modes.contains(&wgpu::CompositeAlphaMode::PreMultiplied) {
+            wgpu::CompositeAlphaMode::PreMultiplied
+        } else if alpha_modes.contains(&wgpu::CompositeAlphaMode::PostMultiplied) {
+            wgpu::CompositeAlphaMode::PostMultiplied
+        } else {
+            wgpu::CompositeAlphaMode::Auto
+        };
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -435,257 +571,104 @@ impl State {
             width,
             height,
             present_mode: wgpu::PresentMode::Fifo,
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            alpha_mode,
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
 
         surface.configure(&device, &config);
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
-
-        let diffuse_texture = device.create_texture(&wgpu::TextureDescriptor {
-            size: texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-
-            format: pref_format,
-
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            label: Some("diffuse_texture"),
-
-            view_formats: &[],
-        });
-        queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: &diffuse_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &diffuse_rgba,
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            texture_size,
-        );
-        let diffuse_texture_view =
-            diffuse_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let diffuse_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
-
-        let hue = use_context::<HSVState>().hue;
-        let sat = use_context::<HSVState>().saturation;
-        let val = use_context::<HSVState>().value;
-
-        let globals = Globals::new(hue(), sat(), val());
-
-        let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("globals buffer"),
-            contents: bytemuck::bytes_of(&globals),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: globals_buffer.as_entire_binding(),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
-
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-        let num_indices = INDICES.len() as u32;
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[self::Vertex::desc()],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, // 2.
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-
-                unclipped_depth: false,
-
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState {
-                count: 1,                         // 2.
-                mask: !0,                         // 3.
-                alpha_to_coverage_enabled: false, // 4.
-            },
-            multiview: None, // 5.
-            cache: None,     // 6.
-        });
-
-        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Output Buffer"),
-            size: 0 as u64,
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-            mapped_at_creation: false,
-        });
+        let capabilities = Capabilities::of(&adapter);
+        if !capabilities.half_float_targets {
+            log("No half float render targets, 16-bit images are edited in 8 bits");
+        }
+        if !capabilities.compute_shaders {
+            log("No compute shader support, histogram disabled");
+        }
+        let mut renderer = Renderer::with_device(device, queue, config.format, capabilities);
+        renderer.upload(initial_dyn_image);
 
-        State {
+        Ok(State {
             tx: tx,
             rx: rx,
             skips: 0,
             img_vec: img_vec,
-            img_index: img_index,
+            img_index: 0,
             surface: surface,
-            device: device,
-            queue: queue,
             config: config,
             is_surface_configured: false,
-            render_pipeline: render_pipeline,
-            vertex_buffer: vertex_buffer,
-            index_buffer: index_buffer,
-            num_indices: num_indices,
-            diffuse_bind_group: diffuse_bind_group,
-            globals_buffer: globals_buffer.clone(),
-            output_buffer: output_buffer,
-        }
+            renderer,
+            paint_layers: HashMap::new(),
+            analysis_texture: None,
+            board: None,
+            textures: TextureCache::new(DEFAULT_TEXTURE_BUDGET_MB * MB),
+            evicted: Vec::new(),
+            #[cfg(target_arch = "wasm32")]
+            instance: instance.clone(),
+            #[cfg(target_arch = "wasm32")]
+            canvas: None,
+        })
     }
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct Vertex {
-    position: [f32; 3],
-    tex_coords: [f32; 2],
+#[cfg(target_arch = "wasm32")]
+fn board_canvas() -> Option<HtmlCanvasElement> {
+    window()?
+        .document()?
+        .get_element_by_id("image-board")?
+        .dyn_into::<HtmlCanvasElement>()
+        .ok()
 }
-impl Vertex {
-    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
-        use std::mem;
-        wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2, // NEW!
-                },
-            ],
-        }
+
+// a pass that only clears `view` to `color`
+fn clear_target(encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, color: wgpu::Color) {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Clear Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            depth_slice: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(color),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_board_inside_the_window_is_drawn_whole() {
+        let board = BoardPlacement { x: 10.0, y: 20.0, width: 100.0, height: 50.0, pixel_ratio: 1.0 };
+        let clip = board.clip((800, 600)).unwrap();
+        assert_eq!(clip.viewport, [10.0, 20.0, 100.0, 50.0]);
+        assert_eq!(clip.range, [(0.0, 0.0), (1.0, 1.0)]);
+    }
+
+    #[test]
+    fn a_board_past_the_window_edge_shows_the_same_source_pixels() {
+        let board = BoardPlacement { x: -50.0, y: 0.0, width: 200.0, height: 100.0, pixel_ratio: 1.0 };
+        let clip = board.clip((100, 600)).unwrap();
+        assert_eq!(clip.viewport, [0.0, 0.0, 100.0, 100.0]);
+        assert_eq!(clip.range, [(0.25, 0.0), (0.75, 1.0)]);
+
+        // the window's left edge is a quarter into the board, its right edge three quarters
+        let matrix = Transform::default().uv_matrix(None, (200, 100));
+        let clipped = uv_sub_range(matrix, clip.range);
+        let uv = |m: [[f32; 4]; 2], x: f32, y: f32| (m[0][0] * x + m[0][1] * y + m[0][2], m[1][0] * x + m[1][1] * y + m[1][2]);
+        assert_eq!(uv(clipped, 0.0, 0.5), uv(matrix, 0.25, 0.5));
+        assert_eq!(uv(clipped, 1.0, 0.5), uv(matrix, 0.75, 0.5));
+    }
+
+    #[test]
+    fn a_board_scrolled_out_of_view_is_not_drawn() {
+        let board = BoardPlacement { x: 900.0, y: 0.0, width: 100.0, height: 100.0, pixel_ratio: 2.0 };
+        assert!(board.clip((800, 600)).is_none());
     }
 }
-pub const VERTICES: &[Vertex] = &[
-    Vertex {
-        position: [-1.0, -1.0, 0.0],
-        tex_coords: [0.0, 1.0],
-    },
-    Vertex {
-        position: [1.0, -1.0, 0.0],
-        tex_coords: [1.0, 1.0],
-    },
-    Vertex {
-        position: [1.0, 1.0, 0.0],
-        tex_coords: [1.0, 0.0],
-    },
-    Vertex {
-        position: [-1.0, 1.0, 0.0],
-        tex_coords: [0.0, 0.0],
-    },
-];
-
-pub const INDICES: &[u16] = &[
-    0, 1, 2, //first
-    0, 2, 3, //second
-];
@@ -0,0 +1,304 @@
+use image::codecs::bmp::BmpEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::codecs::tiff::TiffEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ExtendedColorType, GenericImageView, ImageEncoder, RgbImage};
+use crate::state::metadata::{ImageMetadata, embed};
+use jpeg_encoder::SamplingFactor;
+use std::io::Cursor;
+
+pub const DEFAULT_NAME_TEMPLATE: &str = "{name}_edited.{ext}";
+
+// file name without directories or extension, "photos/beach.jpg" -> "beach"
+pub fn file_stem(path: &str) -> String {
+    let file_name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    match file_name.rfind('.') {
+        Some(dot) if dot > 0 => file_name[..dot].to_string(),
+        _ => file_name.to_string(),
+    }
+}
+
+// fills {name}, {ext} and {index} (1-based) in an export name template
+pub fn fill_template(template: &str, name: &str, format: &ExportFormat, index: usize) -> String {
+    let filled = template
+        .replace("{name}", name)
+        .replace("{ext}", format.extension())
+        .replace("{index}", &(index + 1).to_string());
+    if filled.trim().is_empty() {
+        format.with_extension(name)
+    } else {
+        filled
+    }
+}
+
+// formats without ICC support skip the profile
+fn with_icc<E: ImageEncoder>(mut encoder: E, icc: Option<&Vec<u8>>) -> E {
+    if let Some(icc) = icc {
+        let _ = encoder.set_icc_profile(icc.clone());
+    }
+    encoder
+}
+
+// JPEG has no alpha channel; jpeg-encoder is used over the image crate's encoder, which only
+// writes one chroma subsampling
+fn encode_jpeg(
+    bytes: &mut Vec<u8>,
+    rgb: &RgbImage,
+    quality: u8,
+    subsampling: ChromaSubsampling,
+    icc: Option<&Vec<u8>>,
+) -> Result<(), String> {
+    let (width, height) = rgb.dimensions();
+    let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+        return Err(format!("sides are limited to {} pixels", u16::MAX));
+    };
+    let mut encoder = jpeg_encoder::Encoder::new(bytes, quality.clamp(1, 100));
+    encoder.set_sampling_factor(subsampling.sampling_factor());
+    if let Some(icc) = icc {
+        encoder.add_icc_profile(icc).map_err(|err| err.to_string())?;
+    }
+    encoder
+        .encode(rgb.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)
+        .map_err(|err| err.to_string())
+}
+
+// resolution of the color channels in a JPEG relative to the brightness
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChromaSubsampling {
+    Full,       // 4:4:4
+    Horizontal, // 4:2:2, half the columns
+    Both,       // 4:2:0, half the columns and rows
+}
+
+impl ChromaSubsampling {
+    pub const ALL: [ChromaSubsampling; 3] = [
+        ChromaSubsampling::Full,
+        ChromaSubsampling::Horizontal,
+        ChromaSubsampling::Both,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChromaSubsampling::Full => "4:4:4",
+            ChromaSubsampling::Horizontal => "4:2:2",
+            ChromaSubsampling::Both => "4:2:0",
+        }
+    }
+
+    // "4:2:0" or "420"
+    pub fn parse(text: &str) -> Option<ChromaSubsampling> {
+        let text = text.replace(':', "");
+        Self::ALL
+            .into_iter()
+            .find(|subsampling| subsampling.label().replace(':', "") == text)
+    }
+
+    fn sampling_factor(&self) -> SamplingFactor {
+        match self {
+            ChromaSubsampling::Full => SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Horizontal => SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Both => SamplingFactor::R_4_2_0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExportFormat {
+    Png { compression: CompressionType },
+    Jpeg { quality: u8, subsampling: ChromaSubsampling }, // quality 1 to 100
+    WebP,                                                 // lossless
+    Tiff,
+    Bmp,
+}
+
+impl Default for ExportFormat {
+    fn default() -> Self {
+        ExportFormat::Png {
+            compression: CompressionType::Default,
+        }
+    }
+}
+
+impl ExportFormat {
+    // names for the format select, in menu order
+    pub const NAMES: [&'static str; 5] = ["png", "jpeg", "webp", "tiff", "bmp"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Png { .. } => "png",
+            ExportFormat::Jpeg { .. } => "jpeg",
+            ExportFormat::WebP => "webp",
+            ExportFormat::Tiff => "tiff",
+            ExportFormat::Bmp => "bmp",
+        }
+    }
+
+    pub fn default_for(name: &str) -> Option<ExportFormat> {
+        match name {
+            "png" => Some(ExportFormat::default()),
+            "jpeg" => Some(ExportFormat::Jpeg {
+                quality: 90,
+                subsampling: ChromaSubsampling::Both,
+            }),
+            "webp" => Some(ExportFormat::WebP),
+            "tiff" => Some(ExportFormat::Tiff),
+            "bmp" => Some(ExportFormat::Bmp),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Png { .. } => "PNG",
+            ExportFormat::Jpeg { .. } => "JPEG",
+            ExportFormat::WebP => "WebP (lossless)",
+            ExportFormat::Tiff => "TIFF",
+            ExportFormat::Bmp => "BMP",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png { .. } => "png",
+            ExportFormat::Jpeg { .. } => "jpg",
+            ExportFormat::WebP => "webp",
+            ExportFormat::Tiff => "tif",
+            ExportFormat::Bmp => "bmp",
+        }
+    }
+
+    // `filename` with its extension replaced by the one of this format
+    pub fn with_extension(&self, filename: &str) -> String {
+        let stem = match filename.rfind('.') {
+            Some(dot) if dot > 0 => &filename[..dot],
+            _ => filename,
+        };
+        format!("{}.{}", stem, self.extension())
+    }
+
+    // PNG and TIFF can store 16 bits per channel, the rest get 8
+    pub fn supports_16_bit(&self) -> bool {
+        matches!(self, ExportFormat::Png { .. } | ExportFormat::Tiff)
+    }
+
+    // PNG, JPEG and WebP carry the metadata, TIFF and BMP are written without it
+    pub fn keeps_metadata(&self) -> bool {
+        !matches!(self, ExportFormat::Tiff | ExportFormat::Bmp)
+    }
+
+    pub fn encode(&self, image: &DynamicImage, metadata: &ImageMetadata) -> Result<Vec<u8>, String> {
+        let (width, height) = image.dimensions();
+        let rgba = if self.supports_16_bit() && image.color().bytes_per_pixel() > 4 {
+            DynamicImage::ImageRgba16(image.to_rgba16())
+        } else {
+            DynamicImage::ImageRgba8(image.to_rgba8())
+        };
+        let color = ExtendedColorType::from(rgba.color());
+        let icc = metadata.icc.as_ref();
+        let mut bytes = Vec::new();
+        let result = match self {
+            ExportFormat::Png { compression } => with_icc(
+                PngEncoder::new_with_quality(&mut bytes, *compression, FilterType::Adaptive),
+                icc,
+            )
+            .write_image(rgba.as_bytes(), width, height, color)
+            .map_err(|err| err.to_string()),
+            ExportFormat::Jpeg { quality, subsampling } => {
+                encode_jpeg(&mut bytes, &rgba.to_rgb8(), *quality, *subsampling, icc)
+            }
+            ExportFormat::WebP => with_icc(WebPEncoder::new_lossless(&mut bytes), icc)
+                .write_image(rgba.as_bytes(), width, height, color)
+                .map_err(|err| err.to_string()),
+            ExportFormat::Tiff => TiffEncoder::new(Cursor::new(&mut bytes))
+                .write_image(rgba.as_bytes(), width, height, color)
+                .map_err(|err| err.to_string()),
+            ExportFormat::Bmp => BmpEncoder::new(&mut bytes)
+                .write_image(rgba.as_bytes(), width, height, color)
+                .map_err(|err| err.to_string()),
+        };
+        result
+            .map(|_| embed(bytes, self, metadata))
+            .map_err(|err| format!("could not encode {}: {}", self.label(), err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    // every 8-bit value in every channel, including partly transparent pixels
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(256, 4, |x, y| {
+            let v = x as u8;
+            Rgba([v, 255 - v, v.wrapping_mul(7), if y == 0 { 255 } else { v }])
+        })
+    }
+
+    #[test]
+    fn lossless_formats_round_trip() {
+        let image = gradient();
+        let formats = [
+            ExportFormat::default(),
+            ExportFormat::WebP,
+            ExportFormat::Tiff,
+            ExportFormat::Bmp,
+        ];
+        for format in formats {
+            let bytes = format.encode(&DynamicImage::ImageRgba8(image.clone()), &ImageMetadata::default()).unwrap();
+            let decoded = image::load_from_memory(&bytes).unwrap().to_rgba8();
+            assert_eq!(decoded, image, "{}", format.label());
+        }
+    }
+
+    #[test]
+    fn sixteen_bit_formats_keep_precision() {
+        let image = image::ImageBuffer::<Rgba<u16>, _>::from_fn(1024, 2, |x, y| {
+            let v = (x * 64 + 3) as u16;
+            Rgba([v, u16::MAX - v, v / 3, if y == 0 { u16::MAX } else { v }])
+        });
+        let source = DynamicImage::ImageRgba16(image.clone());
+        for format in [ExportFormat::default(), ExportFormat::Tiff] {
+            let bytes = format.encode(&source, &ImageMetadata::default()).unwrap();
+            let decoded = image::load_from_memory(&bytes).unwrap();
+            assert_eq!(decoded.to_rgba16(), image, "{}", format.label());
+        }
+        // formats without 16-bit support fall back to 8 bits
+        let bytes = ExportFormat::Bmp.encode(&source, &ImageMetadata::default()).unwrap();
+        assert_eq!(image::load_from_memory(&bytes).unwrap().to_rgba8(), source.to_rgba8());
+    }
+
+    // sampling byte of the first component (luma) in the baseline frame header, 0xHV
+    fn luma_sampling(jpeg: &[u8]) -> u8 {
+        let sof = jpeg.windows(2).position(|w| w == [0xFF, 0xC0]).expect("a baseline frame");
+        // marker, length, precision, height, width, component count, then id and sampling
+        jpeg[sof + 11]
+    }
+
+    #[test]
+    fn jpeg_writes_the_chosen_subsampling() {
+        let image = DynamicImage::ImageRgba8(gradient());
+        for (subsampling, factors) in [
+            (ChromaSubsampling::Full, 0x11),
+            (ChromaSubsampling::Horizontal, 0x21),
+            (ChromaSubsampling::Both, 0x22),
+        ] {
+            let format = ExportFormat::Jpeg { quality: 90, subsampling };
+            let bytes = format.encode(&image, &ImageMetadata::default()).unwrap();
+            assert_eq!(luma_sampling(&bytes), factors, "{}", subsampling.label());
+            let decoded = image::load_from_memory(&bytes).unwrap();
+            assert_eq!(decoded.dimensions(), image.dimensions());
+        }
+        assert_eq!(ChromaSubsampling::parse("422"), Some(ChromaSubsampling::Horizontal));
+        assert_eq!(ChromaSubsampling::parse("4:4:4"), Some(ChromaSubsampling::Full));
+        assert_eq!(ChromaSubsampling::parse("411"), None);
+    }
+
+    #[test]
+    fn extension_follows_format() {
+        let jpeg = ExportFormat::default_for("jpeg").unwrap();
+        assert_eq!(jpeg.with_extension("beach.png"), "beach.jpg");
+        assert_eq!(jpeg.with_extension("beach"), "beach.jpg");
+        assert_eq!(file_stem("photos/beach.final.png"), "beach.final");
+    }
+}
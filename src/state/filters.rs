@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+use wgpu::util::DeviceExt;
+
+// must match the size of `weights` in convolve.wgsl
+pub const MAX_KERNEL_WEIGHTS: usize = 1024;
+// widest 1D kernel that fits in one pass
+const MAX_PASS_RADIUS: u32 = (MAX_KERNEL_WEIGHTS as u32 - 1) / 2;
+// largest custom kernel, 31 x 31 = 961 weights
+pub const MAX_CUSTOM_SIZE: usize = 31;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Filter {
+    GaussianBlur {
+        radius: f32, // pixels, about three standard deviations
+    },
+    BoxBlur {
+        radius: u32,
+    },
+    Sharpen {
+        amount: f32,
+    },
+    Custom {
+        size: usize, // odd, the kernel is size x size
+        weights: Vec<f32>,
+        normalize: bool, // divide by the sum of the weights
+    },
+}
+
+impl Filter {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Filter::GaussianBlur { .. } => "Gaussian blur",
+            Filter::BoxBlur { .. } => "Box blur",
+            Filter::Sharpen { .. } => "Sharpen",
+            Filter::Custom { .. } => "Custom kernel",
+        }
+    }
+
+    pub fn default_for(name: &str) -> Option<Filter> {
+        match name {
+            "gaussian" => Some(Filter::GaussianBlur { radius: 3.0 }),
+            "box" => Some(Filter::BoxBlur { radius: 2 }),
+            "sharpen" => Some(Filter::Sharpen { amount: 0.5 }),
+            "custom" => Some(Filter::Custom {
+                size: 3,
+                weights: custom_identity(3),
+                normalize: false,
+            }),
+            _ => None,
+        }
+    }
+
+    // custom kernels have to be odd sized, fit in a pass and have a weight per cell
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Filter::Custom { size, weights, .. } => {
+                if !(1..=MAX_CUSTOM_SIZE).contains(size) || size.is_multiple_of(2) {
+                    return Err(format!("kernel size must be odd and between 1 and {MAX_CUSTOM_SIZE}, got {size}"));
+                }
+                if weights.len() != size * size {
+                    return Err(format!("a {size}x{size} kernel needs {} weights, got {}", size * size, weights.len()));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // kernels making up the filter, run one after the other; an invalid kernel has none
+    pub fn passes(&self) -> Vec<KernelPass> {
+        if self.validate().is_err() {
+            return Vec::new();
+        }
+        match self {
+            Filter::GaussianBlur { radius } => {
+                if *radius < 0.5 {
+                    return Vec::new();
+                }
+                // blurs add up in variance, so a radius too wide for one pass is split into several
+                let half = radius.ceil() as u32;
+                let repeats = ((half as f32 / MAX_PASS_RADIUS as f32).powi(2)).ceil().max(1.0) as u32;
+                let sigma = radius / 3.0 / (repeats as f32).sqrt();
+                let weights = gaussian_weights(sigma, half.min(MAX_PASS_RADIUS));
+                (0..repeats)
+                    .flat_map(|_| KernelPass::separable(&weights))
+                    .collect()
+            }
+            Filter::BoxBlur { radius } => {
+                if *radius == 0 {
+                    return Vec::new();
+                }
+                let size = 2 * (*radius).min(MAX_PASS_RADIUS) + 1;
+                KernelPass::separable(&vec![1.0 / size as f32; size as usize])
+            }
+            Filter::Sharpen { amount } => {
+                let a = *amount;
+                vec![KernelPass {
+                    width: 3,
+                    height: 3,
+                    weights: vec![0.0, -a, 0.0, -a, 1.0 + 4.0 * a, -a, 0.0, -a, 0.0],
+                    blur_alpha: false,
+                }]
+            }
+            Filter::Custom {
+                size,
+                weights,
+                normalize,
+            } => {
+                let sum: f32 = weights.iter().sum();
+                let scale = if *normalize && sum.abs() > f32::EPSILON {
+                    1.0 / sum
+                } else {
+                    1.0
+                };
+                vec![KernelPass {
+                    width: *size as u32,
+                    height: *size as u32,
+                    weights: weights.iter().map(|w| w * scale).collect(),
+                    blur_alpha: false,
+                }]
+            }
+        }
+    }
+}
+
+// size x size kernel that leaves the image unchanged
+pub fn custom_identity(size: usize) -> Vec<f32> {
+    let mut weights = vec![0.0; size * size];
+    weights[size * size / 2] = 1.0;
+    weights
+}
+
+fn gaussian_weights(sigma: f32, half: u32) -> Vec<f32> {
+    let sigma = sigma.max(0.1);
+    let weights: Vec<f32> = (-(half as i32)..=half as i32)
+        .map(|x| (-(x * x) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = weights.iter().sum();
+    weights.iter().map(|w| w / sum).collect()
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct KernelPass {
+    pub width: u32,
+    pub height: u32,
+    pub weights: Vec<f32>, // row-major
+    // alpha is convolved like the colors; other kernels keep each pixel's own alpha, a zero-sum
+    // kernel would otherwise make opaque images transparent
+    pub blur_alpha: bool,
+}
+
+impl KernelPass {
+    // a horizontal and a vertical pass with the same 1D blur kernel
+    fn separable(weights: &[f32]) -> Vec<KernelPass> {
+        let size = weights.len() as u32;
+        vec![
+            KernelPass {
+                width: size,
+                height: 1,
+                weights: weights.to_vec(),
+                blur_alpha: true,
+            },
+            KernelPass {
+                width: 1,
+                height: size,
+                weights: weights.to_vec(),
+                blur_alpha: true,
+            },
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ConvolutionUniform {
+    size: [u32; 2],
+    blur_alpha: u32,
+    _pad: u32,
+    weights: [[f32; 4]; MAX_KERNEL_WEIGHTS / 4],
+}
+
+// ordered neighbourhood filters of one image, applied to the source before the color adjustments
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct FilterStack {
+    pub filters: Vec<Filter>,
+}
+
+impl FilterStack {
+    pub fn passes(&self) -> Vec<KernelPass> {
+        self.filters.iter().flat_map(|filter| filter.passes()).collect()
+    }
+
+    // how many pixels away a filtered pixel can read from, in any direction
+    pub fn reach(&self) -> u32 {
+        self.passes().iter().map(|pass| pass.width.max(pass.height) / 2).sum()
+    }
+
+    // the stack for an image shown at `factor` of its size: blur radii shrink with the image,
+    // sharpening and custom kernels act on neighbouring pixels at any size and stay as they are
+    pub fn scaled(&self, factor: f32) -> FilterStack {
+        let filters = self
+            .filters
+            .iter()
+            .map(|filter| match filter {
+                Filter::GaussianBlur { radius } => Filter::GaussianBlur {
+                    radius: radius * factor,
+                },
+                Filter::BoxBlur { radius } => Filter::BoxBlur {
+                    radius: (*radius as f32 * factor).round() as u32,
+                },
+                other => other.clone(),
+            })
+            .collect();
+        FilterStack { filters }
+    }
+
+    pub fn move_earlier(&mut self, index: usize) {
+        if index > 0 && index < self.filters.len() {
+            self.filters.swap(index, index - 1);
+        }
+    }
+
+    pub fn move_later(&mut self, index: usize) {
+        if index + 1 < self.filters.len() {
+            self.filters.swap(index, index + 1);
+        }
+    }
+}
+
+// runs convolution passes on the GPU, ping-ponging between two intermediate textures
+// in the format of the source
+pub struct FilterEngine {
+    shader: wgpu::ShaderModule,
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+    targets: Vec<wgpu::Texture>,
+}
+
+impl FilterEngine {
+    pub fn new(device: &wgpu::Device) -> FilterEngine {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Convolution Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("convolve.wgsl").into()),
+        });
+        FilterEngine {
+            shader,
+            pipelines: HashMap::new(),
+            targets: Vec::new(),
+        }
+    }
+
+    // pipelines are built the first time a format is filtered
+    fn pipeline(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+        let shader = &self.shader;
+        self.pipelines
+            .entry(format)
+            .or_insert_with(|| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Convolution Pipeline"),
+                    layout: None,
+                    vertex: wgpu::VertexState {
+                        module: shader,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: shader,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: None,
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                    cache: None,
+                })
+            })
+            .clone()
+    }
+
+    fn ensure_targets(&mut self, device: &wgpu::Device, dimensions: (u32, u32), format: wgpu::TextureFormat) {
+        let fits = self
+            .targets
+            .first()
+            .is_some_and(|t| (t.width(), t.height()) == dimensions && t.format() == format);
+        if fits {
+            return;
+        }
+        self.targets = (0..2)
+            .map(|_| {
+                device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("Filter Target"),
+                    size: wgpu::Extent3d {
+                        width: dimensions.0,
+                        height: dimensions.1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+            })
+            .collect();
+    }
+
+    // filters `source` and returns the texture holding the result, or None if there is nothing to do
+    pub fn run(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &wgpu::Texture,
+        stack: &FilterStack,
+    ) -> Option<wgpu::Texture> {
+        let passes = stack.passes();
+        if passes.is_empty() {
+            return None;
+        }
+        self.ensure_targets(device, (source.width(), source.height()), source.format());
+        let pipeline = self.pipeline(device, source.format());
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Filter Encoder"),
+        });
+        let mut input = source.create_view(&wgpu::TextureViewDescriptor::default());
+        for (i, pass) in passes.iter().enumerate() {
+            let mut uniform = ConvolutionUniform {
+                size: [pass.width, pass.height],
+                blur_alpha: pass.blur_alpha as u32,
+                _pad: 0,
+                weights: [[0.0; 4]; MAX_KERNEL_WEIGHTS / 4],
+            };
+            for (j, w) in pass.weights.iter().take(MAX_KERNEL_WEIGHTS).enumerate() {
+                uniform.weights[j / 4][j % 4] = *w;
+            }
+            // every pass gets its own buffer, writes to a shared one would all land before the submit
+            let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Convolution Uniform"),
+                contents: bytemuck::bytes_of(&uniform),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Convolution Bind Group"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&input),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+            let output = self.targets[i % 2].create_view(&wgpu::TextureViewDescriptor::default());
+            {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Convolution Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &output,
+                        resolve_target: None,
+                        depth_slice: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                render_pass.set_pipeline(&pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+            input = output;
+        }
+        queue.submit(Some(encoder.finish()));
+
+        Some(self.targets[(passes.len() - 1) % 2].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_sums_to_one(weights: &[f32]) {
+        let sum: f32 = weights.iter().sum();
+        assert!((sum - 1.0).abs() < 1e-5, "weights sum to {sum}");
+    }
+
+    #[test]
+    fn blur_weights_sum_to_one() {
+        assert_sums_to_one(&gaussian_weights(2.0, 6));
+        for filter in [Filter::GaussianBlur { radius: 7.5 }, Filter::BoxBlur { radius: 4 }] {
+            for pass in filter.passes() {
+                assert_sums_to_one(&pass.weights);
+            }
+        }
+    }
+
+    #[test]
+    fn wide_blurs_are_split_into_repeated_passes() {
+        let radius = 1200.0;
+        let passes = Filter::GaussianBlur { radius }.passes();
+        // (1200 / 511)^2 rounds up to 6 horizontal and vertical pairs
+        assert_eq!(passes.len(), 12);
+        let horizontal: Vec<&KernelPass> = passes.iter().filter(|pass| pass.height == 1).collect();
+        assert_eq!(horizontal.len(), 6);
+        assert!(passes.iter().all(|pass| pass.weights.len() <= MAX_KERNEL_WEIGHTS));
+
+        let reach: u32 = horizontal.iter().map(|pass| pass.width / 2).sum();
+        assert_eq!(reach, 6 * MAX_PASS_RADIUS);
+        // variances add up to the one of the single kernel the radius asks for
+        let variance: f32 = horizontal
+            .iter()
+            .map(|pass| {
+                let half = (pass.width / 2) as f32;
+                let offsets = pass.weights.iter().enumerate().map(|(i, w)| (i as f32 - half).powi(2) * w);
+                offsets.sum::<f32>()
+            })
+            .sum();
+        let sigma = radius / 3.0;
+        assert!((variance.sqrt() - sigma).abs() / sigma < 0.01, "{} != {sigma}", variance.sqrt());
+    }
+
+    #[test]
+    fn custom_kernels_must_be_odd_and_in_range() {
+        let custom = |size: usize, weights: usize| Filter::Custom {
+            size,
+            weights: vec![0.0; weights],
+            normalize: false,
+        };
+        assert!(custom(3, 9).validate().is_ok());
+        assert!(custom(MAX_CUSTOM_SIZE, MAX_CUSTOM_SIZE * MAX_CUSTOM_SIZE).validate().is_ok());
+        assert!(custom(0, 0).validate().is_err());
+        assert!(custom(4, 16).validate().is_err());
+        assert!(custom(33, 33 * 33).validate().is_err());
+        assert!(custom(3, 8).validate().is_err());
+        assert!(custom(33, 33 * 33).passes().is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+use futures_intrusive::channel::shared::OneshotReceiver;
+use wgpu::util::DeviceExt;
+
+pub const BINS: usize = 256;
+// luminance, red, green and blue counters, in that order
+const BIN_COUNT: usize = BINS * 4;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HistogramChannel {
+    Luminance,
+    Red,
+    Green,
+    Blue,
+}
+
+impl HistogramChannel {
+    pub const ALL: [HistogramChannel; 4] = [
+        HistogramChannel::Luminance,
+        HistogramChannel::Red,
+        HistogramChannel::Green,
+        HistogramChannel::Blue,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HistogramChannel::Luminance => "luminance",
+            HistogramChannel::Red => "red",
+            HistogramChannel::Green => "green",
+            HistogramChannel::Blue => "blue",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            HistogramChannel::Luminance => "Lum",
+            HistogramChannel::Red => "R",
+            HistogramChannel::Green => "G",
+            HistogramChannel::Blue => "B",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Histogram {
+    bins: Vec<u32>,
+    pub pixels: u32,
+}
+
+impl Histogram {
+    pub fn from_bins(bins: &[u32]) -> Histogram {
+        let bins = bins[..BIN_COUNT].to_vec();
+        let pixels = bins[..BINS].iter().sum();
+        Histogram { bins, pixels }
+    }
+
+    pub fn channel(&self, channel: HistogramChannel) -> &[u32] {
+        let start = match channel {
+            HistogramChannel::Luminance => 0,
+            HistogramChannel::Red => BINS,
+            HistogramChannel::Green => BINS * 2,
+            HistogramChannel::Blue => BINS * 3,
+        };
+        &self.bins[start..start + BINS]
+    }
+
+    // percentage of pixels at pure black and pure white in a channel
+    pub fn clipping(&self, channel: HistogramChannel) -> (f32, f32) {
+        if self.pixels == 0 {
+            return (0.0, 0.0);
+        }
+        let bins = self.channel(channel);
+        let percent = |count: u32| count as f32 / self.pixels as f32 * 100.0;
+        (percent(bins[0]), percent(bins[BINS - 1]))
+    }
+}
+
+// compute pass that bins a rendered texture on the GPU
+pub struct HistogramPass {
+    pipeline: wgpu::ComputePipeline,
+    bins_buffer: wgpu::Buffer,
+}
+
+impl HistogramPass {
+    pub fn new(device: &wgpu::Device) -> HistogramPass {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Histogram Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("histogram.wgsl").into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Histogram Pipeline"),
+            layout: None,
+            module: &shader,
+            entry_point: Some("cs_main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        let bins_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Histogram Bins"),
+            size: (BIN_COUNT * 4) as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        HistogramPass {
+            pipeline,
+            bins_buffer,
+        }
+    }
+
+    // records the binning of `view` and returns the buffer the bins are copied to for reading
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        dimensions: (u32, u32),
+        linear: bool, // the view decodes sRGB, so texels have to be encoded again before binning
+    ) -> wgpu::Buffer {
+        let encode_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Histogram Encode Flag"),
+            contents: bytemuck::bytes_of(&[linear as u32, 0, 0, 0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Histogram Bind Group"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.bins_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: encode_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        encoder.clear_buffer(&self.bins_buffer, 0, None);
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Histogram Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(dimensions.0.div_ceil(16), dimensions.1.div_ceil(16), 1);
+        }
+
+        // a fresh buffer per request, so a readback still in flight is never overwritten
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Histogram Readback"),
+            size: (BIN_COUNT * 4) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(&self.bins_buffer, 0, &readback, 0, (BIN_COUNT * 4) as u64);
+        readback
+    }
+}
+
+// bins on their way back from the GPU, mapping is requested on creation
+pub struct HistogramReadback {
+    buffer: wgpu::Buffer,
+    device: wgpu::Device,
+    receiver: OneshotReceiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+impl HistogramReadback {
+    // `buffer` is the one HistogramPass::encode returned, after its commands were submitted
+    pub fn new(device: &wgpu::Device, buffer: wgpu::Buffer) -> HistogramReadback {
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |res| {
+            sender.send(res).unwrap();
+        });
+        HistogramReadback {
+            buffer,
+            device: device.clone(),
+            receiver,
+        }
+    }
+
+    pub async fn into_histogram(self) -> Result<Histogram, String> {
+        // native backends only map once polled, the browser maps on its own
+        let _ = self.device.poll(wgpu::PollType::Wait);
+        match self.receiver.receive().await {
+            Some(Ok(())) => {}
+            Some(Err(err)) => return Err(format!("histogram readback failed: {}", err)),
+            None => return Err(String::from("histogram readback was cancelled")),
+        }
+        let data = self.buffer.slice(..).get_mapped_range();
+        let histogram = Histogram::from_bins(bytemuck::cast_slice(&data));
+        drop(data);
+        self.buffer.unmap();
+        Ok(histogram)
+    }
+}
@@ -0,0 +1,53 @@
+// bins the rendered image into 4 x 256 counters: luminance, red, green, blue
+
+@group(0) @binding(0)
+var t_image: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> bins: array<atomic<u32>, 1024>;
+@group(0) @binding(2)
+var<uniform> encode_srgb: vec4<u32>; // x is 1 when the texels are linear
+
+// each workgroup counts into shared memory first to keep contention on `bins` low
+var<workgroup> local_bins: array<atomic<u32>, 1024>;
+
+fn srgb_encode(c: vec3<f32>) -> vec3<f32> {
+    let v = max(c, vec3<f32>(0.0));
+    return select(1.055 * pow(v, vec3<f32>(1.0 / 2.4)) - 0.055, v * 12.92, v <= vec3<f32>(0.0031308));
+}
+
+fn bin(v: f32) -> u32 {
+    return u32(clamp(v, 0.0, 1.0) * 255.0 + 0.5);
+}
+
+@compute @workgroup_size(16, 16)
+fn cs_main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    for (var i = 0u; i < 4u; i++) {
+        atomicStore(&local_bins[local_index * 4u + i], 0u);
+    }
+    workgroupBarrier();
+
+    let dims = textureDimensions(t_image);
+    if id.x < dims.x && id.y < dims.y {
+        var c = textureLoad(t_image, vec2<u32>(id.xy), 0).rgb;
+        if encode_srgb.x != 0u {
+            c = srgb_encode(c);
+        }
+        let luminance = dot(c, vec3<f32>(0.2126, 0.7152, 0.0722));
+        atomicAdd(&local_bins[bin(luminance)], 1u);
+        atomicAdd(&local_bins[256u + bin(c.r)], 1u);
+        atomicAdd(&local_bins[512u + bin(c.g)], 1u);
+        atomicAdd(&local_bins[768u + bin(c.b)], 1u);
+    }
+    workgroupBarrier();
+
+    for (var i = 0u; i < 4u; i++) {
+        let slot = local_index * 4u + i;
+        let count = atomicLoad(&local_bins[slot]);
+        if count > 0u {
+            atomicAdd(&bins[slot], count);
+        }
+    }
+}
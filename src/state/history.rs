@@ -0,0 +1,399 @@
+use crate::state::adjustments::AdjustmentStack;
+use crate::state::brush::DirtyRect;
+use crate::state::curves::CurveSet;
+use crate::state::customlib::CropRect;
+use crate::state::filters::FilterStack;
+use crate::state::levels::Levels;
+use crate::state::transform::Transform;
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbaImage};
+use std::collections::VecDeque;
+
+pub const DEFAULT_HISTORY_LIMIT: usize = 50;
+// default for the pixels kept for undo per image, the newest step is kept even when larger
+pub const HISTORY_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HistoryAction {
+    Undo,
+    Redo,
+}
+
+// a reversible edit; applying a command means moving the image to its `after` state
+pub enum EditCommand {
+    Adjustments {
+        before: AdjustmentStack,
+        after: AdjustmentStack,
+    },
+    Crop {
+        before: Option<CropRect>,
+        after: Option<CropRect>,
+    },
+    Curves {
+        before: CurveSet,
+        after: CurveSet,
+    },
+    Levels {
+        before: Levels,
+        after: Levels,
+    },
+    Filters {
+        before: FilterStack,
+        after: FilterStack,
+    },
+    Transform {
+        before: Transform,
+        after: Transform,
+        crop_before: Option<CropRect>,
+        crop_after: Option<CropRect>,
+    },
+    // only the source is kept, redo resizes it again. The paint layer is kept on both
+    // sides so strokes recorded before and after the resize line up when stepped through
+    Resize {
+        source: DynamicImage,
+        size: (u32, u32),
+        filter: FilterType,
+        restore: bool, // applying puts `source` back instead of resizing it
+        crop_before: Option<CropRect>,
+        crop_after: Option<CropRect>,
+        paint_before: Option<RgbaImage>,
+        paint_after: Option<RgbaImage>,
+    },
+    // the region of the paint layer a stroke changed
+    Paint {
+        rect: DirtyRect,
+        before: RgbaImage,
+        after: RgbaImage,
+    },
+}
+
+impl EditCommand {
+    // the command that undoes this one
+    pub fn inverse(self) -> EditCommand {
+        match self {
+            EditCommand::Adjustments { before, after } => EditCommand::Adjustments {
+                before: after,
+                after: before,
+            },
+            EditCommand::Crop { before, after } => EditCommand::Crop {
+                before: after,
+                after: before,
+            },
+            EditCommand::Curves { before, after } => EditCommand::Curves {
+                before: after,
+                after: before,
+            },
+            EditCommand::Levels { before, after } => EditCommand::Levels {
+                before: after,
+                after: before,
+            },
+            EditCommand::Filters { before, after } => EditCommand::Filters {
+                before: after,
+                after: before,
+            },
+            EditCommand::Transform {
+                before,
+                after,
+                crop_before,
+                crop_after,
+            } => EditCommand::Transform {
+                before: after,
+                after: before,
+                crop_before: crop_after,
+                crop_after: crop_before,
+            },
+            EditCommand::Resize {
+                source,
+                size,
+                filter,
+                restore,
+                crop_before,
+                crop_after,
+                paint_before,
+                paint_after,
+            } => EditCommand::Resize {
+                source,
+                size,
+                filter,
+                restore: !restore,
+                crop_before: crop_after,
+                crop_after: crop_before,
+                paint_before: paint_after,
+                paint_after: paint_before,
+            },
+            EditCommand::Paint {
+                rect,
+                before,
+                after,
+            } => EditCommand::Paint {
+                rect,
+                before: after,
+                after: before,
+            },
+        }
+    }
+
+    // pixel memory the command holds on to
+    pub fn bytes(&self) -> u64 {
+        match self {
+            EditCommand::Resize {
+                source,
+                paint_before,
+                paint_after,
+                ..
+            } => {
+                let paint = paint_before.iter().chain(paint_after).map(|layer| layer.len() as u64);
+                source.as_bytes().len() as u64 + paint.sum::<u64>()
+            }
+            EditCommand::Paint { before, after, .. } => (before.len() + after.len()) as u64,
+            _ => 0,
+        }
+    }
+
+    // folds a newer command of the same kind into this one, keeping the oldest `before`;
+    // hands the newer command back when the kinds differ
+    fn merge(&mut self, newer: EditCommand) -> Option<EditCommand> {
+        match (self, newer) {
+            (
+                EditCommand::Adjustments { after, .. },
+                EditCommand::Adjustments {
+                    after: new_after, ..
+                },
+            ) => {
+                *after = new_after;
+                None
+            }
+            (EditCommand::Crop { after, .. }, EditCommand::Crop { after: new_after, .. }) => {
+                *after = new_after;
+                None
+            }
+            (EditCommand::Curves { after, .. }, EditCommand::Curves { after: new_after, .. }) => {
+                *after = new_after;
+                None
+            }
+            (EditCommand::Levels { after, .. }, EditCommand::Levels { after: new_after, .. }) => {
+                *after = new_after;
+                None
+            }
+            (EditCommand::Filters { after, .. }, EditCommand::Filters { after: new_after, .. }) => {
+                *after = new_after;
+                None
+            }
+            (
+                EditCommand::Transform {
+                    after, crop_after, ..
+                },
+                EditCommand::Transform {
+                    after: new_after,
+                    crop_after: new_crop_after,
+                    ..
+                },
+            ) => {
+                *after = new_after;
+                *crop_after = new_crop_after;
+                None
+            }
+            (_, newer) => Some(newer),
+        }
+    }
+}
+
+// undo/redo stacks of a single image
+pub struct EditHistory {
+    undo_stack: VecDeque<EditCommand>,
+    redo_stack: Vec<EditCommand>,
+    open_group: Option<&'static str>, // coalescing key of the gesture still in progress
+    byte_budget: u64,                 // pixel bytes the undo stack is kept under
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::with_byte_budget(HISTORY_BYTES)
+    }
+}
+
+impl EditHistory {
+    pub fn with_byte_budget(byte_budget: u64) -> Self {
+        Self {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            open_group: None,
+            byte_budget,
+        }
+    }
+
+    // records a command; consecutive pushes with the same key count as one step until `seal`
+    pub fn push(&mut self, command: EditCommand, coalesce: Option<&'static str>, limit: usize) {
+        self.redo_stack.clear();
+
+        let command = match (coalesce, self.open_group, self.undo_stack.back_mut()) {
+            (Some(key), Some(open), Some(last)) if key == open => match last.merge(command) {
+                None => return,
+                Some(command) => command,
+            },
+            _ => command,
+        };
+
+        self.open_group = coalesce;
+        self.undo_stack.push_back(command);
+        self.truncate(limit);
+    }
+
+    pub fn seal(&mut self) {
+        self.open_group = None;
+    }
+
+    // drops the oldest steps past `limit` or past the byte budget
+    pub fn truncate(&mut self, limit: usize) {
+        let mut bytes: u64 = self.undo_stack.iter().map(EditCommand::bytes).sum();
+        while self.undo_stack.len() > 1 && (self.undo_stack.len() > limit || bytes > self.byte_budget) {
+            if let Some(dropped) = self.undo_stack.pop_front() {
+                bytes -= dropped.bytes();
+            }
+        }
+    }
+
+    // returns the command to apply in order to step back. The redo stack keeps commands
+    // inverted, so stepping moves a command between the stacks without copying it
+    pub fn undo(&mut self) -> Option<&EditCommand> {
+        self.open_group = None;
+        let command = self.undo_stack.pop_back()?;
+        self.redo_stack.push(command.inverse());
+        self.redo_stack.last()
+    }
+
+    pub fn redo(&mut self) -> Option<&EditCommand> {
+        self.open_group = None;
+        let command = self.redo_stack.pop()?;
+        self.undo_stack.push_back(command.inverse());
+        self.undo_stack.back()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crop(width: u32) -> Option<CropRect> {
+        Some(CropRect { x: 0, y: 0, width, height: 10 })
+    }
+
+    fn crop_step(before: u32, after: u32) -> EditCommand {
+        EditCommand::Crop {
+            before: crop(before),
+            after: crop(after),
+        }
+    }
+
+    fn after_crop(command: Option<&EditCommand>) -> Option<CropRect> {
+        match command {
+            Some(EditCommand::Crop { after, .. }) => *after,
+            _ => panic!("expected a crop step"),
+        }
+    }
+
+    #[test]
+    fn a_coalesced_drag_is_one_step() {
+        let mut history = EditHistory::default();
+        for width in 1..5 {
+            history.push(crop_step(width - 1, width), Some("crop"), 50);
+        }
+        // undoing goes back to the `before` of the first push
+        assert_eq!(after_crop(history.undo()), crop(0));
+        assert!(!history.can_undo());
+        assert_eq!(after_crop(history.redo()), crop(4));
+    }
+
+    #[test]
+    fn seal_splits_groups() {
+        let mut history = EditHistory::default();
+        history.push(crop_step(0, 1), Some("crop"), 50);
+        history.seal();
+        history.push(crop_step(1, 2), Some("crop"), 50);
+        assert_eq!(after_crop(history.undo()), crop(1));
+        assert_eq!(after_crop(history.undo()), crop(0));
+    }
+
+    #[test]
+    fn other_kinds_are_not_merged() {
+        let mut history = EditHistory::default();
+        history.push(crop_step(0, 1), Some("drag"), 50);
+        let levels = EditCommand::Levels {
+            before: Levels::default(),
+            after: Levels::default(),
+        };
+        history.push(levels, Some("drag"), 50);
+        assert!(matches!(history.undo(), Some(EditCommand::Levels { .. })));
+        assert_eq!(after_crop(history.undo()), crop(0));
+    }
+
+    #[test]
+    fn pushing_clears_redo() {
+        let mut history = EditHistory::default();
+        history.push(crop_step(0, 1), None, 50);
+        history.undo();
+        assert!(history.can_redo());
+        history.push(crop_step(0, 2), None, 50);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn limit_drops_the_oldest() {
+        let mut history = EditHistory::default();
+        for width in 1..=5 {
+            history.push(crop_step(width - 1, width), None, 3);
+        }
+        assert_eq!(after_crop(history.undo()), crop(4));
+        assert_eq!(after_crop(history.undo()), crop(3));
+        assert_eq!(after_crop(history.undo()), crop(2));
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn resize_steps_put_back_the_paint_layer_of_each_size() {
+        let mut history = EditHistory::default();
+        let resize = EditCommand::Resize {
+            source: DynamicImage::new_rgba8(8, 8),
+            size: (4, 4),
+            filter: FilterType::Triangle,
+            restore: false,
+            crop_before: None,
+            crop_after: None,
+            paint_before: Some(RgbaImage::new(8, 8)),
+            paint_after: Some(RgbaImage::new(4, 4)),
+        };
+        history.push(resize, None, 50);
+        let paint_size = |command: Option<&EditCommand>| match command {
+            Some(EditCommand::Resize { paint_after, .. }) => paint_after.as_ref().map(|p| p.dimensions()),
+            _ => panic!("expected a resize step"),
+        };
+        assert_eq!(paint_size(history.undo()), Some((8, 8)));
+        assert_eq!(paint_size(history.redo()), Some((4, 4)));
+    }
+
+    #[test]
+    fn paint_steps_are_capped_by_bytes() {
+        // two 1KB patches per step, five steps fit the byte budget
+        let mut history = EditHistory::with_byte_budget(10 * 1024);
+        let side = 16;
+        let rect = DirtyRect { x: 0, y: 0, width: side, height: side };
+        for _ in 0..8 {
+            let patch = RgbaImage::new(side, side);
+            let step = EditCommand::Paint {
+                rect,
+                before: patch.clone(),
+                after: patch,
+            };
+            history.push(step, None, 50);
+        }
+        assert_eq!(history.undo_stack.len(), 5);
+    }
+}
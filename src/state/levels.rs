@@ -0,0 +1,194 @@
+use crate::state::curves::CurveChannel;
+
+// input/output range of one channel, all values in 0..1
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChannelLevels {
+    pub in_black: f32,
+    pub in_white: f32,
+    pub gamma: f32, // midtone gamma, 1 keeps the midtones unchanged
+    pub out_black: f32,
+    pub out_white: f32,
+}
+
+impl Default for ChannelLevels {
+    fn default() -> Self {
+        Self {
+            in_black: 0.0,
+            in_white: 1.0,
+            gamma: 1.0,
+            out_black: 0.0,
+            out_white: 1.0,
+        }
+    }
+}
+
+impl ChannelLevels {
+    pub fn apply(&self, x: f32) -> f32 {
+        let range = (self.in_white - self.in_black).max(1.0 / 255.0);
+        let v = ((x - self.in_black) / range).clamp(0.0, 1.0);
+        let v = v.powf(1.0 / self.gamma.max(0.01));
+        self.out_black + v * (self.out_white - self.out_black)
+    }
+
+    // gamma that maps the input level `x` to the output level `target`
+    fn gamma_for(&self, x: f32, target: f32) -> f32 {
+        let range = (self.in_white - self.in_black).max(1.0 / 255.0);
+        let v = ((x - self.in_black) / range).clamp(0.001, 0.999);
+        let out_range = self.out_white - self.out_black;
+        if out_range.abs() < f32::EPSILON {
+            return self.gamma;
+        }
+        let t = ((target - self.out_black) / out_range).clamp(0.001, 0.999);
+        (v.ln() / t.ln()).clamp(0.1, 10.0)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Eyedropper {
+    Black,
+    Gray,
+    White,
+}
+
+// levels of one image; the composite applies to all channels before the per-channel levels
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Levels {
+    pub composite: ChannelLevels,
+    pub red: ChannelLevels,
+    pub green: ChannelLevels,
+    pub blue: ChannelLevels,
+}
+
+impl Levels {
+    pub fn channel(&self, channel: CurveChannel) -> &ChannelLevels {
+        match channel {
+            CurveChannel::Master => &self.composite,
+            CurveChannel::Red => &self.red,
+            CurveChannel::Green => &self.green,
+            CurveChannel::Blue => &self.blue,
+        }
+    }
+
+    pub fn channel_mut(&mut self, channel: CurveChannel) -> &mut ChannelLevels {
+        match channel {
+            CurveChannel::Master => &mut self.composite,
+            CurveChannel::Red => &mut self.red,
+            CurveChannel::Green => &mut self.green,
+            CurveChannel::Blue => &mut self.blue,
+        }
+    }
+
+    // output of the red, green and blue levels for an input level
+    pub fn apply(&self, x: f32) -> [f32; 3] {
+        let x = self.composite.apply(x);
+        [self.red.apply(x), self.green.apply(x), self.blue.apply(x)]
+    }
+
+    // sets the per-channel black, white or gray point from a picked colour (0..1 rgb)
+    pub fn pick(&mut self, eyedropper: Eyedropper, color: [f32; 3]) {
+        let color = color.map(|c| self.composite.apply(c));
+        let output = [
+            self.red.apply(color[0]),
+            self.green.apply(color[1]),
+            self.blue.apply(color[2]),
+        ];
+        let channels = [&mut self.red, &mut self.green, &mut self.blue];
+        match eyedropper {
+            Eyedropper::Black => {
+                for (levels, c) in channels.into_iter().zip(color) {
+                    levels.in_black = c.min(levels.in_white - 1.0 / 255.0).max(0.0);
+                }
+            }
+            Eyedropper::White => {
+                for (levels, c) in channels.into_iter().zip(color) {
+                    levels.in_white = c.max(levels.in_black + 1.0 / 255.0).min(1.0);
+                }
+            }
+            Eyedropper::Gray => {
+                // every channel is bent so the picked colour ends up neutral
+                let target = (output[0] + output[1] + output[2]) / 3.0;
+                for (levels, c) in channels.into_iter().zip(color) {
+                    levels.gamma = levels.gamma_for(c, target);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{a} != {b}");
+    }
+
+    #[test]
+    fn input_range_is_stretched_to_the_output_range() {
+        let levels = ChannelLevels {
+            in_black: 0.2,
+            in_white: 0.6,
+            ..ChannelLevels::default()
+        };
+        assert_near(levels.apply(0.2), 0.0);
+        assert_near(levels.apply(0.4), 0.5);
+        assert_near(levels.apply(0.6), 1.0);
+        // past either end clips
+        assert_near(levels.apply(0.1), 0.0);
+        assert_near(levels.apply(0.9), 1.0);
+
+        let narrowed = ChannelLevels {
+            out_black: 0.1,
+            out_white: 0.9,
+            ..levels
+        };
+        assert_near(narrowed.apply(0.2), 0.1);
+        assert_near(narrowed.apply(0.4), 0.5);
+        assert_near(narrowed.apply(0.6), 0.9);
+    }
+
+    #[test]
+    fn gamma_for_maps_the_level_to_the_target() {
+        let mut levels = ChannelLevels::default();
+        levels.gamma = levels.gamma_for(0.25, 0.5);
+        assert_near(levels.gamma, 2.0);
+        assert_near(levels.apply(0.25), 0.5);
+
+        let mut ranged = ChannelLevels {
+            in_black: 0.1,
+            in_white: 0.9,
+            out_black: 0.2,
+            out_white: 0.8,
+            ..ChannelLevels::default()
+        };
+        ranged.gamma = ranged.gamma_for(0.3, 0.6);
+        assert_near(ranged.apply(0.3), 0.6);
+    }
+
+    #[test]
+    fn picking_gray_neutralises_a_tint() {
+        let mut levels = Levels::default();
+        let tinted = [0.6, 0.5, 0.4];
+        levels.pick(Eyedropper::Gray, tinted);
+        let out = [
+            levels.red.apply(tinted[0]),
+            levels.green.apply(tinted[1]),
+            levels.blue.apply(tinted[2]),
+        ];
+        assert_near(out[0], 0.5);
+        assert_near(out[1], 0.5);
+        assert_near(out[2], 0.5);
+    }
+
+    #[test]
+    fn picking_black_and_white_sets_the_input_range() {
+        let mut levels = Levels::default();
+        levels.pick(Eyedropper::Black, [0.1, 0.2, 0.05]);
+        levels.pick(Eyedropper::White, [0.9, 0.8, 0.95]);
+        let channels = [levels.red, levels.green, levels.blue];
+        assert_eq!(channels.map(|c| c.in_black), [0.1, 0.2, 0.05]);
+        assert_eq!(channels.map(|c| c.in_white), [0.9, 0.8, 0.95]);
+        assert_near(levels.apply(0.2)[1], 0.0);
+        assert_near(levels.apply(0.8)[1], 1.0);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::state::export::ExportFormat;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use std::io::Cursor;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const ORIENTATION_TAG: u16 = 0x0112;
+// largest payload of a JPEG marker segment, after its two length bytes
+const MAX_SEGMENT: usize = 65533;
+
+// metadata blocks carried from an imported file to its exports
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ImageMetadata {
+    pub exif: Option<Vec<u8>>, // TIFF structured, without the "Exif\0\0" header
+    pub icc: Option<Vec<u8>>,
+    pub xmp: Option<Vec<u8>>,
+}
+
+// decodes an image with its EXIF orientation applied, and the metadata to carry over
+pub fn decode_with_metadata(bytes: &[u8]) -> Result<(DynamicImage, ImageMetadata), String> {
+    let reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|err| err.to_string())?;
+    let mut decoder = reader.into_decoder().map_err(|err| err.to_string())?;
+
+    let (mut exif, xmp) = read_container(bytes);
+    let icc = decoder.icc_profile().ok().flatten();
+    // TIFF keeps its orientation as a plain tag, the decoder knows where
+    let orientation = match exif.as_deref().and_then(exif_orientation) {
+        Some(orientation) => orientation,
+        None => decoder.orientation().unwrap_or(Orientation::NoTransforms),
+    };
+
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|err| err.to_string())?;
+    image.apply_orientation(orientation);
+    // the pixels are upright now, exports must not rotate them a second time
+    if let Some(exif) = exif.as_mut() {
+        set_exif_orientation(exif, 1);
+    }
+    Ok((image, ImageMetadata { exif, icc, xmp }))
+}
+
+// EXIF and XMP blocks of a JPEG, PNG or WebP file
+fn read_container(bytes: &[u8]) -> (Option<Vec<u8>>, Option<Vec<u8>>) {
+    let mut exif = None;
+    let mut xmp = None;
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        for (marker, payload) in jpeg_segments(bytes) {
+            if marker == 0xE1 && payload.starts_with(EXIF_HEADER) && exif.is_none() {
+                exif = Some(payload[EXIF_HEADER.len()..].to_vec());
+            } else if marker == 0xE1 && payload.starts_with(XMP_HEADER) && xmp.is_none() {
+                xmp = Some(payload[XMP_HEADER.len()..].to_vec());
+            }
+        }
+    } else if bytes.starts_with(PNG_SIGNATURE) {
+        for (kind, data) in png_chunks(bytes) {
+            match kind {
+                b"eXIf" => exif = Some(data.to_vec()),
+                b"iTXt" => xmp = xmp.or_else(|| png_xmp(data)),
+                _ => {}
+            }
+        }
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        for (kind, data) in riff_chunks(bytes) {
+            match kind {
+                b"EXIF" => exif = Some(data.strip_prefix(EXIF_HEADER).unwrap_or(data).to_vec()),
+                b"XMP " => xmp = Some(data.to_vec()),
+                _ => {}
+            }
+        }
+    }
+    (exif, xmp)
+}
+
+// (marker, payload) of the JPEG segments before the image data
+fn jpeg_segments(bytes: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let Some(payload) = bytes.get(pos + 4..pos + 2 + length) else {
+            break;
+        };
+        segments.push((marker, payload));
+        pos += 2 + length;
+    }
+    segments
+}
+
+// (type, data) of the PNG chunks
+fn png_chunks(bytes: &[u8]) -> Vec<(&[u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 8 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind: &[u8; 4] = bytes[pos + 4..pos + 8].try_into().unwrap();
+        let Some(data) = bytes.get(pos + 8..pos + 8 + length) else {
+            break;
+        };
+        chunks.push((kind, data));
+        pos += 12 + length;
+    }
+    chunks
+}
+
+// the packet of an uncompressed XMP iTXt chunk
+fn png_xmp(data: &[u8]) -> Option<Vec<u8>> {
+    let rest = data.strip_prefix(XMP_KEYWORD)?.strip_prefix(b"\0")?;
+    // compression flag and method, then the language and translated keyword
+    let (&compressed, rest) = rest.split_first()?;
+    if compressed != 0 {
+        return None;
+    }
+    let mut fields = rest.get(1..)?.splitn(3, |b| *b == 0);
+    fields.next()?;
+    fields.next()?;
+    fields.next().map(|text| text.to_vec())
+}
+
+// (fourcc, data) of the chunks in a RIFF container
+fn riff_chunks(bytes: &[u8]) -> Vec<(&[u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let kind: &[u8; 4] = bytes[pos..pos + 4].try_into().unwrap();
+        let length = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let Some(data) = bytes.get(pos + 8..pos + 8 + length) else {
+            break;
+        };
+        chunks.push((kind, data));
+        pos += 8 + length + length % 2;
+    }
+    chunks
+}
+
+// position of the orientation value in the first IFD, and whether the EXIF is big endian
+fn orientation_offset(exif: &[u8]) -> Option<(usize, bool)> {
+    let big_endian = match exif.get(0..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let read_u16 = |pos: usize| -> Option<u16> {
+        let b: [u8; 2] = exif.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
+    };
+    let read_u32 = |pos: usize| -> Option<u32> {
+        let b: [u8; 4] = exif.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    };
+    let ifd = read_u32(4)? as usize;
+    let count = read_u16(ifd)? as usize;
+    (0..count)
+        .map(|i| ifd + 2 + 12 * i)
+        .find(|entry| read_u16(*entry) == Some(ORIENTATION_TAG))
+        .map(|entry| entry + 8)
+        // a block cut off inside the entry has no value to read or write
+        .filter(|pos| pos + 2 <= exif.len())
+        .map(|pos| (pos, big_endian))
+}
+
+fn exif_orientation(exif: &[u8]) -> Option<Orientation> {
+    let (pos, big_endian) = orientation_offset(exif)?;
+    let b: [u8; 2] = exif.get(pos..pos + 2)?.try_into().ok()?;
+    let value = if big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) };
+    Orientation::from_exif(u8::try_from(value).ok()?)
+}
+
+fn set_exif_orientation(exif: &mut [u8], value: u16) {
+    if let Some((pos, big_endian)) = orientation_offset(exif) {
+        let bytes = if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        exif[pos..pos + 2].copy_from_slice(&bytes);
+    }
+}
+
+// adds the EXIF and XMP blocks to an encoded file, ICC profiles are written by the encoders;
+// TIFF and BMP files are left as they are
+pub fn embed(encoded: Vec<u8>, format: &ExportFormat, metadata: &ImageMetadata) -> Vec<u8> {
+    if metadata.exif.is_none() && metadata.xmp.is_none() {
+        return encoded;
+    }
+    match format {
+        ExportFormat::Jpeg { .. } => embed_jpeg(encoded, metadata),
+        ExportFormat::Png { .. } => embed_png(encoded, metadata),
+        ExportFormat::WebP => embed_webp(encoded, metadata),
+        ExportFormat::Tiff | ExportFormat::Bmp => encoded,
+    }
+}
+
+fn embed_jpeg(encoded: Vec<u8>, metadata: &ImageMetadata) -> Vec<u8> {
+    let mut segments = Vec::new();
+    let blocks = [(EXIF_HEADER, &metadata.exif), (XMP_HEADER, &metadata.xmp)];
+    for (header, block) in blocks {
+        // blocks too large for one segment would need extended XMP, they are dropped
+        let Some(block) = block.as_ref().filter(|b| header.len() + b.len() <= MAX_SEGMENT) else {
+            continue;
+        };
+        segments.extend_from_slice(&[0xFF, 0xE1]);
+        segments.extend_from_slice(&((2 + header.len() + block.len()) as u16).to_be_bytes());
+        segments.extend_from_slice(header);
+        segments.extend_from_slice(block);
+    }
+    // right after the JFIF header if there is one, after the start of image otherwise
+    let pos = match jpeg_segments(&encoded).first() {
+        Some((0xE0, payload)) => 4 + payload.len() + 2,
+        _ => 2,
+    };
+    let mut out = Vec::with_capacity(encoded.len() + segments.len());
+    out.extend_from_slice(&encoded[..pos]);
+    out.extend_from_slice(&segments);
+    out.extend_from_slice(&encoded[pos..]);
+    out
+}
+
+fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(12 + data.len());
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    chunk.extend_from_slice(&hasher.finalize().to_be_bytes());
+    chunk
+}
+
+fn embed_png(encoded: Vec<u8>, metadata: &ImageMetadata) -> Vec<u8> {
+    let mut chunks = Vec::new();
+    if let Some(exif) = &metadata.exif {
+        chunks.extend(png_chunk(b"eXIf", exif));
+    }
+    if let Some(xmp) = &metadata.xmp {
+        // keyword, uncompressed, no language or translated keyword
+        let mut data = XMP_KEYWORD.to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0, 0]);
+        data.extend_from_slice(xmp);
+        chunks.extend(png_chunk(b"iTXt", &data));
+    }
+    // both have to come before the image data
+    let mut pos = PNG_SIGNATURE.len();
+    for (kind, data) in png_chunks(&encoded) {
+        if kind == b"IDAT" {
+            break;
+        }
+        pos += 12 + data.len();
+    }
+    let mut out = Vec::with_capacity(encoded.len() + chunks.len());
+    out.extend_from_slice(&encoded[..pos]);
+    out.extend_from_slice(&chunks);
+    out.extend_from_slice(&encoded[pos..]);
+    out
+}
+
+fn riff_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(9 + data.len());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+// canvas size and alpha of a simple (lossy or lossless) WebP bitstream
+fn webp_canvas(kind: &[u8; 4], data: &[u8]) -> Option<(u32, u32, bool)> {
+    match kind {
+        b"VP8L" => {
+            let bits = u32::from_le_bytes(data.get(1..5)?.try_into().ok()?);
+            Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1, bits >> 28 & 1 == 1))
+        }
+        b"VP8 " => {
+            let width = u16::from_le_bytes(data.get(6..8)?.try_into().ok()?) & 0x3FFF;
+            let height = u16::from_le_bytes(data.get(8..10)?.try_into().ok()?) & 0x3FFF;
+            Some((width as u32, height as u32, false))
+        }
+        _ => None,
+    }
+}
+
+fn embed_webp(encoded: Vec<u8>, metadata: &ImageMetadata) -> Vec<u8> {
+    let mut chunks: Vec<Vec<u8>> = riff_chunks(&encoded)
+        .into_iter()
+        .map(|(kind, data)| riff_chunk(kind, data))
+        .collect();
+    // EXIF and XMP need the extended format, which announces them in a VP8X header
+    if !chunks.first().is_some_and(|c| c.starts_with(b"VP8X")) {
+        let Some((kind, data)) = riff_chunks(&encoded).first().copied() else {
+            return encoded;
+        };
+        let Some((width, height, alpha)) = webp_canvas(kind, data) else {
+            return encoded;
+        };
+        let mut header = vec![if alpha { 0x10 } else { 0 }, 0, 0, 0];
+        header.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        header.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+        chunks.insert(0, riff_chunk(b"VP8X", &header));
+    }
+    if let Some(exif) = &metadata.exif {
+        chunks[0][8] |= 0x08;
+        chunks.push(riff_chunk(b"EXIF", exif));
+    }
+    if let Some(xmp) = &metadata.xmp {
+        chunks[0][8] |= 0x04;
+        chunks.push(riff_chunk(b"XMP ", xmp));
+    }
+
+    let body: Vec<u8> = chunks.concat();
+    let mut out = Vec::with_capacity(12 + body.len());
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(4 + body.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"WEBP");
+    out.extend_from_slice(&body);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    // little endian EXIF with only an orientation entry
+    fn exif_with_orientation(value: u16) -> Vec<u8> {
+        let mut exif = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+        exif.extend_from_slice(&1u16.to_le_bytes());
+        exif.extend_from_slice(&ORIENTATION_TAG.to_le_bytes());
+        exif.extend_from_slice(&3u16.to_le_bytes()); // SHORT
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&value.to_le_bytes());
+        exif.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        exif
+    }
+
+    // 2 x 1, red then blue
+    fn pixels() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 { Rgba([255, 0, 0, 255]) } else { Rgba([0, 0, 255, 255]) }
+        }))
+    }
+
+    #[test]
+    fn orientation_is_applied_on_import() {
+        let metadata = ImageMetadata {
+            exif: Some(exif_with_orientation(6)), // rotated 90 degrees clockwise
+            ..ImageMetadata::default()
+        };
+        let format = ExportFormat::default();
+        let bytes = format.encode(&pixels(), &metadata).unwrap();
+
+        let (image, read) = decode_with_metadata(&bytes).unwrap();
+        assert_eq!(image.dimensions(), (1, 2));
+        assert_eq!(image.to_rgba8().get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        let exif = read.exif.unwrap();
+        assert_eq!(exif_orientation(&exif), Some(Orientation::NoTransforms));
+    }
+
+    #[test]
+    fn cut_off_exif_is_left_alone() {
+        let full = exif_with_orientation(6);
+        // ends in the middle of the orientation value
+        let mut cut = full[..19].to_vec();
+        assert_eq!(exif_orientation(&cut), None);
+        set_exif_orientation(&mut cut, 1);
+        assert_eq!(cut, full[..19]);
+    }
+
+    #[test]
+    fn metadata_survives_export() {
+        let metadata = ImageMetadata {
+            exif: Some(exif_with_orientation(1)),
+            icc: Some(b"not a real profile, carried as is".to_vec()),
+            xmp: Some(b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>".to_vec()),
+        };
+        let formats = [ExportFormat::default(), ExportFormat::default_for("jpeg").unwrap(), ExportFormat::WebP];
+        for format in formats {
+            let bytes = format.encode(&pixels(), &metadata).unwrap();
+            let (image, read) = decode_with_metadata(&bytes).unwrap();
+            assert_eq!(image.dimensions(), (2, 1), "{}", format.label());
+            assert_eq!(read, metadata, "{}", format.label());
+        }
+    }
+}
@@ -1,2 +1,17 @@
 pub mod app_state;
 pub mod customlib;
+pub mod offscreen;
+pub mod texture_cache;
+pub mod tiles;
+pub mod brush;
+pub mod history;
+pub mod adjustments;
+pub mod curves;
+pub mod levels;
+pub mod histogram;
+pub mod filters;
+pub mod export;
+pub mod color;
+pub mod metadata;
+pub mod transform;
+pub mod reference;
@@ -0,0 +1,1467 @@
+// The render pipeline on its own: a device, a queue and the textures an image is edited with,
+// drawing into whatever target it is handed. `State` presents it on a canvas or window, tests,
+// workers and servers can render with it directly.
+use crate::state::adjustments::AdjustmentStack;
+use crate::state::brush::DirtyRect;
+use crate::state::color::{half_to_u16, is_high_bit_depth, linear_half_texels};
+use crate::state::curves::{CurveSet, LUT_SIZE};
+use crate::state::customlib::{CropRect, RenderSettings};
+use crate::state::filters::{FilterEngine, FilterStack};
+use crate::state::histogram::HistogramPass;
+use crate::state::levels::Levels;
+use crate::state::tiles::{
+    SAMPLING_MARGIN, fit_within, source_region, tile_grid, tile_matrix, tile_size,
+};
+use crate::state::transform::Transform;
+use crate::utils::utils::align_to_256;
+use futures_intrusive::channel::shared::OneshotReceiver;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba, RgbaImage};
+use wgpu::util::DeviceExt;
+
+// format of the source, paint and filter textures: sRGB encoded in memory, linear when sampled,
+// so all processing happens on linear light and 8-bit values survive the round trip exactly
+pub const WORKING_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+// format 16-bit and float sources are uploaded, filtered and exported in, holding linear light
+pub const HIGH_PRECISION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// 8-bit target of a renderer without a surface, encodes sRGB on write like the browser canvas
+pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Globals {
+    pub uv_u: [f32; 4], // source u of a canvas position (x, y, 1), see Transform::uv_matrix
+    pub uv_v: [f32; 4], // source v, the same way
+    pub premultiply: u32, // 1 when the target composites premultiplied colors
+    pub encode_srgb: u32, // 1 when the target format does not encode sRGB itself
+    pub checker_size: f32, // side of the checkerboard drawn under transparent pixels, 0 for none
+    pub _pad: u32,
+}
+
+impl Globals {
+    pub fn new(uv_matrix: [[f32; 4]; 2], premultiply: bool, encode_srgb: bool) -> Self {
+        Self {
+            uv_u: uv_matrix[0],
+            uv_v: uv_matrix[1],
+            premultiply: premultiply as u32,
+            encode_srgb: encode_srgb as u32,
+            checker_size: 0.0,
+            _pad: 0,
+        }
+    }
+}
+
+// a rendered image on its way back from the GPU, mapping was requested on creation
+pub struct Readback {
+    buffer: wgpu::Buffer,
+    device: wgpu::Device,
+    receiver: OneshotReceiver<Result<(), wgpu::BufferAsyncError>>,
+    bgra: bool,           // the texture stored blue first
+    high_precision: bool, // linear half floats, 8 bytes per pixel
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl Readback {
+    // waits for the buffer to map and strips the row padding
+    pub async fn into_image(self) -> Result<DynamicImage, String> {
+        let _ = self.device.poll(wgpu::PollType::Wait);
+        match self.receiver.receive().await {
+            Some(Ok(())) => {}
+            Some(Err(err)) => return Err(format!("GPU readback failed: {}", err)),
+            None => return Err(String::from("GPU readback was cancelled")),
+        }
+
+        let data = self.buffer.slice(..).get_mapped_range();
+        let bytes_per_pixel = if self.high_precision { 8 } else { 4 };
+        let unpadded_bytes_per_row = (bytes_per_pixel * self.width) as usize;
+        let mut image_bytes = Vec::with_capacity(unpadded_bytes_per_row * self.height as usize);
+        for row in data
+            .chunks(self.padded_bytes_per_row as usize)
+            .take(self.height as usize)
+        {
+            image_bytes.extend_from_slice(&row[..unpadded_bytes_per_row]);
+        }
+        drop(data);
+        self.buffer.unmap();
+
+        let image = if self.high_precision {
+            // the shader left these linear, sRGB encoding them here keeps the most precision
+            let channels: Vec<u16> = image_bytes
+                .chunks_exact(2)
+                .enumerate()
+                .map(|(i, bits)| half_to_u16(u16::from_ne_bytes([bits[0], bits[1]]), i % 4 == 3))
+                .collect();
+            ImageBuffer::<Rgba<u16>, _>::from_raw(self.width, self.height, channels)
+                .map(DynamicImage::ImageRgba16)
+        } else {
+            if self.bgra {
+                for pixel in image_bytes.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+            ImageBuffer::<Rgba<u8>, _>::from_raw(self.width, self.height, image_bytes)
+                .map(DynamicImage::ImageRgba8)
+        };
+        image.ok_or_else(|| String::from("GPU readback has the wrong size"))
+    }
+}
+
+// a render on its way to the CPU: one readback, or an image past the texture limit that is
+// rendered tile by tile on its own renderer while it is awaited
+pub enum PendingRender {
+    Whole(Readback),
+    Tiled(Box<TiledRender>),
+}
+
+// everything a tiled render needs once it is awaited
+pub struct TiledRender {
+    pub renderer: Renderer,
+    pub image: DynamicImage,
+    pub paint: Option<RgbaImage>,
+    pub settings: RenderSettings,
+}
+
+impl PendingRender {
+    pub async fn into_image(self) -> Result<DynamicImage, String> {
+        match self {
+            PendingRender::Whole(readback) => readback.into_image().await,
+            PendingRender::Tiled(tiled) => {
+                let TiledRender {
+                    mut renderer,
+                    image,
+                    paint,
+                    settings,
+                } = *tiled;
+                renderer
+                    .render_tiled(&image, paint.as_ref(), &settings)
+                    .await
+            }
+        }
+    }
+}
+
+// what the adapter supports beyond the basics, the passes that need more are left out without it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Capabilities {
+    pub compute_shaders: bool,    // the histogram
+    pub half_float_targets: bool, // editing 16-bit images without dropping to 8 bits
+}
+
+impl Capabilities {
+    pub fn of(adapter: &wgpu::Adapter) -> Capabilities {
+        // half float render targets need EXT_color_buffer_float on WebGL2
+        let half_float = adapter.get_texture_format_features(HIGH_PRECISION_FORMAT);
+        Capabilities {
+            compute_shaders: adapter
+                .get_downlevel_capabilities()
+                .flags
+                .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS),
+            half_float_targets: half_float.allowed_usages.contains(
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            ) && half_float
+                .flags
+                .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE),
+        }
+    }
+}
+
+// a device with everything the adapter allows
+pub async fn request_device(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), String> {
+    //Mozilla Firefox fix
+    let limits = adapter.limits();
+    adapter
+        .request_device(&wgpu::DeviceDescriptor {
+            required_features: wgpu::Features::empty(),
+            required_limits: limits,
+            label: None,
+            trace: wgpu::Trace::Off,
+            memory_hints: wgpu::MemoryHints::Performance,
+        })
+        .await
+        .map_err(|e| format!("No device: {e}"))
+}
+
+pub struct Renderer {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub format: wgpu::TextureFormat, // 8-bit target format the main pipeline is built for
+    pub render_pipeline: wgpu::RenderPipeline,
+    pub high_precision_pipeline: Option<wgpu::RenderPipeline>, // None when half floats can't be rendered to
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_indices: u32,
+    pub sampler: wgpu::Sampler,
+    pub diffuse_bind_group: wgpu::BindGroup,
+    pub globals_buffer: wgpu::Buffer,
+    pub adjustments_buffer: wgpu::Buffer,
+    pub crop: Option<CropRect>,
+    pub transform: Transform,
+    pub paint_texture: wgpu::Texture,
+    pub curves_texture: wgpu::Texture,
+    pub source_texture: wgpu::Texture,
+    pub source_dimensions: (u32, u32), // of the image `source_texture` shows
+    pub max_texture_dimension: u32, // the device's limit; larger images are previewed downscaled and exported in tiles
+    pub filter_engine: FilterEngine,
+    pub filtered_texture: Option<wgpu::Texture>, // output of the convolution filters, if any
+    pub applied_filters: Option<FilterStack>,    // filters baked into `filtered_texture`
+    pub histogram_pass: Option<HistogramPass>,   // None when the backend has no compute shaders
+}
+
+// source and paint textures of one image, cheap to clone as wgpu textures are shared handles
+#[derive(Clone)]
+pub struct ImageTextures {
+    pub source: wgpu::Texture,
+    pub paint: wgpu::Texture,
+    pub dimensions: (u32, u32), // of the image, the textures are smaller for a preview of one past the texture limit
+}
+
+impl ImageTextures {
+    // video memory held by both textures
+    pub fn bytes(&self) -> u64 {
+        [&self.source, &self.paint]
+            .iter()
+            .map(|texture| {
+                let texel = texture.format().block_copy_size(None).unwrap_or(4) as u64;
+                texture.width() as u64 * texture.height() as u64 * texel
+            })
+            .sum()
+    }
+}
+
+impl Renderer {
+    // a renderer with its own device, for rendering without a page or window
+    pub async fn new() -> Result<Renderer, String> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..wgpu::InstanceDescriptor::default()
+        });
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            })
+            .await
+            .map_err(|e| format!("No adapter found: {e}"))?;
+        let (device, queue) = request_device(&adapter).await?;
+        Ok(Renderer::with_device(
+            device,
+            queue,
+            OFFSCREEN_FORMAT,
+            Capabilities::of(&adapter),
+        ))
+    }
+
+    // builds the pipeline on an existing device, drawing 8-bit images into targets of `format`
+    pub fn with_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        format: wgpu::TextureFormat,
+        capabilities: Capabilities,
+    ) -> Renderer {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("texture_bind_group_layout"),
+            });
+
+        let identity = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0]];
+        let globals = Globals::new(identity, false, !format.is_srgb());
+
+        let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("globals buffer"),
+            contents: bytemuck::bytes_of(&globals),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let adjustments_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("adjustments buffer"),
+            contents: bytemuck::bytes_of(&AdjustmentStack::default().to_gpu()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // nothing to show until the first upload
+        let source_texture = create_source_texture(&device, (1, 1), WORKING_FORMAT);
+        let paint_texture = create_paint_texture(&device, (1, 1));
+        let curves_texture = create_curves_texture(&device);
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[&texture_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let num_indices = INDICES.len() as u32;
+
+        let render_pipeline =
+            create_render_pipeline(&device, &render_pipeline_layout, &shader, format);
+        let high_precision_pipeline = capabilities.half_float_targets.then(|| {
+            create_render_pipeline(
+                &device,
+                &render_pipeline_layout,
+                &shader,
+                HIGH_PRECISION_FORMAT,
+            )
+        });
+
+        let filter_engine = FilterEngine::new(&device);
+        let histogram_pass = capabilities
+            .compute_shaders
+            .then(|| HistogramPass::new(&device));
+
+        let diffuse_bind_group = create_bind_group(
+            &device,
+            &texture_bind_group_layout,
+            &sampler,
+            &source_texture,
+            &paint_texture,
+            &curves_texture,
+            &globals_buffer,
+            &adjustments_buffer,
+        );
+
+        let max_texture_dimension = device.limits().max_texture_dimension_2d;
+        Renderer {
+            device,
+            queue,
+            format,
+            render_pipeline,
+            high_precision_pipeline,
+            vertex_buffer,
+            index_buffer,
+            num_indices,
+            sampler,
+            diffuse_bind_group,
+            globals_buffer,
+            adjustments_buffer,
+            crop: None,
+            transform: Transform::default(),
+            paint_texture,
+            curves_texture,
+            source_dimensions: (source_texture.width(), source_texture.height()),
+            max_texture_dimension,
+            source_texture,
+            filter_engine,
+            filtered_texture: None,
+            applied_filters: None,
+            histogram_pass,
+        }
+    }
+
+    pub fn source_dimensions(&self) -> (u32, u32) {
+        self.source_dimensions
+    }
+
+    // size of the rendered (and exported) image after transforming and cropping
+    pub fn output_dimensions(&self) -> (u32, u32) {
+        match self.crop {
+            Some(rect) => (rect.width, rect.height),
+            None => self.transform.oriented_dimensions(self.source_dimensions()),
+        }
+    }
+
+    // images past the device's texture limit can't be uploaded in one piece, see render_tiled
+    pub fn check_size(&self, (width, height): (u32, u32)) -> Result<(), String> {
+        let max = self.max_texture_dimension;
+        if width > max || height > max {
+            return Err(format!(
+                "{}x{} is larger than the GPU texture limit of {}",
+                width, height, max
+            ));
+        }
+        Ok(())
+    }
+
+    // makes `image` the source, with an empty paint layer of its size
+    pub fn upload(&mut self, image: &DynamicImage) {
+        let textures = self.create_image_textures(image);
+        self.bind_image_textures(&textures);
+    }
+
+    // converts and uploads `image` without showing it, so the textures can be kept for later.
+    // Images past the texture limit get a downscaled copy to be shown with
+    pub fn create_image_textures(&self, image: &DynamicImage) -> ImageTextures {
+        let full_dimensions = image.dimensions();
+        let dimensions = fit_within(full_dimensions, self.max_texture_dimension);
+        let preview;
+        let image = if dimensions == full_dimensions {
+            image
+        } else {
+            preview = image.resize_exact(dimensions.0, dimensions.1, FilterType::Triangle);
+            &preview
+        };
+        // deep images stay deep when the GPU can render half floats, others take the 8-bit path
+        let (format, texels, bytes_per_pixel) =
+            if self.high_precision_pipeline.is_some() && is_high_bit_depth(image) {
+                (HIGH_PRECISION_FORMAT, linear_half_texels(image), 8)
+            } else {
+                (WORKING_FORMAT, image.to_rgba8().into_raw(), 4)
+            };
+
+        let diffuse_texture = create_source_texture(&self.device, dimensions, format);
+        self.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &diffuse_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &texels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_pixel * dimensions.0),
+                rows_per_image: Some(dimensions.1),
+            },
+            diffuse_texture.size(),
+        );
+
+        ImageTextures {
+            source: diffuse_texture,
+            paint: create_paint_texture(&self.device, dimensions),
+            dimensions: full_dimensions,
+        }
+    }
+
+    // makes previously created textures the source and paint layer, no pixels are copied
+    pub fn bind_image_textures(&mut self, textures: &ImageTextures) {
+        self.source_texture = textures.source.clone();
+        self.source_dimensions = textures.dimensions;
+        self.paint_texture = textures.paint.clone();
+        // the filters have to run again on the new source
+        self.filtered_texture = None;
+        self.applied_filters = None;
+        self.rebuild_bind_group();
+    }
+
+    // copies `rect` of a paint layer the size of the source into the paint texture, downscaling
+    // it for the preview of an image past the texture limit
+    pub fn upload_paint(&self, pixels: &RgbaImage, rect: DirtyRect) {
+        let texture = (self.paint_texture.width(), self.paint_texture.height());
+        if pixels.dimensions() != texture {
+            let (scaled, origin) = scale_paint_rect(pixels, rect, texture);
+            let (width, height) = scaled.dimensions();
+            self.write_paint(
+                &scaled,
+                DirtyRect {
+                    x: 0,
+                    y: 0,
+                    width,
+                    height,
+                },
+                origin,
+            );
+            return;
+        }
+        self.write_paint(pixels, rect, (rect.x, rect.y));
+    }
+
+    // copies `rect` of `pixels` to `origin` in the paint texture
+    fn write_paint(&self, pixels: &RgbaImage, rect: DirtyRect, origin: (u32, u32)) {
+        let width = pixels.width();
+        self.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.paint_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: origin.0,
+                    y: origin.1,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels.as_raw(),
+            wgpu::TexelCopyBufferLayout {
+                offset: ((rect.y * width + rect.x) * 4) as u64,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: rect.width,
+                height: rect.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    // binds the filtered image if there is one, the source image otherwise
+    fn rebuild_bind_group(&mut self) {
+        self.diffuse_bind_group = create_bind_group(
+            &self.device,
+            &self.render_pipeline.get_bind_group_layout(0),
+            &self.sampler,
+            self.filtered_texture
+                .as_ref()
+                .unwrap_or(&self.source_texture),
+            &self.paint_texture,
+            &self.curves_texture,
+            &self.globals_buffer,
+            &self.adjustments_buffer,
+        );
+    }
+
+    // uploads everything the shader reads for the uploaded image
+    pub fn apply(&mut self, settings: &RenderSettings) {
+        self.crop = settings.crop;
+        self.transform = settings.transform;
+
+        // a preview of an image past the texture limit is smaller than the image, the blurs
+        // shrink with it to look as they will in the export
+        let scale = self.source_texture.width() as f32 / self.source_dimensions.0 as f32;
+        let filters = if scale < 1.0 {
+            settings.filters.scaled(scale)
+        } else {
+            settings.filters.clone()
+        };
+        // neighbourhood filters only run again when they change
+        if self.applied_filters.as_ref() != Some(&filters) {
+            self.filtered_texture =
+                self.filter_engine
+                    .run(&self.device, &self.queue, &self.source_texture, &filters);
+            self.applied_filters = Some(filters);
+            self.rebuild_bind_group();
+        }
+
+        // straight alpha unless the caller is about to draw to a canvas
+        self.write_globals(self.uv_matrix(), false, self.target_format(), 0.0);
+        self.queue.write_buffer(
+            &self.adjustments_buffer,
+            0,
+            bytemuck::bytes_of(&settings.adjustments.to_gpu()),
+        );
+        self.upload_tone_lut(&settings.levels, &settings.curves);
+    }
+
+    fn upload_tone_lut(&self, levels: &Levels, curves: &CurveSet) {
+        let texels: Vec<u8> = curves
+            .bake(levels)
+            .iter()
+            .flat_map(|v| v.to_bits().to_ne_bytes())
+            .collect();
+        self.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.curves_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &texels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(8 * LUT_SIZE as u32),
+                rows_per_image: Some(1),
+            },
+            wgpu::Extent3d {
+                width: LUT_SIZE as u32,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    // crop and transform of the whole output, see Transform::uv_matrix
+    pub fn uv_matrix(&self) -> [[f32; 4]; 2] {
+        self.transform
+            .uv_matrix(self.crop, self.source_dimensions())
+    }
+
+    // `target` is the format about to be rendered to; half float targets stay linear,
+    // their readback encodes them
+    pub fn write_globals(
+        &self,
+        uv_matrix: [[f32; 4]; 2],
+        premultiply: bool,
+        target: wgpu::TextureFormat,
+        checker_size: f32,
+    ) {
+        let encode_srgb = !target.is_srgb() && target != HIGH_PRECISION_FORMAT;
+        let globals = Globals {
+            checker_size,
+            ..Globals::new(uv_matrix, premultiply, encode_srgb)
+        };
+        self.queue
+            .write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+    }
+
+    // deep sources render to half floats, everything else to the pipeline's format
+    pub fn target_format(&self) -> wgpu::TextureFormat {
+        if self.source_texture.format() == HIGH_PRECISION_FORMAT {
+            HIGH_PRECISION_FORMAT
+        } else {
+            self.format
+        }
+    }
+
+    pub fn encode_render_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        clear: wgpu::Color,
+        viewport: Option<[f32; 4]>,
+    ) {
+        let pipeline = match &self.high_precision_pipeline {
+            Some(pipeline) if format == HIGH_PRECISION_FORMAT => pipeline,
+            _ => &self.render_pipeline,
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                depth_slice: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        if let Some([x, y, width, height]) = viewport {
+            render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+        }
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+
+    // target the size of the output, in the format it renders to
+    pub fn create_target(&self) -> wgpu::Texture {
+        self.create_target_sized(self.output_dimensions())
+    }
+
+    fn create_target_sized(&self, (width, height): (u32, u32)) -> wgpu::Texture {
+        self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Filesaver Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            format: self.target_format(),
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            view_formats: &[],
+        })
+    }
+
+    // renders the uploaded image with the applied settings into a new target and submits it
+    pub fn render_uploaded(&self) -> wgpu::Texture {
+        let texture = self.create_target();
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Offscreen Encoder"),
+            });
+        self.encode_render_pass(
+            &mut encoder,
+            &view,
+            texture.format(),
+            wgpu::Color::TRANSPARENT,
+            None,
+        );
+        self.queue.submit(Some(encoder.finish()));
+        texture
+    }
+
+    // uploads `image`, its paint layer if any, and `settings`
+    fn prepare(
+        &mut self,
+        image: &DynamicImage,
+        paint: Option<&RgbaImage>,
+        settings: &RenderSettings,
+    ) -> Result<(), String> {
+        self.check_size(image.dimensions())?;
+        self.upload(image);
+        if let Some(paint) = paint.filter(|paint| paint.dimensions() == image.dimensions()) {
+            let (width, height) = paint.dimensions();
+            self.upload_paint(
+                paint,
+                DirtyRect {
+                    x: 0,
+                    y: 0,
+                    width,
+                    height,
+                },
+            );
+        }
+        self.apply(settings);
+        Ok(())
+    }
+
+    // renders `image` into a texture that stays on the GPU, half floats for deep sources
+    pub fn render_texture(
+        &mut self,
+        image: &DynamicImage,
+        paint: Option<&RgbaImage>,
+        settings: &RenderSettings,
+    ) -> Result<wgpu::Texture, String> {
+        self.prepare(image, paint, settings)?;
+        Ok(self.render_uploaded())
+    }
+
+    // renders `image` and starts reading it back, see Readback::into_image
+    pub fn render(
+        &mut self,
+        image: &DynamicImage,
+        paint: Option<&RgbaImage>,
+        settings: &RenderSettings,
+    ) -> Result<Readback, String> {
+        let texture = self.render_texture(image, paint, settings)?;
+        let encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            });
+        Ok(self.submit_readback(encoder, &texture))
+    }
+
+    // renders `image` down to 8-bit sRGB pixels
+    pub async fn render_rgba(
+        &mut self,
+        image: &DynamicImage,
+        paint: Option<&RgbaImage>,
+        settings: &RenderSettings,
+    ) -> Result<RgbaImage, String> {
+        let rendered = self.render_image(image, paint, settings).await?;
+        Ok(rendered.to_rgba8())
+    }
+
+    // renders `image` in one piece, or in tiles when it is past the texture limit
+    pub async fn render_image(
+        &mut self,
+        image: &DynamicImage,
+        paint: Option<&RgbaImage>,
+        settings: &RenderSettings,
+    ) -> Result<DynamicImage, String> {
+        if self.check_size(image.dimensions()).is_err() {
+            return self.render_tiled(image, paint, settings).await;
+        }
+        self.render(image, paint, settings)?.into_image().await
+    }
+
+    // renders `image` tile by tile, each from only the part of the source it shows, and stitches
+    // the tiles into one image
+    pub async fn render_tiled(
+        &mut self,
+        image: &DynamicImage,
+        paint: Option<&RgbaImage>,
+        settings: &RenderSettings,
+    ) -> Result<DynamicImage, String> {
+        let source = image.dimensions();
+        let output = match settings.crop {
+            Some(rect) => (rect.width, rect.height),
+            None => settings.transform.oriented_dimensions(source),
+        };
+        let matrix = settings.transform.uv_matrix(settings.crop, source);
+        let margin = settings.filters.reach() + SAMPLING_MARGIN;
+        let tile = tile_size(self.max_texture_dimension, margin)?;
+        let paint = paint.filter(|paint| paint.dimensions() == source);
+
+        let mut stitched = if self.high_precision_pipeline.is_some() && is_high_bit_depth(image) {
+            DynamicImage::new_rgba16(output.0, output.1)
+        } else {
+            DynamicImage::new_rgba8(output.0, output.1)
+        };
+        for rect in tile_grid(output, tile) {
+            // tiles in the uncovered corners of a straightened image stay transparent
+            let Some(region) = source_region(matrix, output, rect, source, margin) else {
+                continue;
+            };
+            self.check_size((region.width, region.height))?;
+            self.upload(&image.crop_imm(region.x, region.y, region.width, region.height));
+            if let Some(paint) = paint {
+                let part =
+                    imageops::crop_imm(paint, region.x, region.y, region.width, region.height)
+                        .to_image();
+                self.upload_paint(
+                    &part,
+                    DirtyRect {
+                        x: 0,
+                        y: 0,
+                        width: region.width,
+                        height: region.height,
+                    },
+                );
+            }
+            // filters run on the region, the margin keeps their clamped edges outside the tile
+            self.apply(settings);
+            self.write_globals(
+                tile_matrix(matrix, output, rect, source, region),
+                false,
+                self.target_format(),
+                0.0,
+            );
+
+            let texture = self.create_target_sized((rect.width, rect.height));
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Tile Encoder"),
+                });
+            self.encode_render_pass(
+                &mut encoder,
+                &view,
+                texture.format(),
+                wgpu::Color::TRANSPARENT,
+                None,
+            );
+            let part = self.submit_readback(encoder, &texture).into_image().await?;
+            paste(&mut stitched, &part, rect.x, rect.y);
+        }
+        Ok(stitched)
+    }
+
+    // a renderer on the same device with textures of its own, for renders that outlive a borrow of this one
+    pub fn sibling(&self) -> Renderer {
+        let capabilities = Capabilities {
+            compute_shaders: self.histogram_pass.is_some(),
+            half_float_targets: self.high_precision_pipeline.is_some(),
+        };
+        let mut renderer = Renderer::with_device(
+            self.device.clone(),
+            self.queue.clone(),
+            self.format,
+            capabilities,
+        );
+        renderer.max_texture_dimension = self.max_texture_dimension;
+        renderer
+    }
+
+    // copies `texture` into a new buffer, rows padded to 256 bytes, submits `encoder` and starts mapping
+    pub fn submit_readback(
+        &self,
+        mut encoder: wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) -> Readback {
+        let (width, height) = (texture.width(), texture.height());
+        let high_precision = texture.format() == HIGH_PRECISION_FORMAT;
+        let bytes_per_pixel = if high_precision { 8 } else { 4 };
+        let padded_bytes_per_row = align_to_256(bytes_per_pixel * width);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Output Buffer"),
+            size: (height * padded_bytes_per_row) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |res| {
+            sender.send(res).unwrap();
+        });
+        Readback {
+            buffer,
+            receiver,
+            device: self.device.clone(),
+            bgra: matches!(
+                texture.format(),
+                wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+            ),
+            high_precision,
+            width,
+            height,
+            padded_bytes_per_row,
+        }
+    }
+}
+
+// copies a rendered tile into the stitched image, keeping 16-bit tiles 16-bit
+fn paste(stitched: &mut DynamicImage, tile: &DynamicImage, x: u32, y: u32) {
+    match (stitched, tile) {
+        (DynamicImage::ImageRgba16(out), DynamicImage::ImageRgba16(tile)) => {
+            imageops::replace(out, tile, x as i64, y as i64)
+        }
+        (out, tile) => imageops::replace(out, &tile.to_rgba8(), x as i64, y as i64),
+    }
+}
+
+// `rect` of a full size paint layer scaled down for a `texture` sized one, grown to whole texels,
+// and where it goes in the texture
+fn scale_paint_rect(
+    pixels: &RgbaImage,
+    rect: DirtyRect,
+    texture: (u32, u32),
+) -> (RgbaImage, (u32, u32)) {
+    let (sx, sy) = (
+        texture.0 as f64 / pixels.width() as f64,
+        texture.1 as f64 / pixels.height() as f64,
+    );
+    let x0 = ((rect.x as f64 * sx).floor() as u32).min(texture.0 - 1);
+    let y0 = ((rect.y as f64 * sy).floor() as u32).min(texture.1 - 1);
+    let x1 = (((rect.x + rect.width) as f64 * sx).ceil() as u32).clamp(x0 + 1, texture.0);
+    let y1 = (((rect.y + rect.height) as f64 * sy).ceil() as u32).clamp(y0 + 1, texture.1);
+    // the full size pixels under those texels
+    let px0 = ((x0 as f64 / sx).floor() as u32).min(pixels.width() - 1);
+    let py0 = ((y0 as f64 / sy).floor() as u32).min(pixels.height() - 1);
+    let px1 = ((x1 as f64 / sx).ceil() as u32).clamp(px0 + 1, pixels.width());
+    let py1 = ((y1 as f64 / sy).ceil() as u32).clamp(py0 + 1, pixels.height());
+    let region = imageops::crop_imm(pixels, px0, py0, px1 - px0, py1 - py0).to_image();
+    (
+        imageops::resize(&region, x1 - x0, y1 - y0, FilterType::Triangle),
+        (x0, y0),
+    )
+}
+
+// the shader's bindings: source (or its filtered copy), sampler, globals, paint, adjustments and tone LUT
+#[allow(clippy::too_many_arguments)]
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    source: &wgpu::Texture,
+    paint: &wgpu::Texture,
+    curves: &wgpu::Texture,
+    globals: &wgpu::Buffer,
+    adjustments: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    let source_view = source.create_view(&wgpu::TextureViewDescriptor::default());
+    let paint_view = paint.create_view(&wgpu::TextureViewDescriptor::default());
+    let curves_view = curves.create_view(&wgpu::TextureViewDescriptor::default());
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&source_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: globals.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&paint_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: adjustments.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::TextureView(&curves_view),
+            },
+        ],
+        label: Some("diffuse_bind_group"),
+    })
+}
+
+// source image texture, sRGB 8-bit or linear half floats
+fn create_source_texture(
+    device: &wgpu::Device,
+    dimensions: (u32, u32),
+    format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width: dimensions.0,
+            height: dimensions.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        label: Some("diffuse_texture"),
+        view_formats: &[],
+    })
+}
+
+// the main pipeline, rendering into targets of `format`
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[self::Vertex::desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None, // the shader's alpha is written as is
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList, // 1.
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw, // 2.
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+
+            unclipped_depth: false,
+
+            conservative: false,
+        },
+        depth_stencil: None, // 1.
+        multisample: wgpu::MultisampleState {
+            count: 1,                         // 2.
+            mask: !0,                         // 3.
+            alpha_to_coverage_enabled: false, // 4.
+        },
+        multiview: None, // 5.
+        cache: None,     // 6.
+    })
+}
+
+// transparent layer the brush strokes are uploaded to, composited over the image
+fn create_paint_texture(device: &wgpu::Device, dimensions: (u32, u32)) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width: dimensions.0,
+            height: dimensions.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: WORKING_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        label: Some("paint_texture"),
+        view_formats: &[],
+    })
+}
+
+// LUT_SIZE x 1 lookup table of the levels and curves adjustments, read with textureLoad in the shader
+fn create_curves_texture(device: &wgpu::Device) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width: LUT_SIZE as u32,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        label: Some("curves_texture"),
+        view_formats: &[],
+    })
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    position: [f32; 3],
+    tex_coords: [f32; 2],
+}
+impl Vertex {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2, // NEW!
+                },
+            ],
+        }
+    }
+}
+pub const VERTICES: &[Vertex] = &[
+    Vertex {
+        position: [-1.0, -1.0, 0.0],
+        tex_coords: [0.0, 1.0],
+    },
+    Vertex {
+        position: [1.0, -1.0, 0.0],
+        tex_coords: [1.0, 1.0],
+    },
+    Vertex {
+        position: [1.0, 1.0, 0.0],
+        tex_coords: [1.0, 0.0],
+    },
+    Vertex {
+        position: [-1.0, 1.0, 0.0],
+        tex_coords: [0.0, 0.0],
+    },
+];
+
+pub const INDICES: &[u16] = &[
+    0, 1, 2, //first
+    0, 2, 3, //second
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::adjustments::AdjustmentKind;
+    use crate::state::filters::Filter;
+    use crate::state::reference;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    // native wgpu futures resolve once the device is polled, which the renderer does itself
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    // machines without a GPU or software adapter skip the comparison
+    fn renderer() -> Option<Renderer> {
+        match block_on(Renderer::new()) {
+            Ok(renderer) => Some(renderer),
+            Err(err) => {
+                eprintln!("skipping GPU test: {err}");
+                None
+            }
+        }
+    }
+
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(32, 16, |x, y| {
+            Rgba([(x * 8) as u8, (y * 16) as u8, 128, 255 - (x * 4) as u8])
+        }))
+    }
+
+    fn assert_within(gpu: &RgbaImage, cpu: &RgbaImage, tolerance: u8) {
+        assert_eq!(gpu.dimensions(), cpu.dimensions());
+        for (x, y, pixel) in gpu.enumerate_pixels() {
+            let expected = cpu.get_pixel(x, y);
+            let close = pixel
+                .0
+                .iter()
+                .zip(expected.0)
+                .all(|(a, b)| a.abs_diff(b) <= tolerance);
+            assert!(
+                close,
+                "pixel ({x}, {y}): GPU {:?}, CPU {:?}",
+                pixel.0, expected.0
+            );
+        }
+    }
+
+    #[test]
+    fn unadjusted_render_matches_the_reference() {
+        let Some(mut renderer) = renderer() else {
+            return;
+        };
+        let source = gradient();
+        let settings = RenderSettings::default();
+        let gpu = block_on(renderer.render_rgba(&source, None, &settings)).unwrap();
+        assert_within(&gpu, &reference::render(&source, None, &settings), 1);
+    }
+
+    #[test]
+    fn adjusted_and_rotated_render_matches_the_reference() {
+        let Some(mut renderer) = renderer() else {
+            return;
+        };
+        let source = gradient();
+        let mut settings = RenderSettings::default();
+        settings.adjustments.push(AdjustmentKind::Hsv {
+            hue: 0.25,
+            saturation: 0.3,
+            value: -0.2,
+        });
+        settings.transform.quarter_turns = 1;
+        settings.crop = Some(CropRect {
+            x: 2,
+            y: 4,
+            width: 12,
+            height: 20,
+        });
+        let gpu = block_on(renderer.render_rgba(&source, None, &settings)).unwrap();
+        assert_within(&gpu, &reference::render(&source, None, &settings), 2);
+    }
+
+    #[test]
+    fn sharpen_and_custom_kernels_match_the_reference() {
+        let Some(mut renderer) = renderer() else {
+            return;
+        };
+        // the edge kernel runs on hard edges, near black on a gradient its output sits where
+        // sRGB is steepest and rounding differences grow past the tolerance
+        let square = DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, y| {
+            let inside = (4..12).contains(&x) && (4..12).contains(&y);
+            if inside {
+                Rgba([255, 200, 100, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        }));
+        let edges = Filter::Custom {
+            size: 3,
+            weights: vec![-1.0, -1.0, -1.0, -1.0, 8.0, -1.0, -1.0, -1.0, -1.0],
+            normalize: false,
+        };
+        for (source, filter) in [
+            (gradient(), Filter::Sharpen { amount: 0.5 }),
+            (square, edges),
+        ] {
+            let mut settings = RenderSettings::default();
+            settings.filters.filters.push(filter);
+            let gpu = block_on(renderer.render_rgba(&source, None, &settings)).unwrap();
+            assert_within(&gpu, &reference::render(&source, None, &settings), 2);
+        }
+    }
+
+    #[test]
+    fn tiled_render_matches_the_whole_render() {
+        let Some(mut renderer) = renderer() else {
+            return;
+        };
+        let source = DynamicImage::ImageRgba8(RgbaImage::from_fn(90, 70, |x, y| {
+            Rgba([(x * 2) as u8, (y * 3) as u8, (x + y) as u8, 255])
+        }));
+        let paint = RgbaImage::from_fn(90, 70, |x, y| {
+            let distance = (x as i32 - 45).unsigned_abs() + (y as i32 - 40).unsigned_abs();
+            Rgba([255, 0, 0, 200u32.saturating_sub(distance * 8) as u8])
+        });
+        let mut settings = RenderSettings::default();
+        settings
+            .filters
+            .filters
+            .push(Filter::GaussianBlur { radius: 3.0 });
+        // straightening is covered by the tile matrix tests, at 1:1 it flips the sampler between
+        // nearest and linear filtering which differs slightly between the two renders
+        settings.transform.quarter_turns = 1;
+        settings.crop = Some(CropRect {
+            x: 3,
+            y: 5,
+            width: 80,
+            height: 60,
+        });
+        let whole = block_on(renderer.render_rgba(&source, Some(&paint), &settings)).unwrap();
+        // a limit below the image forces a grid of tiles
+        renderer.max_texture_dimension = 48;
+        let tiled = block_on(renderer.render_rgba(&source, Some(&paint), &settings)).unwrap();
+        assert_within(&tiled, &whole, 2);
+    }
+
+    #[test]
+    fn downscaled_previews_blur_like_the_export() {
+        let Some(mut renderer) = renderer() else {
+            return;
+        };
+        let source = DynamicImage::ImageRgba8(RgbaImage::from_fn(256, 128, |x, y| {
+            let inside = (64..192).contains(&x) && (32..96).contains(&y);
+            if inside {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        }));
+        let mut settings = RenderSettings::default();
+        settings
+            .filters
+            .filters
+            .push(Filter::GaussianBlur { radius: 8.0 });
+        renderer.max_texture_dimension = 128;
+        let export = block_on(renderer.render_rgba(&source, None, &settings)).unwrap();
+        let export = imageops::resize(&export, 128, 64, FilterType::Triangle);
+
+        // the preview the editor shows, half the size of the image
+        renderer.upload(&source);
+        renderer.apply(&settings);
+        let texture = renderer.create_target_sized((128, 64));
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        renderer.encode_render_pass(
+            &mut encoder,
+            &view,
+            texture.format(),
+            wgpu::Color::TRANSPARENT,
+            None,
+        );
+        let readback = renderer.submit_readback(encoder, &texture);
+        let preview = block_on(readback.into_image()).unwrap().to_rgba8();
+        // the preview is resampled before the blur and the export after it, which moves the
+        // square's edges a little; a blur at full strength on the preview is several times off
+        let diffs: Vec<u8> = preview
+            .pixels()
+            .zip(export.pixels())
+            .map(|(a, b)| {
+                a.0.iter()
+                    .zip(b.0)
+                    .map(|(x, y)| x.abs_diff(y))
+                    .max()
+                    .unwrap()
+            })
+            .collect();
+        let mean = diffs.iter().map(|&d| d as f32).sum::<f32>() / diffs.len() as f32;
+        assert!(
+            mean < 2.0 && diffs.iter().all(|&d| d <= 20),
+            "mean difference {mean}"
+        );
+    }
+
+    #[test]
+    fn images_past_the_limit_are_previewed_downscaled() {
+        let Some(mut renderer) = renderer() else {
+            return;
+        };
+        renderer.max_texture_dimension = 48;
+        let textures = renderer.create_image_textures(&DynamicImage::new_rgba8(90, 70));
+        assert_eq!(
+            (textures.source.width(), textures.source.height()),
+            (48, 37)
+        );
+        renderer.bind_image_textures(&textures);
+        assert_eq!(renderer.source_dimensions(), (90, 70));
+        // strokes on the full size layer land on the matching preview texels
+        let (scaled, origin) = scale_paint_rect(
+            &RgbaImage::new(90, 70),
+            DirtyRect {
+                x: 45,
+                y: 35,
+                width: 10,
+                height: 10,
+            },
+            (48, 37),
+        );
+        assert_eq!(origin, (24, 18));
+        assert_eq!(scaled.dimensions(), (6, 6));
+    }
+}
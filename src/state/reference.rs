@@ -0,0 +1,696 @@
+// CPU version of the render pipeline, for machines without a GPU and for testing edits.
+// Each function mirrors the one of the same name in shader.wgsl or convolve.wgsl, keep them in step.
+use crate::state::adjustments::{AdjustmentUniform, GpuLayer};
+use crate::state::color::{half_to_u16, is_high_bit_depth, linear_to_srgb, srgb_to_linear, to_u8};
+use crate::state::customlib::RenderSettings;
+use crate::state::filters::KernelPass;
+use half::f16;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba, Rgba32FImage, RgbaImage};
+
+type Rgb = [f32; 3];
+
+fn srgb_encode(c: Rgb) -> Rgb {
+    c.map(linear_to_srgb)
+}
+
+fn srgb_decode(c: Rgb) -> Rgb {
+    c.map(srgb_to_linear)
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+
+pub fn hsv2rgb([h, s, v]: Rgb) -> Rgb {
+    let h = h * 6.0;
+    let i = h.floor();
+    let f = h - i;
+    let p = v * (1.0 - s);
+    let q = v * (1.0 - s * f);
+    let t = v * (1.0 - s * (1.0 - f));
+    if i == 0.0 {
+        return [v, t, p];
+    }
+    if i == 1.0 {
+        return [q, v, p];
+    }
+    if i == 2.0 {
+        return [p, v, t];
+    }
+    if i == 3.0 {
+        return [p, q, v];
+    }
+    if i == 4.0 {
+        return [t, p, v];
+    }
+    [v, p, q]
+}
+
+pub fn rgb2hsv([r, g, b]: Rgb) -> Rgb {
+    let mx = r.max(g.max(b));
+    let mn = r.min(g.min(b));
+    let d = mx - mn;
+    let mut h = 0.0;
+    if d != 0.0 {
+        h = if mx == r {
+            (g - b) / d
+        } else if mx == g {
+            2.0 + (b - r) / d
+        } else {
+            4.0 + (r - g) / d
+        };
+        h /= 6.0;
+        if h < 0.0 {
+            h += 1.0;
+        }
+    }
+    if mx == 0.0 {
+        [h, 0.0, mx]
+    } else {
+        [h, d / mx, mx]
+    }
+}
+
+pub fn hue_shift_rgb(color: Rgb, hue: f32) -> Rgb {
+    let k = 0.57735; // (1 / sqrt(3)), the gray axis
+    let (sin, cos) = hue.sin_cos();
+    let [r, g, b] = color;
+    let cross = [k * b - k * g, k * r - k * b, k * g - k * r];
+    let dot = k * (r + g + b);
+    // Rodrigues' rotation formula in RGB space
+    [0, 1, 2].map(|i| color[i] * cos + cross[i] * sin + k * dot * (1.0 - cos))
+}
+
+pub fn apply_hsv(color: Rgb, params: [f32; 4]) -> Rgb {
+    let shifted = hue_shift_rgb(color, params[0]);
+    let mut hsv = rgb2hsv(shifted);
+    hsv[1] *= params[1] + 1.0;
+    hsv[2] *= params[2] + 1.0;
+    hsv2rgb(hsv)
+}
+
+pub fn apply_light(color: Rgb, params0: [f32; 4], params1: [f32; 4]) -> Rgb {
+    let exposure = params0[0].exp2();
+    srgb_encode(color.map(|c| c * exposure)).map(|c| {
+        let c = c + params0[1];
+        let c = (c - params0[3]) * (1.0 + params0[2]) + params0[3];
+        srgb_to_linear(c.max(0.0).powf(1.0 / params1[0].max(0.01)))
+    })
+}
+
+// takes and returns linear light
+pub fn apply_layer(layer: &GpuLayer, color: Rgb) -> Rgb {
+    match layer.kind {
+        0 => srgb_decode(apply_hsv(srgb_encode(color), layer.params[0])),
+        1 => apply_light(color, layer.params[0], layer.params[1]),
+        _ => color,
+    }
+}
+
+// `lut` as baked by CurveSet::bake
+fn curves_lookup(lut: &[f16], channel: usize, x: f32) -> f32 {
+    let last = lut.len() / 4 - 1;
+    let pos = x.clamp(0.0, 1.0) * last as f32;
+    let i = pos.floor() as usize;
+    let j = (i + 1).min(last);
+    let texel = |k: usize| lut[k * 4 + channel].to_f32();
+    mix(texel(i), texel(j), pos.fract())
+}
+
+pub fn apply_curves(lut: &[f16], color: Rgb) -> Rgb {
+    let c = srgb_encode(color);
+    srgb_decode([0, 1, 2].map(|i| curves_lookup(lut, i, c[i])))
+}
+
+// the adjustment layers of fs_main, bottom to top, in linear light
+pub fn apply_adjustments(adjustments: &AdjustmentUniform, mut rgb: Rgb) -> Rgb {
+    for layer in &adjustments.layers[..adjustments.count as usize] {
+        let applied = apply_layer(layer, rgb);
+        rgb = [0, 1, 2].map(|i| mix(rgb[i], applied[i], layer.opacity));
+    }
+    rgb
+}
+
+// fs_main for one pixel, in linear light with straight alpha; the sRGB encode on output is left
+// to the caller, as it is to the target format on the GPU
+pub fn shade(
+    source: [f32; 4],
+    paint: [f32; 4],
+    inside: bool,
+    adjustments: &AdjustmentUniform,
+    lut: &[f16],
+) -> [f32; 4] {
+    let rgb = apply_adjustments(adjustments, [source[0], source[1], source[2]]);
+    let rgb = apply_curves(lut, rgb);
+
+    let alpha = if inside {
+        paint[3] + source[3] * (1.0 - paint[3])
+    } else {
+        0.0
+    };
+    let mut color = [0.0; 3];
+    if alpha > 0.0 {
+        color = [0, 1, 2]
+            .map(|i| (paint[i] * paint[3] + rgb[i] * source[3] * (1.0 - paint[3])) / alpha);
+    }
+    [color[0], color[1], color[2], alpha]
+}
+
+// one pass of convolve.wgsl over a linear image
+pub fn convolve(image: &Rgba32FImage, pass: &KernelPass) -> Rgba32FImage {
+    let (width, height) = image.dimensions();
+    let (half_w, half_h) = ((pass.width / 2) as i64, (pass.height / 2) as i64);
+    Rgba32FImage::from_fn(width, height, |x, y| {
+        let mut sum = [0.0f32; 4];
+        for ky in 0..pass.height as i64 {
+            for kx in 0..pass.width as i64 {
+                // edges are extended by clamping to the border pixels
+                let sx = (x as i64 + kx - half_w).clamp(0, width as i64 - 1) as u32;
+                let sy = (y as i64 + ky - half_h).clamp(0, height as i64 - 1) as u32;
+                let [r, g, b, a] = image.get_pixel(sx, sy).0;
+                let w = pass.weights[(ky * pass.width as i64 + kx) as usize];
+                // premultiplied, so transparent pixels do not bleed their color into the result
+                for (total, v) in sum.iter_mut().zip([r * a, g * a, b * a, a]) {
+                    *total += v * w;
+                }
+            }
+        }
+        let alpha = if pass.blur_alpha {
+            sum[3].clamp(0.0, 1.0)
+        } else {
+            image.get_pixel(x, y)[3]
+        };
+        if alpha <= 0.0 {
+            return Rgba([0.0; 4]);
+        }
+        let [r, g, b] = [sum[0], sum[1], sum[2]].map(|c| (c / alpha).clamp(0.0, 1.0));
+        Rgba([r, g, b, alpha])
+    })
+}
+
+// rounds a linear image to what a texture of the source's format would hold
+fn quantize(image: &mut Rgba32FImage, high_precision: bool) {
+    for pixel in image.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        pixel.0 = if high_precision {
+            [r, g, b, a].map(|v| f16::from_f32(v).to_f32())
+        } else {
+            let [r, g, b] =
+                [r, g, b].map(|v| srgb_to_linear(to_u8(linear_to_srgb(v)) as f32 / 255.0));
+            [r, g, b, to_u8(a) as f32 / 255.0]
+        };
+    }
+}
+
+// an sRGB image decoded to linear light, as sampling an sRGB texture returns it
+fn decode(image: &DynamicImage) -> Rgba32FImage {
+    let mut linear = image.to_rgba32f();
+    for pixel in linear.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        pixel.0 = [
+            srgb_to_linear(r),
+            srgb_to_linear(g),
+            srgb_to_linear(b),
+            a.clamp(0.0, 1.0),
+        ];
+    }
+    linear
+}
+
+// bilinear sampling with clamped edges, like the linear filtering sampler of the render pass
+fn sample(image: &Rgba32FImage, (u, v): (f32, f32)) -> [f32; 4] {
+    let (width, height) = image.dimensions();
+    let x = u * width as f32 - 0.5;
+    let y = v * height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let texel = |tx: f32, ty: f32| {
+        let tx = (tx as i64).clamp(0, width as i64 - 1) as u32;
+        let ty = (ty as i64).clamp(0, height as i64 - 1) as u32;
+        image.get_pixel(tx, ty).0
+    };
+    let (a, b) = (texel(x0, y0), texel(x0 + 1.0, y0));
+    let (c, d) = (texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0));
+    [0, 1, 2, 3].map(|i| mix(mix(a[i], b[i], fx), mix(c[i], d[i], fx), fy))
+}
+
+// renders `source` with `paint` strokes on top, in linear light with straight alpha
+pub fn render_linear(
+    source: &DynamicImage,
+    paint: Option<&RgbaImage>,
+    settings: &RenderSettings,
+) -> Rgba32FImage {
+    let dimensions = source.dimensions();
+    let high_precision = is_high_bit_depth(source);
+    let mut texels = decode(source);
+    quantize(&mut texels, high_precision);
+    for pass in settings.filters.passes() {
+        texels = convolve(&texels, &pass);
+        quantize(&mut texels, high_precision);
+    }
+    let paint = paint.map(|paint| decode(&DynamicImage::ImageRgba8(paint.clone())));
+
+    let adjustments = settings.adjustments.to_gpu();
+    let lut = settings.curves.bake(&settings.levels);
+    let (width, height) = match settings.crop {
+        Some(rect) => (rect.width, rect.height),
+        None => settings.transform.oriented_dimensions(dimensions),
+    };
+    let [uv_u, uv_v] = settings.transform.uv_matrix(settings.crop, dimensions);
+    Rgba32FImage::from_fn(width.max(1), height.max(1), |x, y| {
+        // fragments are shaded at pixel centers
+        let canvas = (
+            (x as f32 + 0.5) / width as f32,
+            (y as f32 + 0.5) / height as f32,
+        );
+        let uv = (
+            uv_u[0] * canvas.0 + uv_u[1] * canvas.1 + uv_u[2],
+            uv_v[0] * canvas.0 + uv_v[1] * canvas.1 + uv_v[2],
+        );
+        let inside = (0.0..=1.0).contains(&uv.0) && (0.0..=1.0).contains(&uv.1);
+        let paint = paint.as_ref().map_or([0.0; 4], |paint| sample(paint, uv));
+        Rgba(shade(
+            sample(&texels, uv),
+            paint,
+            inside,
+            &adjustments,
+            &lut,
+        ))
+    })
+}
+
+// average colour of the 3x3 source pixels around (x, y) as it enters the levels: filtered and
+// adjusted, sRGB encoded like the LUT input. Only the neighbourhood the filters read is rendered
+pub fn levels_input(
+    source: &DynamicImage,
+    settings: &RenderSettings,
+    (x, y): (f64, f64),
+) -> [f32; 3] {
+    let (width, height) = source.dimensions();
+    let cx = (x.floor().max(0.0) as u32).min(width.saturating_sub(1));
+    let cy = (y.floor().max(0.0) as u32).min(height.saturating_sub(1));
+    let reach = settings.filters.reach() + 1;
+    let (x0, y0) = (cx.saturating_sub(reach), cy.saturating_sub(reach));
+    let x1 = (cx + reach + 1).min(width);
+    let y1 = (cy + reach + 1).min(height);
+
+    let high_precision = is_high_bit_depth(source);
+    let mut texels = decode(&source.crop_imm(x0, y0, x1 - x0, y1 - y0));
+    quantize(&mut texels, high_precision);
+    for pass in settings.filters.passes() {
+        texels = convolve(&texels, &pass);
+        quantize(&mut texels, high_precision);
+    }
+
+    let adjustments = settings.adjustments.to_gpu();
+    let mut sum = [0.0f32; 3];
+    let mut count = 0.0;
+    for py in cy.saturating_sub(1)..=(cy + 1).min(height.saturating_sub(1)) {
+        for px in cx.saturating_sub(1)..=(cx + 1).min(width.saturating_sub(1)) {
+            let [r, g, b, _] = texels.get_pixel(px - x0, py - y0).0;
+            let encoded = srgb_encode(apply_adjustments(&adjustments, [r, g, b]));
+            for c in 0..3 {
+                sum[c] += encoded[c];
+            }
+            count += 1.0;
+        }
+    }
+    sum.map(|s| s / count)
+}
+
+// renders to 8-bit sRGB, what an export of the working format holds
+pub fn render(
+    source: &DynamicImage,
+    paint: Option<&RgbaImage>,
+    settings: &RenderSettings,
+) -> RgbaImage {
+    let linear = render_linear(source, paint, settings);
+    RgbaImage::from_fn(linear.width(), linear.height(), |x, y| {
+        let [r, g, b, a] = linear.get_pixel(x, y).0;
+        Rgba([
+            to_u8(linear_to_srgb(r)),
+            to_u8(linear_to_srgb(g)),
+            to_u8(linear_to_srgb(b)),
+            to_u8(a),
+        ])
+    })
+}
+
+// renders to what an export holds: 16 bits per channel for deep sources, rounded through half
+// floats like the GPU's high precision path, and 8 bits otherwise
+pub fn render_image(
+    source: &DynamicImage,
+    paint: Option<&RgbaImage>,
+    settings: &RenderSettings,
+) -> DynamicImage {
+    if !is_high_bit_depth(source) {
+        return DynamicImage::ImageRgba8(render(source, paint, settings));
+    }
+    let linear = render_linear(source, paint, settings);
+    let deep = ImageBuffer::<Rgba<u16>, _>::from_fn(linear.width(), linear.height(), |x, y| {
+        let pixel = linear.get_pixel(x, y).0;
+        Rgba([0, 1, 2, 3].map(|i| half_to_u16(f16::from_f32(pixel[i]).to_bits(), i == 3)))
+    });
+    DynamicImage::ImageRgba16(deep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::adjustments::AdjustmentKind;
+    use crate::state::filters::Filter;
+    use crate::state::transform::Transform;
+    use std::f32::consts::PI;
+
+    fn assert_close(a: Rgb, b: Rgb) {
+        assert!(
+            a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-5),
+            "{a:?} != {b:?}"
+        );
+    }
+
+    fn hsv_settings(hue: f32, saturation: f32, value: f32) -> RenderSettings {
+        let mut settings = RenderSettings::default();
+        settings.adjustments.push(AdjustmentKind::Hsv {
+            hue,
+            saturation,
+            value,
+        });
+        settings
+    }
+
+    // two opaque pixels side by side, red then green
+    fn red_green() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 255, 0, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn hsv_conversions_match_known_values() {
+        for (rgb, hsv) in [
+            ([1.0, 0.0, 0.0], [0.0, 1.0, 1.0]),
+            ([0.0, 0.5, 0.5], [0.5, 1.0, 0.5]),
+            ([0.5, 0.25, 0.75], [0.75, 2.0 / 3.0, 0.75]),
+            ([0.2, 0.2, 0.2], [0.0, 0.0, 0.2]),
+            ([0.0, 0.0, 0.0], [0.0, 0.0, 0.0]),
+        ] {
+            assert_close(rgb2hsv(rgb), hsv);
+            assert_close(hsv2rgb(hsv), rgb);
+        }
+    }
+
+    #[test]
+    fn a_third_of_a_turn_cycles_the_primaries() {
+        assert_close(
+            hue_shift_rgb([1.0, 0.0, 0.0], 2.0 * PI / 3.0),
+            [0.0, 1.0, 0.0],
+        );
+        assert_close(
+            hue_shift_rgb([0.0, 0.0, 1.0], 2.0 * PI / 3.0),
+            [1.0, 0.0, 0.0],
+        );
+        // grays lie on the axis and do not move
+        assert_close(hue_shift_rgb([0.4, 0.4, 0.4], 1.0), [0.4, 0.4, 0.4]);
+    }
+
+    #[test]
+    fn saturation_and_value_scale_hsv() {
+        assert_close(
+            apply_hsv([0.8, 0.2, 0.4], [0.0, -1.0, 0.0, 0.0]),
+            [0.8, 0.8, 0.8],
+        );
+        assert_close(
+            apply_hsv([0.8, 0.2, 0.4], [0.0, 0.0, -0.5, 0.0]),
+            [0.4, 0.1, 0.2],
+        );
+    }
+
+    // exposure, brightness, contrast, pivot then gamma, as the light layer packs them
+    fn light(color: Rgb, exposure: f32, contrast: f32, pivot: f32, gamma: f32) -> Rgb {
+        apply_light(
+            color,
+            [exposure, 0.0, contrast, pivot],
+            [gamma, 0.0, 0.0, 0.0],
+        )
+    }
+
+    #[test]
+    fn a_stop_of_exposure_doubles_linear_light() {
+        assert_close(light([0.1, 0.2, 0.05], 1.0, 0.0, 0.5, 1.0), [0.2, 0.4, 0.1]);
+        assert_close(
+            light([0.1, 0.2, 0.05], -1.0, 0.0, 0.5, 1.0),
+            [0.05, 0.1, 0.025],
+        );
+    }
+
+    #[test]
+    fn contrast_scales_around_the_pivot() {
+        // contrast works on encoded values, the pivot is an sRGB level
+        let at_pivot = [srgb_to_linear(0.4); 3];
+        assert_close(light(at_pivot, 0.0, 0.8, 0.4, 1.0), at_pivot);
+        let above = [srgb_to_linear(0.6); 3];
+        assert_close(light(above, 0.0, 0.5, 0.4, 1.0), [srgb_to_linear(0.7); 3]);
+    }
+
+    #[test]
+    fn gamma_is_applied_to_encoded_values() {
+        let quarter = [srgb_to_linear(0.25); 3];
+        assert_close(light(quarter, 0.0, 0.0, 0.5, 2.0), [srgb_to_linear(0.5); 3]);
+        // black and white are fixed points
+        assert_close(light([0.0, 1.0, 0.0], 0.0, 0.0, 0.5, 0.5), [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn levels_input_keeps_deep_values_and_applies_the_layers_below() {
+        let level = 30000u16; // between two 8-bit steps
+        let source = DynamicImage::ImageRgba16(ImageBuffer::from_pixel(
+            5,
+            5,
+            Rgba([level, level, level, 65535]),
+        ));
+        let encoded = level as f32 / 65535.0;
+        let picked = levels_input(&source, &RenderSettings::default(), (2.5, 2.5));
+        assert!(
+            picked.iter().all(|c| (c - encoded).abs() < 1e-3),
+            "{picked:?}"
+        );
+        assert!((picked[0] * 255.0 - (picked[0] * 255.0).round()).abs() > 0.1);
+
+        let mut settings = RenderSettings::default();
+        settings.adjustments.push(AdjustmentKind::Light {
+            exposure: 1.0,
+            brightness: 0.0,
+            contrast: 0.0,
+            pivot: 0.5,
+            gamma: 1.0,
+        });
+        let brighter = linear_to_srgb(2.0 * srgb_to_linear(encoded));
+        let picked = levels_input(&source, &settings, (2.5, 2.5));
+        assert!(
+            picked.iter().all(|c| (c - brighter).abs() < 1e-3),
+            "{picked:?}"
+        );
+    }
+
+    #[test]
+    fn levels_input_is_read_after_the_filters() {
+        // a white column in black, the pick beside it only sees it once blurred
+        let source = DynamicImage::ImageRgba8(RgbaImage::from_fn(32, 9, |x, _| {
+            let v = if x == 16 { 255 } else { 0 };
+            Rgba([v, v, v, 255])
+        }));
+        let plain = levels_input(&source, &RenderSettings::default(), (20.5, 4.5));
+        assert_eq!(plain, [0.0; 3]);
+        let mut settings = RenderSettings::default();
+        settings.filters.filters.push(Filter::BoxBlur { radius: 4 });
+        let blurred = levels_input(&source, &settings, (20.5, 4.5));
+        let full = render_linear(&source, None, &settings);
+        let expected = (19..=21)
+            .map(|x| linear_to_srgb(full.get_pixel(x, 4).0[0]))
+            .sum::<f32>()
+            / 3.0;
+        assert!(blurred[0] > 0.0);
+        // the render also passes the identity tone table, rounded to half floats
+        assert!(
+            (blurred[0] - expected).abs() < 1e-3,
+            "{blurred:?} != {expected}"
+        );
+    }
+
+    #[test]
+    fn gentle_curves_keep_deep_gradients_smooth() {
+        let source = DynamicImage::ImageRgba16(ImageBuffer::from_fn(4096, 1, |x, _| {
+            let v = (x * 16) as u16;
+            Rgba([v, v, v, u16::MAX])
+        }));
+        let mut settings = RenderSettings::default();
+        settings.curves.master.points = vec![(0.0, 0.0), (0.5, 0.55), (1.0, 1.0)];
+        let rendered = render_image(&source, None, &settings).to_rgba16();
+        let levels: std::collections::HashSet<u16> = rendered.pixels().map(|p| p[0]).collect();
+        assert!(levels.len() > 256, "only {} output levels", levels.len());
+
+        // the table is read within a fraction of an 8-bit step of the curve itself
+        let lut = settings.curves.bake(&settings.levels);
+        for i in 0..=1000 {
+            let x = i as f32 / 1000.0;
+            let exact = settings.curves.master.evaluate(x);
+            let looked_up = curves_lookup(&lut, 0, x);
+            assert!(
+                (looked_up - exact).abs() < 0.25 / 255.0,
+                "{x}: {looked_up} != {exact}"
+            );
+        }
+    }
+
+    #[test]
+    fn unadjusted_render_returns_the_source() {
+        let source = DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, y| {
+            Rgba([
+                (x * 16) as u8,
+                (y * 16) as u8,
+                (x * y) as u8,
+                255 - (x + y) as u8,
+            ])
+        }));
+        assert_eq!(
+            render(&source, None, &RenderSettings::default()),
+            source.to_rgba8()
+        );
+    }
+
+    // saturation used to be scaled by `s + 0.9`, which took 10% off every image at rest
+    #[test]
+    fn neutral_hsv_layer_is_the_identity() {
+        let source = DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, y| {
+            Rgba([(x * 16) as u8, 255 - (y * 16) as u8, (x * y) as u8, 255])
+        }));
+        assert_eq!(
+            render(&source, None, &hsv_settings(0.0, 0.0, 0.0)),
+            source.to_rgba8()
+        );
+    }
+
+    // known outputs, worked out by hand in sRGB values like the HSV panel works
+    #[test]
+    fn hsv_layer_renders_known_pixels() {
+        let source =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([200, 100, 50, 128])));
+        let halved = render(&source, None, &hsv_settings(0.0, 0.0, -0.5));
+        assert_eq!(halved.get_pixel(0, 0).0, [100, 50, 25, 128]);
+        let gray = render(&source, None, &hsv_settings(0.0, -1.0, 0.0));
+        assert_eq!(gray.get_pixel(0, 0).0, [200, 200, 200, 128]);
+        let turned = render(&red_green(), None, &hsv_settings(2.0 * PI / 3.0, 0.0, 0.0));
+        assert_eq!(turned.get_pixel(0, 0).0, [0, 255, 0, 255]);
+        assert_eq!(turned.get_pixel(1, 0).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn quarter_turn_puts_the_left_pixel_on_top() {
+        let mut settings = RenderSettings::default();
+        settings.transform.rotate_clockwise();
+        let turned = render(&red_green(), None, &settings);
+        assert_eq!(turned.dimensions(), (1, 2));
+        assert_eq!(turned.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(turned.get_pixel(0, 1).0, [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn straightening_leaves_transparent_corners() {
+        let source =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(32, 32, Rgba([90, 90, 90, 255])));
+        let settings = RenderSettings {
+            transform: Transform {
+                straighten: 30.0,
+                ..Transform::default()
+            },
+            ..RenderSettings::default()
+        };
+        let straightened = render(&source, None, &settings);
+        assert_eq!(straightened.get_pixel(0, 0).0[3], 0);
+        assert_eq!(straightened.get_pixel(16, 16).0, [90, 90, 90, 255]);
+    }
+
+    #[test]
+    fn edge_detection_keeps_opaque_images_opaque() {
+        // a bright square on black, edges of a zero-sum kernel show around it
+        let source = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |x, y| {
+            let inside = (2..6).contains(&x) && (2..6).contains(&y);
+            if inside {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        }));
+        let mut settings = RenderSettings::default();
+        settings.filters.filters.push(Filter::Custom {
+            size: 3,
+            weights: vec![-1.0, -1.0, -1.0, -1.0, 8.0, -1.0, -1.0, -1.0, -1.0],
+            normalize: false,
+        });
+        let edges = render(&source, None, &settings);
+        assert!(edges.pixels().all(|p| p[3] == 255));
+        assert_eq!(edges.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(edges.get_pixel(2, 2).0, [255, 255, 255, 255]);
+        // flat inside the square, nothing to detect
+        assert_eq!(edges.get_pixel(3, 3).0, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn blurs_do_not_bleed_transparent_colors() {
+        let source = DynamicImage::ImageRgba8(RgbaImage::from_fn(3, 1, |x, _| {
+            if x == 1 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 0])
+            }
+        }));
+        let mut settings = RenderSettings::default();
+        settings.filters.filters.push(Filter::BoxBlur { radius: 1 });
+        let blurred = render(&source, None, &settings);
+        for pixel in blurred.pixels() {
+            assert_eq!(&pixel.0[..3], &[255, 0, 0]);
+        }
+        // a box of three spreads a third of the red pixel over each neighbour
+        assert_eq!(blurred.get_pixel(1, 0).0[3], to_u8(1.0 / 3.0));
+    }
+
+    #[test]
+    fn deep_sources_render_to_16_bits() {
+        let source = DynamicImage::ImageRgba16(ImageBuffer::from_pixel(
+            2,
+            2,
+            Rgba([40000u16, 1000, 65535, 65535]),
+        ));
+        let rendered = render_image(&source, None, &RenderSettings::default());
+        let DynamicImage::ImageRgba16(deep) = rendered else {
+            panic!("expected a 16-bit image");
+        };
+        for (channel, expected) in deep
+            .get_pixel(1, 1)
+            .0
+            .into_iter()
+            .zip([40000u16, 1000, 65535, 65535])
+        {
+            assert!(channel.abs_diff(expected) <= 16, "{channel} != {expected}");
+        }
+    }
+
+    #[test]
+    fn paint_sits_on_top_of_the_adjusted_image() {
+        let paint = RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([0, 0, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        });
+        let painted = render(&red_green(), Some(&paint), &hsv_settings(0.0, -1.0, 0.0));
+        assert_eq!(painted.get_pixel(0, 0).0, [0, 0, 255, 255]);
+        assert_eq!(painted.get_pixel(1, 0).0, [255, 255, 255, 255]);
+    }
+}
@@ -9,7 +9,36 @@ struct VertexOutput {
 }
 
 struct Globals {
-    hsv: vec3<f32>,
+    uv_u: vec4<f32>,    // crop and transform: source u = dot(uv_u.xyz, vec3(canvas uv, 1))
+    uv_v: vec4<f32>,    // source v, the same way
+    premultiply: u32,   // 1 when drawing to a canvas that composites premultiplied colors
+    encode_srgb: u32,   // 1 when the target does not encode sRGB on write
+    checker_size: f32,  // cell size in target pixels of a checkerboard under transparent pixels, 0 for none
+    _pad: u32,
+}
+
+// one adjustment layer, see AdjustmentStack::to_gpu
+struct Layer {
+    kind: u32,
+    opacity: f32,
+    params0: vec4<f32>,
+    params1: vec4<f32>,
+}
+
+struct Adjustments {
+    count: u32,
+    layers: array<Layer, 16>,
+}
+
+// sRGB transfer functions, see color.rs for the CPU versions
+fn srgb_encode(c: vec3<f32>) -> vec3<f32> {
+    let v = max(c, vec3<f32>(0.0));
+    return select(1.055 * pow(v, vec3<f32>(1.0 / 2.4)) - 0.055, v * 12.92, v <= vec3<f32>(0.0031308));
+}
+
+fn srgb_decode(c: vec3<f32>) -> vec3<f32> {
+    let v = max(c, vec3<f32>(0.0));
+    return select(pow((v + 0.055) / 1.055, vec3<f32>(2.4)), v / 12.92, v <= vec3<f32>(0.04045));
 }
 
 fn hsv2rgb(hsv: vec3<f32>) -> vec3<f32> {
@@ -61,6 +90,33 @@ fn hue_shift_rgb(color: vec3<f32>, hue: f32) -> vec3<f32> {
     return color * cosAngle + cross(k, color) * sinAngle + k * dot(k, color) * (1.0 - cosAngle);
 }
 
+fn apply_hsv(color: vec3<f32>, params: vec4<f32>) -> vec3<f32> {
+    let shifted = hue_shift_rgb(color, params.x);
+    var hsv_out = rgb2hsv(shifted);
+    hsv_out.y *= params.y + 1.0;
+    hsv_out.z *= params.z + 1.0;
+    return hsv2rgb(hsv_out);
+}
+
+// params0: exposure (stops), brightness, contrast, pivot; params1.x: gamma
+// exposure scales linear light, the other controls work on perceptual (sRGB encoded) values
+fn apply_light(color: vec3<f32>, params0: vec4<f32>, params1: vec4<f32>) -> vec3<f32> {
+    var c = srgb_encode(color * exp2(params0.x));
+    c = c + vec3<f32>(params0.y);
+    c = (c - vec3<f32>(params0.w)) * (1.0 + params0.z) + vec3<f32>(params0.w);
+    c = pow(max(c, vec3<f32>(0.0)), vec3<f32>(1.0 / max(params1.x, 0.01)));
+    return srgb_decode(c);
+}
+
+// takes and returns linear light
+fn apply_layer(layer: Layer, color: vec3<f32>) -> vec3<f32> {
+    switch layer.kind {
+        case 0u: { return srgb_decode(apply_hsv(srgb_encode(color), layer.params0)); }
+        case 1u: { return apply_light(color, layer.params0, layer.params1); }
+        default: { return color; }
+    }
+}
+
 // Vertex shader
 
 @vertex
@@ -68,7 +124,8 @@ fn vs_main(
     model: VertexInput,
 ) -> VertexOutput {
     var out: VertexOutput;
-    out.tex_coords = model.tex_coords;
+    let canvas = vec3<f32>(model.tex_coords, 1.0);
+    out.tex_coords = vec2<f32>(dot(globals.uv_u.xyz, canvas), dot(globals.uv_v.xyz, canvas));
     out.clip_position = vec4<f32>(model.position, 1.0);
     return out;
 }
@@ -82,15 +139,61 @@ var t_diffuse: texture_2d<f32>;
 var s_diffuse: sampler;
 @group(0) @binding(2)
 var<uniform> globals: Globals;
+@group(0) @binding(3)
+var t_paint: texture_2d<f32>;
+@group(0) @binding(4)
+var<uniform> adjustments: Adjustments;
+@group(0) @binding(5)
+var t_curves: texture_2d<f32>; // 256 x 1 levels + curves lookup table, see CurveSet::bake
+
+// linear interpolation between the two nearest lookup table entries
+fn curves_lookup(x: f32) -> vec4<f32> {
+    let last = f32(textureDimensions(t_curves).x - 1u);
+    let pos = clamp(x, 0.0, 1.0) * last;
+    let i = u32(floor(pos));
+    let j = min(i + 1u, u32(last));
+    return mix(textureLoad(t_curves, vec2<u32>(i, 0u), 0), textureLoad(t_curves, vec2<u32>(j, 0u), 0), fract(pos));
+}
+
+// the table maps sRGB encoded values, like the curve editor and the histogram show them
+fn apply_curves(color: vec3<f32>) -> vec3<f32> {
+    let c = srgb_encode(color);
+    return srgb_decode(vec3<f32>(curves_lookup(c.r).r, curves_lookup(c.g).g, curves_lookup(c.b).b));
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let hue = globals.hsv.x;
-    let tex_color = textureSample(t_diffuse, s_diffuse, in.tex_coords).rgb;
-    let shifted = hue_shift_rgb(tex_color, hue);
-    var hsv_out = rgb2hsv(shifted);
-    hsv_out.y *= globals.hsv.y + 0.9;
-    hsv_out.z *= globals.hsv.z + 1.0;
-    let rgb_out = hsv2rgb(hsv_out);
-    return vec4<f32>(rgb_out, 1.0);
+    // linear light, the source is stored as sRGB; alpha is straight and passes the adjustments untouched
+    let source = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    var rgb_out = source.rgb;
+    for (var i = 0u; i < adjustments.count; i++) {
+        let layer = adjustments.layers[i];
+        rgb_out = mix(rgb_out, apply_layer(layer, rgb_out), layer.opacity);
+    }
+    rgb_out = apply_curves(rgb_out);
+
+    // brush strokes sit on top of the adjusted image
+    let paint = textureSample(t_paint, s_diffuse, in.tex_coords);
+    // a straightened image leaves corners of the canvas uncovered, they stay transparent
+    let inside = all(in.tex_coords >= vec2<f32>(0.0)) && all(in.tex_coords <= vec2<f32>(1.0));
+    var alpha = select(0.0, paint.a + source.a * (1.0 - paint.a), inside);
+    var color = vec3<f32>(0.0);
+    if (alpha > 0.0) {
+        color = (paint.rgb * paint.a + rgb_out * source.a * (1.0 - paint.a)) / alpha;
+    }
+    // a window shared with the UI has no page behind it to show transparency, white and #cccccc cells
+    if (globals.checker_size > 0.0) {
+        let cell = floor(in.clip_position.xy / globals.checker_size);
+        let light = (i32(cell.x + cell.y) & 1) == 0;
+        color = mix(select(vec3<f32>(0.6038), vec3<f32>(1.0), light), color, alpha);
+        alpha = 1.0;
+    }
+    // encoded once on the way out, by the target format or here
+    if (globals.encode_srgb != 0u) {
+        color = srgb_encode(color);
+    }
+    if (globals.premultiply != 0u) {
+        color *= alpha;
+    }
+    return vec4<f32>(color, alpha);
 }
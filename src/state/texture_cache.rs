@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+pub const DEFAULT_TEXTURE_BUDGET_MB: u64 = 512;
+
+pub const MB: u64 = 1024 * 1024;
+
+struct Entry<T> {
+    value: T,
+    bytes: u64,
+    last_used: u64,
+}
+
+// least recently used cache of per-image GPU textures, keyed by image index, that keeps its
+// total size under a budget. Generic over the cached value so the bookkeeping runs without a GPU
+pub struct TextureCache<T> {
+    budget: u64, // bytes
+    used: u64,
+    clock: u64,
+    entries: HashMap<usize, Entry<T>>,
+}
+
+impl<T: Clone> TextureCache<T> {
+    pub fn new(budget: u64) -> TextureCache<T> {
+        TextureCache {
+            budget,
+            used: 0,
+            clock: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.entries.contains_key(&id)
+    }
+
+    // the cached value of `id`, which becomes the most recently used
+    pub fn get(&mut self, id: usize) -> Option<T> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(&id)?;
+        entry.last_used = self.clock;
+        Some(entry.value.clone())
+    }
+
+    // caches `value` of `bytes` for `id` and returns the ids evicted to make room, oldest first.
+    // The new entry itself is kept even when it alone is over budget
+    pub fn insert(&mut self, id: usize, value: T, bytes: u64) -> Vec<usize> {
+        self.remove(id);
+        self.clock += 1;
+        self.entries.insert(
+            id,
+            Entry {
+                value,
+                bytes,
+                last_used: self.clock,
+            },
+        );
+        self.used += bytes;
+        self.evict(Some(id))
+    }
+
+    // drops `id`, for images whose pixels changed
+    pub fn remove(&mut self, id: usize) {
+        if let Some(entry) = self.entries.remove(&id) {
+            self.used -= entry.bytes;
+        }
+    }
+
+    // changes the budget and returns the ids evicted to fit it, oldest first
+    pub fn set_budget(&mut self, budget: u64) -> Vec<usize> {
+        self.budget = budget;
+        let newest = self
+            .entries
+            .iter()
+            .max_by_key(|(_, entry)| entry.last_used)
+            .map(|(&id, _)| id);
+        self.evict(newest)
+    }
+
+    fn evict(&mut self, keep: Option<usize>) -> Vec<usize> {
+        let mut evicted = Vec::new();
+        while self.used > self.budget {
+            let oldest = self
+                .entries
+                .iter()
+                .filter(|(id, _)| Some(**id) != keep)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(&id, _)| id);
+            let Some(id) = oldest else {
+                break;
+            };
+            self.remove(id);
+            evicted.push(id);
+        }
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_the_least_recently_used_first() {
+        let mut cache = TextureCache::new(300);
+        assert!(cache.insert(0, "a", 100).is_empty());
+        assert!(cache.insert(1, "b", 100).is_empty());
+        assert!(cache.insert(2, "c", 100).is_empty());
+        // showing image 0 again makes image 1 the oldest
+        assert_eq!(cache.get(0), Some("a"));
+        assert_eq!(cache.insert(3, "d", 150), vec![1, 2]);
+        assert!(cache.contains(0) && cache.contains(3));
+        assert_eq!(cache.used(), 250);
+    }
+
+    #[test]
+    fn keeps_an_entry_larger_than_the_budget() {
+        let mut cache = TextureCache::new(100);
+        cache.insert(0, "a", 50);
+        assert_eq!(cache.insert(1, "b", 400), vec![0]);
+        assert_eq!(cache.get(1), Some("b"));
+        assert_eq!(cache.used(), 400);
+    }
+
+    #[test]
+    fn replacing_an_entry_does_not_count_it_twice() {
+        let mut cache = TextureCache::new(200);
+        cache.insert(0, "a", 100);
+        assert!(cache.insert(0, "a2", 150).is_empty());
+        assert_eq!(cache.used(), 150);
+        cache.remove(0);
+        assert_eq!(cache.used(), 0);
+        assert_eq!(cache.get(0), None);
+    }
+
+    #[test]
+    fn shrinking_the_budget_keeps_the_newest() {
+        let mut cache = TextureCache::new(1000);
+        cache.insert(0, "a", 100);
+        cache.insert(1, "b", 100);
+        cache.insert(2, "c", 100);
+        cache.get(1);
+        assert_eq!(cache.set_budget(50), vec![0, 2]);
+        assert!(cache.contains(1));
+        assert_eq!(cache.budget(), 50);
+    }
+}
@@ -0,0 +1,284 @@
+// Images past the GPU's texture limit are rendered in tiles: the output is split into a grid and
+// every tile renders from only the part of the source it shows, grown by a margin so neighbourhood
+// filters and bilinear sampling read the same pixels at a tile's edge as they would in one piece.
+use crate::state::customlib::{CropRect, uv_sub_range};
+
+// pixels bilinear sampling and the rounding of a tile's footprint reach past it
+pub const SAMPLING_MARGIN: u32 = 2;
+// keeps the readback of one tile and the textures under it small
+const MAX_TILE: u32 = 2048;
+const MIN_TILE: u32 = 16;
+
+// largest size with the aspect ratio of `dimensions` that fits in `max` on both sides
+pub fn fit_within(dimensions: (u32, u32), max: u32) -> (u32, u32) {
+    let (width, height) = dimensions;
+    if width <= max && height <= max {
+        return dimensions;
+    }
+    let scale = max as f64 / width.max(height) as f64;
+    (
+        ((width as f64 * scale).round() as u32).clamp(1, max),
+        ((height as f64 * scale).round() as u32).clamp(1, max),
+    )
+}
+
+// side of the output tiles, small enough that the source under a tile straightened by up to
+// 45 degrees, plus `margin` on both sides, still fits in a `max_texture` sized texture
+pub fn tile_size(max_texture: u32, margin: u32) -> Result<u32, String> {
+    let usable = max_texture.saturating_sub(2 * margin + 2);
+    let tile = (usable as f64 / std::f64::consts::SQRT_2).floor() as u32;
+    if tile < MIN_TILE {
+        return Err(format!(
+            "the filters reach {} pixels, too far for tiles within the GPU texture limit of {}",
+            margin, max_texture
+        ));
+    }
+    Ok(tile.min(MAX_TILE))
+}
+
+// tiles of at most `tile` pixels covering an `output` sized image, row by row
+pub fn tile_grid(output: (u32, u32), tile: u32) -> Vec<CropRect> {
+    let mut tiles = Vec::new();
+    for y in (0..output.1).step_by(tile as usize) {
+        for x in (0..output.0).step_by(tile as usize) {
+            tiles.push(CropRect {
+                x,
+                y,
+                width: tile.min(output.0 - x),
+                height: tile.min(output.1 - y),
+            });
+        }
+    }
+    tiles
+}
+
+// normalized range of the output `tile` covers, as uv_sub_range takes it
+fn tile_range(output: (u32, u32), tile: CropRect) -> [(f64, f64); 2] {
+    let (width, height) = (output.0 as f64, output.1 as f64);
+    [
+        (tile.x as f64 / width, tile.y as f64 / height),
+        (
+            (tile.x + tile.width) as f64 / width,
+            (tile.y + tile.height) as f64 / height,
+        ),
+    ]
+}
+
+// source pixels `tile` of the output shows through `matrix` (output uv to source uv, see
+// Transform::uv_matrix), grown by `margin` and kept within the source; None when the tile
+// shows none of the source
+pub fn source_region(
+    matrix: [[f32; 4]; 2],
+    output: (u32, u32),
+    tile: CropRect,
+    source: (u32, u32),
+    margin: u32,
+) -> Option<CropRect> {
+    let [from, to] = tile_range(output, tile);
+    let corners = [
+        (from.0, from.1),
+        (to.0, from.1),
+        (from.0, to.1),
+        (to.0, to.1),
+    ]
+    .map(|(u, v)| {
+        let [mu, mv] = matrix.map(|[a, b, c, _]| a as f64 * u + b as f64 * v + c as f64);
+        (mu * source.0 as f64, mv * source.1 as f64)
+    });
+    let min_x = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+    let max_x = corners
+        .iter()
+        .map(|c| c.0)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let min_y = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+    let max_y = corners
+        .iter()
+        .map(|c| c.1)
+        .fold(f64::NEG_INFINITY, f64::max);
+
+    let margin = margin as f64;
+    let x0 = (min_x.floor() - margin).clamp(0.0, source.0 as f64) as u32;
+    let y0 = (min_y.floor() - margin).clamp(0.0, source.1 as f64) as u32;
+    let x1 = (max_x.ceil() + margin).clamp(0.0, source.0 as f64) as u32;
+    let y1 = (max_y.ceil() + margin).clamp(0.0, source.1 as f64) as u32;
+    if x1 <= x0 || y1 <= y0 {
+        return None;
+    }
+    Some(CropRect {
+        x: x0,
+        y: y0,
+        width: x1 - x0,
+        height: y1 - y0,
+    })
+}
+
+// the matrix to render `tile` with when only `region` of the source is uploaded: canvas uv over
+// the tile to uv over the region
+pub fn tile_matrix(
+    matrix: [[f32; 4]; 2],
+    output: (u32, u32),
+    tile: CropRect,
+    source: (u32, u32),
+    region: CropRect,
+) -> [[f32; 4]; 2] {
+    let [u, v] = uv_sub_range(matrix, tile_range(output, tile));
+    // source uv to region uv, separately per axis
+    let rescale = |[a, b, c, d]: [f32; 4], size: u32, offset: u32, extent: u32| {
+        let scale = size as f32 / extent as f32;
+        [
+            a * scale,
+            b * scale,
+            c * scale - offset as f32 / extent as f32,
+            d,
+        ]
+    };
+    [
+        rescale(u, source.0, region.x, region.width),
+        rescale(v, source.1, region.y, region.height),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::transform::Transform;
+
+    fn apply(matrix: [[f32; 4]; 2], p: (f64, f64)) -> (f64, f64) {
+        let [u, v] = matrix.map(|[a, b, c, _]| a as f64 * p.0 + b as f64 * p.1 + c as f64);
+        (u, v)
+    }
+
+    #[test]
+    fn fits_the_longer_side() {
+        assert_eq!(fit_within((12000, 3000), 4000), (4000, 1000));
+        assert_eq!(fit_within((300, 200), 4000), (300, 200));
+    }
+
+    #[test]
+    fn grid_covers_the_output_once() {
+        let tiles = tile_grid((100, 50), 40);
+        assert_eq!(tiles.len(), 6);
+        let area: u32 = tiles.iter().map(|t| t.width * t.height).sum();
+        assert_eq!(area, 100 * 50);
+        assert_eq!(
+            tiles[2],
+            CropRect {
+                x: 80,
+                y: 0,
+                width: 20,
+                height: 40
+            }
+        );
+        assert_eq!(
+            tiles[5],
+            CropRect {
+                x: 80,
+                y: 40,
+                width: 20,
+                height: 10
+            }
+        );
+    }
+
+    #[test]
+    fn straightened_tiles_fit_the_texture_limit() {
+        let max = 512;
+        let margin = 10;
+        let tile = tile_size(max, margin).unwrap();
+        let source = (4000, 3000);
+        let transform = Transform {
+            straighten: 45.0,
+            ..Transform::default()
+        };
+        let matrix = transform.uv_matrix(None, source);
+        for rect in tile_grid(source, tile) {
+            if let Some(region) = source_region(matrix, source, rect, source, margin) {
+                assert!(region.width <= max && region.height <= max, "{region:?}");
+            }
+        }
+        assert!(tile_size(64, 40).is_err());
+    }
+
+    #[test]
+    fn unrotated_region_is_the_tile_plus_margin() {
+        let source = (200, 100);
+        let matrix = Transform::default().uv_matrix(None, source);
+        let tile = CropRect {
+            x: 50,
+            y: 40,
+            width: 50,
+            height: 30,
+        };
+        let region = source_region(matrix, source, tile, source, 4).unwrap();
+        assert_eq!(
+            region,
+            CropRect {
+                x: 46,
+                y: 36,
+                width: 58,
+                height: 38
+            }
+        );
+        // clamped at the source's edges
+        let corner = CropRect {
+            x: 0,
+            y: 0,
+            width: 10,
+            height: 10,
+        };
+        let region = source_region(matrix, source, corner, source, 4).unwrap();
+        assert_eq!(
+            region,
+            CropRect {
+                x: 0,
+                y: 0,
+                width: 14,
+                height: 14
+            }
+        );
+    }
+
+    #[test]
+    fn tile_matrix_lands_on_the_same_source_pixels() {
+        let source = (300, 200);
+        let transform = Transform {
+            quarter_turns: 1,
+            straighten: 10.0,
+            ..Transform::default()
+        };
+        let crop = Some(CropRect {
+            x: 20,
+            y: 30,
+            width: 150,
+            height: 240,
+        });
+        let matrix = transform.uv_matrix(crop, source);
+        let output = (150, 240);
+        let tile = CropRect {
+            x: 64,
+            y: 128,
+            width: 64,
+            height: 64,
+        };
+        let region = source_region(matrix, output, tile, source, 3).unwrap();
+        let local = tile_matrix(matrix, output, tile, source, region);
+        for p in [(0.0, 0.0), (1.0, 0.0), (0.25, 0.75), (1.0, 1.0)] {
+            let whole = apply(
+                matrix,
+                (
+                    (tile.x as f64 + p.0 * tile.width as f64) / output.0 as f64,
+                    (tile.y as f64 + p.1 * tile.height as f64) / output.1 as f64,
+                ),
+            );
+            let part = apply(local, p);
+            let whole_px = (whole.0 * source.0 as f64, whole.1 * source.1 as f64);
+            let part_px = (
+                region.x as f64 + part.0 * region.width as f64,
+                region.y as f64 + part.1 * region.height as f64,
+            );
+            assert!((whole_px.0 - part_px.0).abs() < 1e-2 && (whole_px.1 - part_px.1).abs() < 1e-2);
+            // well inside the region, where the margin keeps it
+            assert!(part.0 > 0.0 && part.0 < 1.0 && part.1 > 0.0 && part.1 < 1.0);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::state::customlib::CropRect;
+
+// widest straighten angle offered, in degrees either way
+pub const MAX_STRAIGHTEN: f32 = 45.0;
+
+// orientation of one image: flips of the source, then quarter turns, then a free rotation
+// within the turned frame. Crops are taken in that frame.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Transform {
+    pub quarter_turns: u8, // clockwise, 0 to 3
+    pub flip_x: bool,      // mirrors the source left to right
+    pub flip_y: bool,      // mirrors the source top to bottom
+    pub straighten: f32,   // degrees, clockwise
+    pub auto_crop: bool,   // zooms in until the straightened image covers the whole frame
+}
+
+impl Transform {
+    pub fn rotate_clockwise(&mut self) {
+        self.quarter_turns = (self.quarter_turns + 1) % 4;
+    }
+
+    pub fn rotate_counter_clockwise(&mut self) {
+        self.quarter_turns = (self.quarter_turns + 3) % 4;
+    }
+
+    // mirrors what is on screen left to right
+    pub fn flip_horizontal(&mut self) {
+        if self.quarter_turns.is_multiple_of(2) {
+            self.flip_x = !self.flip_x;
+        } else {
+            self.flip_y = !self.flip_y;
+        }
+        self.straighten = -self.straighten;
+    }
+
+    // mirrors what is on screen top to bottom
+    pub fn flip_vertical(&mut self) {
+        if self.quarter_turns.is_multiple_of(2) {
+            self.flip_y = !self.flip_y;
+        } else {
+            self.flip_x = !self.flip_x;
+        }
+        self.straighten = -self.straighten;
+    }
+
+    // size of the frame the image is shown and cropped in
+    pub fn oriented_dimensions(&self, source: (u32, u32)) -> (u32, u32) {
+        if self.quarter_turns.is_multiple_of(2) {
+            source
+        } else {
+            (source.1, source.0)
+        }
+    }
+
+    // normalized source coordinates to normalized frame coordinates, ignoring the straighten angle
+    pub fn orient(&self, (mut x, mut y): (f64, f64)) -> (f64, f64) {
+        if self.flip_x {
+            x = 1.0 - x;
+        }
+        if self.flip_y {
+            y = 1.0 - y;
+        }
+        for _ in 0..self.quarter_turns % 4 {
+            (x, y) = (1.0 - y, x);
+        }
+        (x, y)
+    }
+
+    // inverse of `orient`
+    pub fn unorient(&self, (mut x, mut y): (f64, f64)) -> (f64, f64) {
+        for _ in 0..self.quarter_turns % 4 {
+            (x, y) = (y, 1.0 - x);
+        }
+        if self.flip_x {
+            x = 1.0 - x;
+        }
+        if self.flip_y {
+            y = 1.0 - y;
+        }
+        (x, y)
+    }
+
+    // zoom that keeps the corners of the rotated image outside a `frame` sized view
+    pub fn auto_crop_scale(&self, frame: (u32, u32)) -> f64 {
+        if !self.auto_crop {
+            return 1.0;
+        }
+        let (width, height) = (frame.0 as f64, frame.1 as f64);
+        let (sin, cos) = (self.straighten as f64).to_radians().sin_cos();
+        let (sin, cos) = (sin.abs(), cos.abs());
+        ((width * cos + height * sin) / width).max((width * sin + height * cos) / height)
+    }
+
+    // normalized source coordinates shown at normalized frame position `p`
+    pub fn source_point(&self, p: (f64, f64), source: (u32, u32)) -> (f64, f64) {
+        let (width, height) = self.oriented_dimensions(source);
+        let (width, height) = (width as f64, height as f64);
+        let scale = self.auto_crop_scale(self.oriented_dimensions(source));
+        let (sin, cos) = (self.straighten as f64).to_radians().sin_cos();
+        // around the center in pixels, so the rotation keeps its angles on non-square images
+        let (px, py) = ((p.0 - 0.5) * width, (p.1 - 0.5) * height);
+        let (qx, qy) = ((px * cos + py * sin) / scale, (py * cos - px * sin) / scale);
+        self.unorient((qx / width + 0.5, qy / height + 0.5))
+    }
+
+    // normalized source coordinates at normalized position `p` of the output, which shows `crop`
+    // of the frame, or all of it
+    pub fn canvas_to_uv(&self, crop: Option<CropRect>, p: (f64, f64), source: (u32, u32)) -> (f64, f64) {
+        let (width, height) = self.oriented_dimensions(source);
+        let rect = crop.unwrap_or(CropRect::full((width, height)));
+        let frame = (
+            (rect.x as f64 + p.0 * rect.width as f64) / width as f64,
+            (rect.y as f64 + p.1 * rect.height as f64) / height as f64,
+        );
+        self.source_point(frame, source)
+    }
+
+    // crop and transform as one affine map from output to source uv, rows of (x, y, 1) weights;
+    // being affine it is exact when interpolated between the vertices
+    pub fn uv_matrix(&self, crop: Option<CropRect>, source: (u32, u32)) -> [[f32; 4]; 2] {
+        let origin = self.canvas_to_uv(crop, (0.0, 0.0), source);
+        let x = self.canvas_to_uv(crop, (1.0, 0.0), source);
+        let y = self.canvas_to_uv(crop, (0.0, 1.0), source);
+        [
+            [(x.0 - origin.0) as f32, (y.0 - origin.0) as f32, origin.0 as f32, 0.0],
+            [(x.1 - origin.1) as f32, (y.1 - origin.1) as f32, origin.1 as f32, 0.0],
+        ]
+    }
+
+    // where `crop`, taken in the frame of `self`, lies in the frame of `other`
+    pub fn remap_crop(&self, crop: CropRect, other: &Transform, source: (u32, u32)) -> CropRect {
+        let (width, height) = self.oriented_dimensions(source);
+        let (new_width, new_height) = other.oriented_dimensions(source);
+        let corners = [
+            (crop.x as f64 / width as f64, crop.y as f64 / height as f64),
+            (
+                (crop.x + crop.width) as f64 / width as f64,
+                (crop.y + crop.height) as f64 / height as f64,
+            ),
+        ]
+        .map(|corner| other.orient(self.unorient(corner)));
+        // quarter turns and flips keep the rectangle axis aligned, only its corners swap
+        let (x0, x1) = (corners[0].0.min(corners[1].0), corners[0].0.max(corners[1].0));
+        let (y0, y1) = (corners[0].1.min(corners[1].1), corners[0].1.max(corners[1].1));
+        CropRect {
+            x: (x0 * new_width as f64).round() as u32,
+            y: (y0 * new_height as f64).round() as u32,
+            width: ((x1 - x0) * new_width as f64).round() as u32,
+            height: ((y1 - y0) * new_height as f64).round() as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: (u32, u32) = (400, 300);
+
+    fn assert_close(a: (f64, f64), b: (f64, f64)) {
+        assert!((a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn quarter_turns_and_flips_undo_themselves() {
+        let mut transform = Transform {
+            straighten: 3.0,
+            ..Transform::default()
+        };
+        for _ in 0..4 {
+            transform.rotate_clockwise();
+        }
+        // a vertical flip seen after a quarter turn is the horizontal flip from before it
+        transform.flip_horizontal();
+        transform.rotate_clockwise();
+        transform.flip_vertical();
+        transform.rotate_counter_clockwise();
+        assert_eq!(
+            transform,
+            Transform {
+                straighten: 3.0,
+                ..Transform::default()
+            }
+        );
+    }
+
+    #[test]
+    fn clockwise_turn_moves_the_left_edge_to_the_top() {
+        let mut transform = Transform::default();
+        transform.rotate_clockwise();
+        assert_eq!(transform.oriented_dimensions(SOURCE), (300, 400));
+        // the top-left of the frame shows the bottom-left of the source
+        assert_close(transform.source_point((0.0, 0.0), SOURCE), (0.0, 1.0));
+        assert_close(transform.source_point((1.0, 0.0), SOURCE), (0.0, 0.0));
+        for p in [(0.2, 0.7), (0.9, 0.1)] {
+            assert_close(transform.unorient(transform.orient(p)), p);
+        }
+    }
+
+    #[test]
+    fn auto_crop_keeps_the_frame_inside_the_image() {
+        let transform = Transform {
+            straighten: 12.5,
+            auto_crop: true,
+            ..Transform::default()
+        };
+        for corner in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+            let (u, v) = transform.source_point(corner, SOURCE);
+            assert!((-1e-9..=1.0 + 1e-9).contains(&u) && (-1e-9..=1.0 + 1e-9).contains(&v), "{u} {v}");
+        }
+    }
+
+    #[test]
+    fn crops_follow_a_turn() {
+        let before = Transform::default();
+        let mut after = before;
+        after.rotate_clockwise();
+        let crop = CropRect {
+            x: 10,
+            y: 20,
+            width: 100,
+            height: 50,
+        };
+        let turned = before.remap_crop(crop, &after, SOURCE);
+        assert_eq!(
+            turned,
+            CropRect {
+                x: 230,
+                y: 10,
+                width: 50,
+                height: 100
+            }
+        );
+        assert_eq!(after.remap_crop(turned, &before, SOURCE), crop);
+    }
+}
@@ -1,2 +1,3 @@
 pub mod utils;
 pub mod renderer;
+pub mod platform;
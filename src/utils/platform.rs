@@ -0,0 +1,432 @@
+// What the editor needs from its host, for the browser and for the native desktop window.
+// The browser build talks to the DOM through web_sys; the desktop build runs the same components
+// in a webview it can only reach through `document::eval`, so everything here is either
+// fire-and-forget or delivered through signals and callbacks.
+use dioxus::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Viewport {
+    pub width: f64, // CSS pixels
+    pub height: f64,
+    pub pixel_ratio: f64, // physical pixels per CSS pixel
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn log(message: impl AsRef<str>) {
+    web_sys::console::log_1(&message.as_ref().into());
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn log(message: impl AsRef<str>) {
+    println!("{}", message.as_ref());
+}
+
+// size of the window, kept up to date as it is resized
+pub fn use_viewport() -> Signal<Viewport> {
+    imp::use_viewport()
+}
+
+// calls `on_move` with the client position of the pointer anywhere in the window and `on_up`
+// when a button is released, so drags keep working outside the element they started on
+pub fn use_window_pointer(on_move: impl FnMut(f64, f64) + 'static, on_up: impl FnMut() + 'static) {
+    imp::use_window_pointer(on_move, on_up)
+}
+
+// calls `on_key(key, shift)` for Ctrl / Cmd + one of `keys` (lower case), instead of the
+// browser's own handling of that shortcut
+pub fn use_shortcut_keys(keys: &'static [&'static str], on_key: impl FnMut(&str, bool) + 'static) {
+    imp::use_shortcut_keys(keys, on_key)
+}
+
+// hands `bytes` to the user as `filename`: a download in the browser, a save dialog on the desktop
+pub async fn save_file(bytes: Vec<u8>, filename: String) -> Result<(), String> {
+    imp::save_file(bytes, filename).await
+}
+
+// where a batch export writes its files, chosen once for the whole batch
+pub struct BatchTarget(imp::BatchTarget);
+
+// asks once where `count` files go: a folder on the desktop, a confirmation of that many
+// downloads in the browser
+pub async fn choose_batch_target(count: usize) -> Result<BatchTarget, String> {
+    imp::choose_batch_target(count).await.map(BatchTarget)
+}
+
+impl BatchTarget {
+    pub async fn save(&self, bytes: Vec<u8>, filename: String) -> Result<(), String> {
+        imp::save_to_target(&self.0, bytes, filename).await
+    }
+}
+
+// a small piece of text kept between sessions: local storage in the browser, a file in the
+// user's config directory on the desktop
+pub fn load_setting(key: &str) -> Option<String> {
+    imp::load_setting(key)
+}
+
+pub fn store_setting(key: &str, value: &str) -> Result<(), String> {
+    imp::store_setting(key, value)
+}
+
+#[cfg(target_arch = "wasm32")]
+mod imp {
+    use super::Viewport;
+    use dioxus::prelude::*;
+    use std::rc::Rc;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen::prelude::Closure;
+    use web_sys::js_sys;
+    use web_sys::{
+        Blob, Event, HtmlAnchorElement, KeyboardEvent, MouseEvent, Storage, Url, window,
+    };
+
+    fn current_viewport() -> Viewport {
+        let window = window().expect("No global window found.");
+        Viewport {
+            width: window.inner_width().unwrap().as_f64().unwrap(),
+            height: window.inner_height().unwrap().as_f64().unwrap(),
+            pixel_ratio: window.device_pixel_ratio(),
+        }
+    }
+
+    // adds `listeners` to the window for the lifetime of the calling component
+    fn use_window_listeners(
+        listeners: impl FnOnce() -> Vec<(&'static str, Closure<dyn FnMut(Event)>)> + 'static,
+    ) {
+        use_hook_with_cleanup(
+            move || {
+                let listeners = Rc::new(listeners());
+                let window = window().expect("No global window found.");
+                for (event, closure) in listeners.iter() {
+                    window
+                        .add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())
+                        .unwrap();
+                }
+                listeners
+            },
+            move |listeners| {
+                if let Some(window) = window() {
+                    for (event, closure) in listeners.iter() {
+                        window
+                            .remove_event_listener_with_callback(
+                                event,
+                                closure.as_ref().unchecked_ref(),
+                            )
+                            .unwrap();
+                    }
+                }
+            },
+        );
+    }
+
+    pub fn use_viewport() -> Signal<Viewport> {
+        let mut viewport = use_signal(current_viewport);
+        use_window_listeners(move || {
+            let resize = Closure::wrap(
+                Box::new(move |_: Event| viewport.set(current_viewport())) as Box<dyn FnMut(_)>
+            );
+            vec![("resize", resize)]
+        });
+        viewport
+    }
+
+    pub fn use_window_pointer(
+        mut on_move: impl FnMut(f64, f64) + 'static,
+        mut on_up: impl FnMut() + 'static,
+    ) {
+        use_window_listeners(move || {
+            let mouse_move = Closure::wrap(Box::new(move |event: Event| {
+                if let Some(event) = event.dyn_ref::<MouseEvent>() {
+                    on_move(event.client_x() as f64, event.client_y() as f64);
+                }
+            }) as Box<dyn FnMut(_)>);
+            let mouse_up = Closure::wrap(Box::new(move |_: Event| on_up()) as Box<dyn FnMut(_)>);
+            vec![("mousemove", mouse_move), ("mouseup", mouse_up)]
+        });
+    }
+
+    pub fn use_shortcut_keys(
+        keys: &'static [&'static str],
+        mut on_key: impl FnMut(&str, bool) + 'static,
+    ) {
+        use_window_listeners(move || {
+            let key_down = Closure::wrap(Box::new(move |event: Event| {
+                let Some(event) = event.dyn_ref::<KeyboardEvent>() else {
+                    return;
+                };
+                let key = event.key().to_lowercase();
+                if (event.ctrl_key() || event.meta_key()) && keys.contains(&key.as_str()) {
+                    event.prevent_default();
+                    on_key(&key, event.shift_key());
+                }
+            }) as Box<dyn FnMut(_)>);
+            vec![("keydown", key_down)]
+        });
+    }
+
+    // a temporary link with the download attribute, clicked once
+    pub async fn save_file(bytes: Vec<u8>, filename: String) -> Result<(), String> {
+        let array = js_sys::Uint8Array::from(bytes.as_slice());
+        let blob_parts = js_sys::Array::new();
+        blob_parts.push(&array);
+        let blob =
+            Blob::new_with_u8_array_sequence(&blob_parts).map_err(|err| format!("{:?}", err))?;
+        let url = Url::create_object_url_with_blob(&blob).map_err(|err| format!("{:?}", err))?;
+        let document = window().unwrap().document().unwrap();
+        let a = document
+            .create_element("a")
+            .unwrap()
+            .dyn_into::<HtmlAnchorElement>()
+            .unwrap();
+        a.set_href(&url);
+        a.set_download(&filename);
+        document.body().unwrap().append_child(&a).unwrap();
+        a.click();
+        document.body().unwrap().remove_child(&a).unwrap();
+        Url::revoke_object_url(&url).unwrap();
+        Ok(())
+    }
+
+    pub struct BatchTarget;
+
+    pub async fn choose_batch_target(count: usize) -> Result<BatchTarget, String> {
+        let window = window().ok_or_else(|| String::from("no window"))?;
+        let confirmed = window
+            .confirm_with_message(&format!("Download {count} images?"))
+            .map_err(|err| format!("{:?}", err))?;
+        if confirmed {
+            Ok(BatchTarget)
+        } else {
+            Err(String::from("the batch was cancelled"))
+        }
+    }
+
+    // every file is its own download, the browser puts them in its download folder
+    pub async fn save_to_target(
+        _target: &BatchTarget,
+        bytes: Vec<u8>,
+        filename: String,
+    ) -> Result<(), String> {
+        save_file(bytes, filename).await
+    }
+
+    fn local_storage() -> Option<Storage> {
+        window()?.local_storage().ok().flatten()
+    }
+
+    pub fn load_setting(key: &str) -> Option<String> {
+        local_storage()?.get_item(key).ok().flatten()
+    }
+
+    pub fn store_setting(key: &str, value: &str) -> Result<(), String> {
+        local_storage()
+            .ok_or_else(|| String::from("local storage is not available"))?
+            .set_item(key, value)
+            .map_err(|err| format!("{:?}", err))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod imp {
+    use super::Viewport;
+    use dioxus::prelude::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT_LISTENER: AtomicUsize = AtomicUsize::new(0);
+
+    // runs `setup` in the webview for the lifetime of the calling component. The script gets
+    // `emit(...numbers)` to send events back and registers its listeners with `on(target, event, fn)`
+    // so they can be removed again
+    fn use_js_listener(setup: String, mut handler: impl FnMut(Vec<f64>) + 'static) {
+        use_hook_with_cleanup(
+            move || {
+                let id = NEXT_LISTENER.fetch_add(1, Ordering::Relaxed);
+                let script = format!(
+                    r#"
+                    const removers = [];
+                    const on = (target, event, listener) => {{
+                        target.addEventListener(event, listener);
+                        removers.push(() => target.removeEventListener(event, listener));
+                    }};
+                    const emit = (...values) => dioxus.send(values);
+                    window.__editorListeners = window.__editorListeners || {{}};
+                    window.__editorListeners[{id}] = () => removers.forEach((remove) => remove());
+                    {setup}
+                    await new Promise(() => {{}});
+                    "#
+                );
+                let mut eval = document::eval(&script);
+                spawn(async move {
+                    while let Ok(values) = eval.recv::<Vec<f64>>().await {
+                        handler(values);
+                    }
+                });
+                id
+            },
+            |id| {
+                document::eval(&format!(
+                    "window.__editorListeners?.[{id}]?.(); delete window.__editorListeners?.[{id}];"
+                ));
+            },
+        );
+    }
+
+    pub fn use_viewport() -> Signal<Viewport> {
+        let mut viewport = use_signal(|| Viewport {
+            width: 0.0,
+            height: 0.0,
+            pixel_ratio: 1.0,
+        });
+        use_js_listener(
+            String::from(
+                r#"
+                const report = () => emit(window.innerWidth, window.innerHeight, window.devicePixelRatio);
+                on(window, "resize", report);
+                report();
+                "#,
+            ),
+            move |values| {
+                if let [width, height, pixel_ratio] = values[..] {
+                    viewport.set(Viewport {
+                        width,
+                        height,
+                        pixel_ratio,
+                    });
+                }
+            },
+        );
+        viewport
+    }
+
+    pub fn use_window_pointer(
+        mut on_move: impl FnMut(f64, f64) + 'static,
+        mut on_up: impl FnMut() + 'static,
+    ) {
+        use_js_listener(
+            String::from(
+                r#"
+                on(window, "mousemove", (event) => emit(event.clientX, event.clientY));
+                on(window, "mouseup", () => emit());
+                "#,
+            ),
+            move |values| match values[..] {
+                [x, y] => on_move(x, y),
+                _ => on_up(),
+            },
+        );
+    }
+
+    pub fn use_shortcut_keys(
+        keys: &'static [&'static str],
+        mut on_key: impl FnMut(&str, bool) + 'static,
+    ) {
+        let key_list = keys
+            .iter()
+            .map(|key| format!("{:?}", key))
+            .collect::<Vec<_>>()
+            .join(", ");
+        use_js_listener(
+            format!(
+                r#"
+                const keys = [{key_list}];
+                on(window, "keydown", (event) => {{
+                    const index = keys.indexOf(event.key.toLowerCase());
+                    if ((event.ctrlKey || event.metaKey) && index >= 0) {{
+                        event.preventDefault();
+                        emit(index, event.shiftKey ? 1 : 0);
+                    }}
+                }});
+                "#
+            ),
+            move |values| {
+                if let [index, shift] = values[..]
+                    && let Some(key) = keys.get(index as usize)
+                {
+                    on_key(key, shift != 0.0);
+                }
+            },
+        );
+    }
+
+    #[cfg(feature = "desktop")]
+    pub async fn save_file(bytes: Vec<u8>, filename: String) -> Result<(), String> {
+        let Some(handle) = rfd::AsyncFileDialog::new()
+            .set_file_name(&filename)
+            .save_file()
+            .await
+        else {
+            return Err(String::from("saving was cancelled"));
+        };
+        handle.write(&bytes).await.map_err(|err| err.to_string())
+    }
+
+    #[cfg(not(feature = "desktop"))]
+    pub async fn save_file(bytes: Vec<u8>, filename: String) -> Result<(), String> {
+        let _ = bytes;
+        Err(format!(
+            "no way to save {} without the desktop build",
+            filename
+        ))
+    }
+
+    pub struct BatchTarget {
+        folder: PathBuf,
+    }
+
+    #[cfg(feature = "desktop")]
+    pub async fn choose_batch_target(count: usize) -> Result<BatchTarget, String> {
+        let Some(handle) = rfd::AsyncFileDialog::new()
+            .set_title(format!("Save {count} images to"))
+            .pick_folder()
+            .await
+        else {
+            return Err(String::from("the batch was cancelled"));
+        };
+        Ok(BatchTarget {
+            folder: handle.path().to_path_buf(),
+        })
+    }
+
+    #[cfg(not(feature = "desktop"))]
+    pub async fn choose_batch_target(count: usize) -> Result<BatchTarget, String> {
+        Err(format!(
+            "no way to save {} images without the desktop build",
+            count
+        ))
+    }
+
+    pub async fn save_to_target(
+        target: &BatchTarget,
+        bytes: Vec<u8>,
+        filename: String,
+    ) -> Result<(), String> {
+        std::fs::write(target.folder.join(filename), bytes).map_err(|err| err.to_string())
+    }
+
+    // $XDG_CONFIG_HOME or ~/.config, %APPDATA% on Windows
+    fn setting_path(key: &str) -> Option<PathBuf> {
+        let env = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+        let config = env("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env("APPDATA").map(PathBuf::from))
+            .or_else(|| env("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(
+            config
+                .join(env!("CARGO_PKG_NAME"))
+                .join(format!("{key}.txt")),
+        )
+    }
+
+    pub fn load_setting(key: &str) -> Option<String> {
+        std::fs::read_to_string(setting_path(key)?).ok()
+    }
+
+    pub fn store_setting(key: &str, value: &str) -> Result<(), String> {
+        let path = setting_path(key).ok_or_else(|| String::from("no config directory"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        std::fs::write(path, value).map_err(|err| err.to_string())
+    }
+}
@@ -1,10 +1,22 @@
 use crate::state::customlib::*;
 use image::DynamicImage;
 
-pub async fn start_wgpu(initial_image: &DynamicImage) -> State {
+pub async fn start_wgpu(initial_image: &DynamicImage) -> Result<State, String> {
     #[cfg(target_arch = "wasm32")]
     {
         let renderer = State::new(initial_image).await;
         return renderer;
     }
+    #[cfg(all(not(target_arch = "wasm32"), feature = "desktop"))]
+    {
+        let desktop = dioxus::desktop::window();
+        let size = desktop.window.inner_size();
+        return State::new(desktop.window.clone(), (size.width, size.height), initial_image).await;
+    }
+    // the renderer draws to a browser canvas or the desktop window, elsewhere the CPU renders instead
+    #[cfg(not(any(target_arch = "wasm32", feature = "desktop")))]
+    {
+        let _ = initial_image;
+        Err(String::from("No canvas or window to render to"))
+    }
 }
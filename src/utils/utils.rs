@@ -1,10 +1,18 @@
 use crate::dioxus_elements::geometry::WheelDelta;
+use crate::state::customlib::CropRect;
+use crate::state::export::ExportFormat;
+use crate::state::metadata::ImageMetadata;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as base64_engine;
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use std::io::Cursor;
 use image::ImageEncoder; // Import the trait to bring encode into scope
 use image::codecs::png::PngEncoder;
 use image::{ImageBuffer, Rgba};
 use wasm_bindgen::prelude::*;
 use web_sys::js_sys;
-use web_sys::{Blob, HtmlAnchorElement, HtmlElement, Url, window};
+use web_sys::{Blob, HtmlElement, Url, window};
+use crate::utils::platform::save_file;
 
 pub fn clamp_translate_value(
     tx: f64,
@@ -60,39 +68,148 @@ pub fn save_png(buffer: Vec<u8>, width: u32, height: u32, filename: String) {
     Url::revoke_object_url(&url).unwrap();
 }
 
-pub fn save_file_via_dialog(buffer: Vec<u8>, width: u32, height: u32, filename: String) {
-    let img_buf =
-        ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, buffer).expect("Invalid buffer size");
+// encodes the image and hands it to the platform to save, see platform::save_file
+pub async fn save_file_via_dialog(
+    image: &DynamicImage,
+    filename: String,
+    format: &ExportFormat,
+    metadata: &ImageMetadata,
+) -> Result<(), String> {
+    let encoded = format.encode(image, metadata)?;
+    save_file(encoded, format.with_extension(&filename)).await
+}
 
-    let mut png_data = Vec::new();
-    PngEncoder::new(&mut png_data)
-        .write_image(
-            &img_buf,
-            img_buf.width(),
-            img_buf.height(),
-            image::ColorType::Rgba8.into(),
-        )
-        .expect("Failed to encode PNG");
+// small jpeg preview used by the gallery
+pub fn make_thumbnail_base64(img: &DynamicImage) -> String {
+    let max_width = 480;
+    let resized = img.resize(max_width, u32::MAX, image::imageops::FilterType::Triangle);
+    let dynamic_rgb = DynamicImage::ImageRgb8(resized.to_rgb8());
+    let mut cursor = Cursor::new(Vec::new());
+    if let Err(err) = dynamic_rgb.write_to(&mut cursor, image::ImageFormat::Jpeg) {
+        println!("Error during formatting: {err:?}");
+    }
+    format!("data:image/jpeg;base64,{}", base64_engine.encode(cursor.into_inner()))
+}
 
-    let array = js_sys::Uint8Array::from(png_data.as_slice());
-    let blob_parts = js_sys::Array::new();
-    blob_parts.push(&array);
-    let blob = Blob::new_with_u8_array_sequence(&blob_parts).unwrap();
-    let url = Url::create_object_url_with_blob(&blob).unwrap();
-    let document = window().unwrap().document().unwrap();
-    let a = document
-        .create_element("a")
-        .unwrap()
-        .dyn_into::<HtmlAnchorElement>()
-        .unwrap();
-    a.set_href(&url);
-    a.set_download(&*filename);
-    document.body().unwrap().append_child(&a).unwrap();
-    a.click();
-    document.body().unwrap().remove_child(&a).unwrap();
-    Url::revoke_object_url(&url).unwrap();
+// lossless data url of a rendered image, for showing it without a canvas
+pub fn make_png_base64(img: &RgbaImage) -> String {
+    let mut cursor = Cursor::new(Vec::new());
+    if let Err(err) = img.write_to(&mut cursor, image::ImageFormat::Png) {
+        println!("Error during formatting: {err:?}");
+    }
+    format!("data:image/png;base64,{}", base64_engine.encode(cursor.into_inner()))
 }
 
 pub fn align_to_256(x: u32) -> u32 {
     ((x + 255) / 256) * 256
 }
+
+// builds a crop rectangle spanning from a fixed anchor corner to the pointer,
+// keeping the aspect ratio (width / height) if one is given
+pub fn crop_from_corners(
+    anchor: (f64, f64),
+    pointer: (f64, f64),
+    ratio: Option<f64>,
+    bounds: (u32, u32),
+) -> CropRect {
+    let px = pointer.0.clamp(0.0, bounds.0 as f64);
+    let py = pointer.1.clamp(0.0, bounds.1 as f64);
+    let mut w = (px - anchor.0).abs().max(1.0);
+    let mut h = (py - anchor.1).abs().max(1.0);
+
+    if let Some(r) = ratio {
+        if w / h > r {
+            w = h * r;
+        } else {
+            h = w / r;
+        }
+    }
+
+    let x = if px < anchor.0 { anchor.0 - w } else { anchor.0 };
+    let y = if py < anchor.1 { anchor.1 - h } else { anchor.1 };
+
+    clamp_crop(
+        CropRect {
+            x: x.round().max(0.0) as u32,
+            y: y.round().max(0.0) as u32,
+            width: w.round() as u32,
+            height: h.round() as u32,
+        },
+        bounds,
+    )
+}
+
+// shrinks the rectangle to the largest centered one with the given aspect ratio
+pub fn fit_crop_to_aspect(rect: CropRect, ratio: Option<f64>, bounds: (u32, u32)) -> CropRect {
+    let Some(r) = ratio else {
+        return clamp_crop(rect, bounds);
+    };
+    let (mut w, mut h) = (rect.width as f64, rect.height as f64);
+    if w / h > r {
+        w = h * r;
+    } else {
+        h = w / r;
+    }
+    let x = rect.x as f64 + (rect.width as f64 - w) / 2.0;
+    let y = rect.y as f64 + (rect.height as f64 - h) / 2.0;
+
+    clamp_crop(
+        CropRect {
+            x: x.round() as u32,
+            y: y.round() as u32,
+            width: w.round() as u32,
+            height: h.round() as u32,
+        },
+        bounds,
+    )
+}
+
+pub fn clamp_crop(rect: CropRect, bounds: (u32, u32)) -> CropRect {
+    let width = rect.width.clamp(1, bounds.0.max(1));
+    let height = rect.height.clamp(1, bounds.1.max(1));
+    CropRect {
+        x: rect.x.min(bounds.0.saturating_sub(width)),
+        y: rect.y.min(bounds.1.saturating_sub(height)),
+        width,
+        height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corners_can_be_dragged_either_way() {
+        let bounds = (400, 300);
+        let down_right = crop_from_corners((100.0, 50.0), (200.0, 150.0), None, bounds);
+        let up_left = crop_from_corners((200.0, 150.0), (100.0, 50.0), None, bounds);
+        assert_eq!(down_right, CropRect { x: 100, y: 50, width: 100, height: 100 });
+        assert_eq!(up_left, down_right);
+        // a locked ratio shrinks the longer side and keeps the anchor corner
+        let wide = crop_from_corners((200.0, 150.0), (0.0, 100.0), Some(2.0), bounds);
+        assert_eq!(wide, CropRect { x: 100, y: 100, width: 100, height: 50 });
+    }
+
+    #[test]
+    fn aspect_fitting_stays_inside_the_image() {
+        let bounds = (400, 300);
+        // full frame to 16:9, centered vertically
+        let fitted = fit_crop_to_aspect(CropRect::full(bounds), Some(16.0 / 9.0), bounds);
+        assert_eq!(fitted, CropRect { x: 0, y: 38, width: 400, height: 225 });
+        // a square against the right edge
+        let edge = CropRect { x: 300, y: 0, width: 100, height: 300 };
+        let square = fit_crop_to_aspect(edge, Some(1.0), bounds);
+        assert_eq!(square, CropRect { x: 300, y: 100, width: 100, height: 100 });
+        assert!(square.x + square.width <= bounds.0);
+    }
+
+    #[test]
+    fn rectangles_past_the_image_are_clamped() {
+        let bounds = (400, 300);
+        let past = CropRect { x: 350, y: 280, width: 100, height: 50 };
+        assert_eq!(clamp_crop(past, bounds), CropRect { x: 300, y: 250, width: 100, height: 50 });
+        let larger = CropRect { x: 10, y: 10, width: 1000, height: 0 };
+        assert_eq!(clamp_crop(larger, bounds), CropRect { x: 0, y: 10, width: 400, height: 1 });
+    }
+}